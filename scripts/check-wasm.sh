@@ -0,0 +1,6 @@
+#!/bin/sh
+# Checks that the web build still compiles. It can't run yet, see the README.
+set -e
+cd "$(dirname "$0")/.."
+rustup target add wasm32-unknown-unknown
+cargo check --target wasm32-unknown-unknown --profile wasm-release
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 use voxine::{cam_controller::CameraConfig, config::EngineConfig};
 
@@ -14,9 +16,244 @@ pub struct LiveConfig {
 
     pub camera: CameraConfig,
     pub gpu_mesh_upload_time: f64,
+    pub lighting: LightingConfig,
+    pub day_cycle: DayCycleConfig,
+    pub post_processing: Vec<PostEffectConfig>,
+    pub dynamic_resolution: DynamicResolutionConfig,
+    pub display: DisplayConfig,
 }
 impl voxine::config_loader::Live for LiveConfig {}
 
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct LightingConfig {
+    /// The brightness of the sun at noon, the moon is dimmer.
+    pub sun_intensity: f32,
+    pub ambient: f32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct DayCycleConfig {
+    /// 1 = one day per `sky::DAY_LENGTH` seconds, 0 stops the time.
+    pub time_speed: f64,
+    /// The time of day at startup, 0 = midnight, 0.5 = noon.
+    pub start_time: f32,
+    /// Overrides the running time of day, without it the time continues where it was.
+    pub fixed_time: Option<f32>,
+    /// The compass direction of the sunrise in degrees, 0 = +X, 90 = +Z.
+    pub sun_azimuth: f32,
+    /// How far the path of the sun leans away from the zenith in degrees.
+    pub sun_tilt: f32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct DisplayConfig {
+    /// Falls back to `auto-vsync` if the surface doesn't support it.
+    pub present_mode: PresentMode,
+    /// How many frames may be queued for the GPU, fewer lower the input latency.
+    pub frame_latency: u32,
+    /// Without a cap frames are drawn as fast as the present mode allows.
+    pub max_fps: Option<f64>,
+    /// The frame rate while the window is unfocused or the game is paused.
+    pub background_fps: f64,
+}
+
+/// See `wgpu::PresentMode`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentMode {
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(value: PresentMode) -> Self {
+        match value {
+            PresentMode::AutoVsync => Self::AutoVsync,
+            PresentMode::AutoNoVsync => Self::AutoNoVsync,
+            PresentMode::Fifo => Self::Fifo,
+            PresentMode::FifoRelaxed => Self::FifoRelaxed,
+            PresentMode::Immediate => Self::Immediate,
+            PresentMode::Mailbox => Self::Mailbox,
+        }
+    }
+}
+
+/// Scales the resolution the scene is drawn in to hold a frame time, the post-processing scales
+/// it up to the window again.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DynamicResolutionConfig {
+    pub enabled: bool,
+    /// In milliseconds.
+    pub target_frame_time: f64,
+    /// The bounds of the scale of both sides, 1 is the window resolution.
+    pub min_scale: f32,
+    pub max_scale: f32,
+}
+
+/// The upper limit of the length of the post-processing stack.
+pub const MAX_POST_EFFECTS: usize = 8;
+/// The upper limit of `PostEffect::Ssao::samples`.
+pub const MAX_SSAO_SAMPLES: u32 = 64;
+
+/// One stage of the post-processing stack, the stages run in the order they are listed.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PostEffectConfig {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub effect: PostEffect,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "effect", rename_all = "kebab-case")]
+pub enum PostEffect {
+    Vignette {
+        /// How dark the corners get, 0 disables it.
+        strength: f32,
+        /// The distance from the center at which the darkening starts.
+        radius: f32,
+    },
+    /// Distances are fractions of the render distance.
+    Fog {
+        start: f32,
+        end: f32,
+        density: f32,
+        max: f32,
+    },
+    /// Maps the HDR scene into the 0..1 range of the screen.
+    Tonemap {
+        exposure: f32,
+        operator: Tonemapper,
+        /// Scales the exposure so the average brightness of the scene ends up at middle gray,
+        /// adapting over time like an eye.
+        auto_exposure: bool,
+    },
+    /// Lets everything brighter than `threshold` glow. Has to come before the tone mapping,
+    /// only one bloom effect can be enabled.
+    Bloom {
+        /// The brightness above which pixels glow, 1 is white.
+        threshold: f32,
+        /// How much of the glow is added to the scene.
+        intensity: f32,
+    },
+    /// Screen space ambient occlusion, darkens creases and the ground next to walls.
+    /// Only one ssao effect can be enabled.
+    Ssao {
+        /// The radius of the sampled hemisphere as a fraction of the distance to the camera,
+        /// so it covers the same part of the screen near and far.
+        radius: f32,
+        /// The exponent applied to the occlusion, 0 disables it.
+        intensity: f32,
+        /// The samples per pixel, up to `MAX_SSAO_SAMPLES`.
+        samples: u32,
+    },
+    ColorGrading {
+        contrast: f32,
+        saturation: f32,
+        brightness: f32,
+        tint: [f32; 3],
+    },
+}
+
+/// Has to match the `TONEMAP_*` constants in effects.wgsl.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tonemapper {
+    /// `1 - exp(-color)`, washes out saturated colors.
+    Exponential = 0,
+    /// The filmic curve of the Academy Color Encoding System, in the fit by Krzysztof Narkowicz.
+    Aces = 1,
+    /// Desaturates bright colors towards white instead of clipping them.
+    Agx = 2,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AntiAliasing {
+    None,
+    /// A post-processing pass before the configured ones.
+    Fxaa,
+    Msaa2,
+    Msaa4,
+    /// Jitters the projection and blends every frame with the earlier ones.
+    Taa,
+}
+
+impl AntiAliasing {
+    /// The samples per pixel of the main pass.
+    pub fn sample_count(self) -> u32 {
+        match self {
+            Self::Msaa2 => 2,
+            Self::Msaa4 => 4,
+            Self::None | Self::Fxaa | Self::Taa => 1,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl Backend {
+    /// The same names as in the config file.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "vulkan" => Some(Self::Vulkan),
+            "metal" => Some(Self::Metal),
+            "dx12" => Some(Self::Dx12),
+            "gl" => Some(Self::Gl),
+            _ => None,
+        }
+    }
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(value: Backend) -> Self {
+        match value {
+            Backend::Vulkan => Self::VULKAN,
+            Backend::Metal => Self::METAL,
+            Backend::Dx12 => Self::DX12,
+            Backend::Gl => Self::GL,
+        }
+    }
+}
+
+/// The upper limit of `ShadowConfig::cascades`, has to match the shader.
+pub const MAX_SHADOW_CASCADES: u32 = 4;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ShadowConfig {
+    /// 0 disables shadows.
+    pub cascades: u32,
+    pub map_size: u32,
+    /// The distance up to which shadows are drawn.
+    pub distance: f32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct TextureConfig {
+    pub mipmaps: bool,
+    /// Added to the mip level the GPU selects, negative values make distant textures sharper.
+    pub mip_bias: f32,
+    /// Maximum anisotropy, 1 disables anisotropic filtering.
+    pub anisotropy: u16,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct ConfigFile {
@@ -32,10 +269,26 @@ pub struct ConfigFile {
 
     pub camera: CameraConfig,
     pub gpu_mesh_upload_time: f64,
+    pub lighting: LightingConfig,
+    pub day_cycle: DayCycleConfig,
+    pub post_processing: Vec<PostEffectConfig>,
+    pub dynamic_resolution: DynamicResolutionConfig,
+    pub display: DisplayConfig,
 
     pub starting_pos: [f32; 3],
     pub fov: f32,
     pub near_plane: f32,
+    pub resource_pack: Option<PathBuf>,
+    /// Shader files in this directory override the embedded ones and are hot reloaded.
+    pub shader_dir: Option<PathBuf>,
+    pub textures: TextureConfig,
+    pub ambient_occlusion: bool,
+    pub shadows: ShadowConfig,
+    pub anti_aliasing: AntiAliasing,
+    /// Overridden by the `WGPU_BACKEND` environment variable.
+    pub backend: Option<Backend>,
+    /// A part of the name of the adapter to use, overridden by `WGPU_ADAPTER_NAME`.
+    pub adapter: Option<String>,
 
     pub worker_count: usize,
 
@@ -62,10 +315,26 @@ pub struct Config {
 
     pub camera: CameraConfig,
     pub gpu_mesh_upload_time: f64,
+    pub lighting: LightingConfig,
+    pub day_cycle: DayCycleConfig,
+    pub post_processing: Vec<PostEffectConfig>,
+    pub dynamic_resolution: DynamicResolutionConfig,
+    pub display: DisplayConfig,
 
     pub starting_pos: [f32; 3],
     pub fov: f32,
     pub near_plane: f32,
+    pub resource_pack: Option<PathBuf>,
+    /// Shader files in this directory override the embedded ones and are hot reloaded.
+    pub shader_dir: Option<PathBuf>,
+    pub textures: TextureConfig,
+    pub ambient_occlusion: bool,
+    pub shadows: ShadowConfig,
+    pub anti_aliasing: AntiAliasing,
+    /// Overridden by the `WGPU_BACKEND` environment variable.
+    pub backend: Option<Backend>,
+    /// A part of the name of the adapter to use, overridden by `WGPU_ADAPTER_NAME`.
+    pub adapter: Option<String>,
 
     pub worker_count: usize,
 
@@ -83,6 +352,14 @@ pub struct Error {
     msg: String,
 }
 
+/// How many of the enabled effects are matched by `kind`.
+fn enabled_count(effects: &[PostEffectConfig], kind: impl Fn(&PostEffect) -> bool) -> usize {
+    effects
+        .iter()
+        .filter(|effect| effect.enabled && kind(&effect.effect))
+        .count()
+}
+
 impl From<Error> for voxine::error::ConfigError {
     fn from(value: Error) -> Self {
         voxine::error::ConfigError::LogicError { msg: value.msg }
@@ -102,9 +379,22 @@ impl voxine::config_loader::ConfigFile<LiveConfig, Config, Error> for ConfigFile
             target_tps,
             camera,
             gpu_mesh_upload_time,
+            lighting,
+            day_cycle,
+            post_processing,
+            dynamic_resolution,
+            display,
             starting_pos,
             fov,
             near_plane,
+            resource_pack,
+            shader_dir,
+            textures,
+            ambient_occlusion,
+            shadows,
+            anti_aliasing,
+            backend,
+            adapter,
             worker_count,
             task_queue_cap,
             engine_worker_config_queue_cap,
@@ -141,9 +431,92 @@ impl voxine::config_loader::ConfigFile<LiveConfig, Config, Error> for ConfigFile
 
             camera,
             gpu_mesh_upload_time,
+            lighting,
+            day_cycle,
+            post_processing: if post_processing.len() > MAX_POST_EFFECTS {
+                return Err(Error {
+                    msg: format!(
+                        "post-processing can have at most {} effects",
+                        MAX_POST_EFFECTS
+                    ),
+                });
+            } else if enabled_count(&post_processing, |effect| {
+                matches!(effect, PostEffect::Bloom { .. })
+            }) > 1
+            {
+                return Err(Error {
+                    msg: "only one bloom effect can be enabled".to_string(),
+                });
+            } else if enabled_count(&post_processing, |effect| {
+                matches!(effect, PostEffect::Ssao { .. })
+            }) > 1
+            {
+                return Err(Error {
+                    msg: "only one ssao effect can be enabled".to_string(),
+                });
+            } else if post_processing.iter().any(|effect| match effect.effect {
+                PostEffect::Ssao { samples, .. } => !(1..=MAX_SSAO_SAMPLES).contains(&samples),
+                _ => false,
+            }) {
+                return Err(Error {
+                    msg: format!("ssao samples have to be in 1..={}", MAX_SSAO_SAMPLES),
+                });
+            } else {
+                post_processing
+            },
+            dynamic_resolution: if dynamic_resolution.target_frame_time <= 0. {
+                return Err(Error {
+                    msg: "dynamic-resolution.target-frame-time has to be positive".to_string(),
+                });
+            } else if dynamic_resolution.min_scale <= 0.
+                || dynamic_resolution.min_scale > dynamic_resolution.max_scale
+                || dynamic_resolution.max_scale > 1.
+            {
+                return Err(Error {
+                    msg: "dynamic-resolution needs 0 < min-scale <= max-scale <= 1".to_string(),
+                });
+            } else {
+                dynamic_resolution
+            },
+            display: if display.frame_latency == 0 {
+                return Err(Error {
+                    msg: "display.frame-latency has to be at least 1".to_string(),
+                });
+            } else if display.max_fps.is_some_and(|fps| fps <= 0.) || display.background_fps <= 0. {
+                return Err(Error {
+                    msg: "display.max-fps and display.background-fps have to be positive"
+                        .to_string(),
+                });
+            } else {
+                display
+            },
             starting_pos,
             fov,
             near_plane,
+            resource_pack,
+            shader_dir,
+            textures: if (1..=16).contains(&textures.anisotropy) {
+                textures
+            } else {
+                return Err(Error {
+                    msg: "textures.anisotropy has to be between 1 and 16".to_string(),
+                });
+            },
+            ambient_occlusion,
+            shadows: if shadows.cascades > MAX_SHADOW_CASCADES {
+                return Err(Error {
+                    msg: format!("shadows.cascades can be at most {}", MAX_SHADOW_CASCADES),
+                });
+            } else if shadows.map_size == 0 {
+                return Err(Error {
+                    msg: "shadows.map-size has to be positive".to_string(),
+                });
+            } else {
+                shadows
+            },
+            anti_aliasing,
+            backend,
+            adapter,
 
             worker_count: worker_count.min(num_cpus::get()),
 
@@ -165,6 +538,14 @@ impl voxine::config_loader::Config<LiveConfig> for Config {
             starting_pos: _,
             fov: _,
             near_plane: _,
+            resource_pack: _,
+            shader_dir: _,
+            textures: _,
+            ambient_occlusion: _,
+            shadows: _,
+            anti_aliasing: _,
+            backend: _,
+            adapter: _,
             worker_count: _,
             task_queue_cap: _,
             engine_worker_config_queue_cap: _,
@@ -184,6 +565,11 @@ impl voxine::config_loader::Config<LiveConfig> for Config {
             target_tps,
             camera,
             gpu_mesh_upload_time,
+            lighting,
+            day_cycle,
+            post_processing,
+            dynamic_resolution,
+            display,
         } = self;
 
         LiveConfig {
@@ -198,6 +584,11 @@ impl voxine::config_loader::Config<LiveConfig> for Config {
 
             camera,
             gpu_mesh_upload_time,
+            lighting,
+            day_cycle,
+            post_processing,
+            dynamic_resolution,
+            display,
         }
     }
 
@@ -207,6 +598,20 @@ impl voxine::config_loader::Config<LiveConfig> for Config {
 }
 
 impl Config {
+    /// The config.toml the binary was built with, for the web where there is no file to load or
+    /// watch. Nothing is ever sent through the returned consumer.
+    #[cfg(target_arch = "wasm32")]
+    pub fn embedded() -> (Self, rtrb::Consumer<LiveConfig>) {
+        let file: ConfigFile = toml::from_str(include_str!("../config.toml")).expect("config");
+        let mut config = voxine::config_loader::ConfigFile::check(file)
+            .unwrap_or_else(|err| panic!("config: {}", err.msg));
+        // these point into the file system
+        config.resource_pack = None;
+        config.shader_dir = None;
+        let (_, updates) = rtrb::RingBuffer::new(1);
+        (config, updates)
+    }
+
     pub fn update(&mut self, update: LiveConfig) {
         let LiveConfig {
             full_detail_distance,
@@ -218,6 +623,11 @@ impl Config {
             target_tps,
             camera,
             gpu_mesh_upload_time,
+            lighting,
+            day_cycle,
+            post_processing,
+            dynamic_resolution,
+            display,
         } = update;
 
         self.full_detail_distance = full_detail_distance;
@@ -229,6 +639,11 @@ impl Config {
         self.target_tps = target_tps;
         self.camera = camera;
         self.gpu_mesh_upload_time = gpu_mesh_upload_time;
+        self.lighting = lighting;
+        self.day_cycle = day_cycle;
+        self.post_processing = post_processing;
+        self.dynamic_resolution = dynamic_resolution;
+        self.display = display;
     }
 
     pub fn engine_config(self) -> EngineConfig {
@@ -238,9 +653,22 @@ impl Config {
             full_detail_distance: _,
             camera: _,
             gpu_mesh_upload_time: _,
+            lighting: _,
+            day_cycle: _,
+            post_processing: _,
+            dynamic_resolution: _,
+            display: _,
             starting_pos: _,
             fov: _,
             near_plane: _,
+            resource_pack: _,
+            shader_dir: _,
+            textures: _,
+            ambient_occlusion: _,
+            shadows: _,
+            anti_aliasing: _,
+            backend: _,
+            adapter: _,
 
             full_detail_generation_distance,
             task_cancelation_lod_threshold,
@@ -286,6 +714,11 @@ impl LiveConfig {
             full_detail_distance: _,
             camera: _,
             gpu_mesh_upload_time: _,
+            lighting: _,
+            day_cycle: _,
+            post_processing: _,
+            dynamic_resolution: _,
+            display: _,
 
             full_detail_generation_distance,
             task_cancelation_lod_threshold,
@@ -0,0 +1,540 @@
+//! An egui window for tuning the live config while the game runs, opened with F1. It edits a copy
+//! of the live config, which is applied like a change of the config file. Winit events are
+//! translated by hand, egui-winit doesn't support this version of winit.
+
+use std::{mem::Discriminant, time::Duration};
+
+use serde::{Serialize, de::DeserializeOwned};
+use web_time::Instant;
+use winit::{
+    event::{DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{Key, NamedKey},
+    window::WindowId,
+};
+
+use crate::{
+    config::{Config, LiveConfig, MAX_SSAO_SAMPLES, PostEffect, PresentMode, Tonemapper},
+    gpu::{UiFrame, window::Window},
+    input::Inputs,
+};
+use voxine::config_loader::Config as _;
+
+/// Every edit goes to the engine thread as well, dragging a slider would flood its queue.
+const APPLY_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct DebugUi {
+    context: egui::Context,
+    open: bool,
+    /// Collected between two frames, with positions in physical pixels until `run` scales them.
+    events: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    /// In physical pixels.
+    pointer_pos: egui::Pos2,
+    /// The right mouse button was pressed outside of the UI and is held, the camera follows the
+    /// mouse meanwhile.
+    looking: bool,
+    /// An edit which waits for `APPLY_INTERVAL` to pass.
+    pending: Option<LiveConfig>,
+    last_applied: Instant,
+    start_time: Instant,
+}
+
+impl DebugUi {
+    pub fn new() -> Self {
+        Self {
+            context: egui::Context::default(),
+            open: false,
+            events: Vec::new(),
+            modifiers: egui::Modifiers::default(),
+            pointer_pos: egui::Pos2::ZERO,
+            looking: false,
+            pending: None,
+            last_applied: Instant::now(),
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.looking = false;
+    }
+
+    /// Takes the events the UI uses while it is open, so they don't reach the `InputEventFilter`.
+    /// The mouse only turns the camera while the right button is held outside of the UI.
+    pub fn could_handle(&mut self, event: &Event<()>, own_window_id: WindowId) -> bool {
+        if !self.open {
+            return false;
+        }
+        match event {
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { .. },
+                ..
+            } => !self.looking,
+            Event::WindowEvent { window_id, event } if own_window_id == *window_id => match event {
+                WindowEvent::ModifiersChanged(modifiers) => {
+                    let state = modifiers.state();
+                    self.modifiers = egui::Modifiers {
+                        alt: state.alt_key(),
+                        ctrl: state.control_key(),
+                        shift: state.shift_key(),
+                        mac_cmd: cfg!(target_os = "macos") && state.super_key(),
+                        command: if cfg!(target_os = "macos") {
+                            state.super_key()
+                        } else {
+                            state.control_key()
+                        },
+                    };
+                    false
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.pointer_pos = egui::pos2(position.x as f32, position.y as f32);
+                    self.events
+                        .push(egui::Event::PointerMoved(self.pointer_pos));
+                    true
+                }
+                WindowEvent::CursorLeft { .. } => {
+                    self.events.push(egui::Event::PointerGone);
+                    true
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    let pressed = *state == ElementState::Pressed;
+                    let over_ui = self.context.is_pointer_over_area();
+                    if *button == MouseButton::Right {
+                        self.looking = pressed && !over_ui;
+                    }
+                    let button = match button {
+                        MouseButton::Left => egui::PointerButton::Primary,
+                        MouseButton::Right => egui::PointerButton::Secondary,
+                        MouseButton::Middle => egui::PointerButton::Middle,
+                        MouseButton::Back => egui::PointerButton::Extra1,
+                        MouseButton::Forward => egui::PointerButton::Extra2,
+                        MouseButton::Other(_) => return false,
+                    };
+                    self.events.push(egui::Event::PointerButton {
+                        pos: self.pointer_pos,
+                        button,
+                        pressed,
+                        modifiers: self.modifiers,
+                    });
+                    over_ui || self.context.wants_pointer_input()
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let (unit, delta) = match delta {
+                        MouseScrollDelta::LineDelta(x, y) => {
+                            (egui::MouseWheelUnit::Line, egui::vec2(*x, *y))
+                        }
+                        MouseScrollDelta::PixelDelta(delta) => (
+                            egui::MouseWheelUnit::Point,
+                            egui::vec2(delta.x as f32, delta.y as f32),
+                        ),
+                    };
+                    self.events.push(egui::Event::MouseWheel {
+                        unit,
+                        delta,
+                        modifiers: self.modifiers,
+                    });
+                    // otherwise it changes the speed of the camera
+                    self.context.is_pointer_over_area()
+                }
+                WindowEvent::KeyboardInput { event, .. } => {
+                    let pressed = event.state.is_pressed();
+                    if let Some(key) = key(&event.logical_key) {
+                        self.events.push(egui::Event::Key {
+                            key,
+                            physical_key: None,
+                            pressed,
+                            repeat: event.repeat,
+                            modifiers: self.modifiers,
+                        });
+                    }
+                    if let Some(text) = &event.text
+                        && pressed
+                        && !text.chars().any(char::is_control)
+                    {
+                        self.events.push(egui::Event::Text(text.to_string()));
+                    }
+                    // the movement keys still work while nothing is typed into
+                    self.context.wants_keyboard_input()
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Runs the widgets. Buttons set the same inputs as their keys, changes of the config are
+    /// returned as a whole new live config, at most once per `APPLY_INTERVAL`.
+    pub fn run(
+        &mut self,
+        window: &Window,
+        config: &Config,
+        change_mesh: bool,
+        inputs: &mut Inputs,
+    ) -> (UiFrame, Option<LiveConfig>) {
+        let pixels_per_point = window.scale_factor() as f32;
+        let size = window.size();
+        let mut events = std::mem::take(&mut self.events);
+        // egui works in logical pixels
+        for event in &mut events {
+            match event {
+                egui::Event::PointerMoved(pos) | egui::Event::PointerButton { pos, .. } => {
+                    *pos = (pos.to_vec2() / pixels_per_point).to_pos2()
+                }
+                egui::Event::MouseWheel {
+                    unit: egui::MouseWheelUnit::Point,
+                    delta,
+                    ..
+                } => *delta /= pixels_per_point,
+                _ => {}
+            }
+        }
+        let mut raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(size.width as f32, size.height as f32) / pixels_per_point,
+            )),
+            time: Some(self.start_time.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events,
+            focused: true,
+            ..Default::default()
+        };
+        raw_input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(pixels_per_point);
+
+        let mut changed = self.pending.is_some();
+        let mut live = self.pending.take().unwrap_or_else(|| config.clone().live());
+
+        // a single pass, the widgets change the config and may not run twice
+        self.context.begin_pass(raw_input);
+        egui::Window::new("Debug")
+            .default_pos([16., 16.])
+            .show(&self.context, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.collapsing("Debug", |ui| debug_toggles(ui, change_mesh, inputs));
+                    ui.collapsing("World", |ui| changed |= world(ui, &mut live));
+                    ui.collapsing("Camera", |ui| {
+                        changed |= edit_serialized(ui, &mut live.camera)
+                    });
+                    ui.collapsing("Lighting", |ui| changed |= lighting(ui, &mut live));
+                    ui.collapsing("Post-processing", |ui| {
+                        changed |= post_processing(ui, &mut live)
+                    });
+                    ui.collapsing("Display", |ui| changed |= display(ui, &mut live));
+                });
+            });
+        let output = self.context.end_pass();
+
+        let frame = UiFrame {
+            primitives: self
+                .context
+                .tessellate(output.shapes, output.pixels_per_point),
+            textures_delta: output.textures_delta,
+            pixels_per_point: output.pixels_per_point,
+        };
+        if !changed {
+            return (frame, None);
+        }
+        if self.last_applied.elapsed() < APPLY_INTERVAL {
+            self.pending = Some(live);
+            return (frame, None);
+        }
+        self.last_applied = Instant::now();
+        (frame, Some(live))
+    }
+}
+
+/// The keys the widgets use for editing text and moving the focus.
+fn key(key: &Key) -> Option<egui::Key> {
+    Some(match key {
+        Key::Named(NamedKey::ArrowDown) => egui::Key::ArrowDown,
+        Key::Named(NamedKey::ArrowLeft) => egui::Key::ArrowLeft,
+        Key::Named(NamedKey::ArrowRight) => egui::Key::ArrowRight,
+        Key::Named(NamedKey::ArrowUp) => egui::Key::ArrowUp,
+        Key::Named(NamedKey::Backspace) => egui::Key::Backspace,
+        Key::Named(NamedKey::Delete) => egui::Key::Delete,
+        Key::Named(NamedKey::End) => egui::Key::End,
+        Key::Named(NamedKey::Enter) => egui::Key::Enter,
+        Key::Named(NamedKey::Escape) => egui::Key::Escape,
+        Key::Named(NamedKey::Home) => egui::Key::Home,
+        Key::Named(NamedKey::Tab) => egui::Key::Tab,
+        // for the shortcuts, like copying
+        Key::Character(c) => egui::Key::from_name(c)?,
+        _ => return None,
+    })
+}
+
+fn debug_toggles(ui: &mut egui::Ui, change_mesh: bool, inputs: &mut Inputs) {
+    let mut change_mesh = change_mesh;
+    inputs.remesh |= ui
+        .checkbox(&mut change_mesh, "upload new meshes (R)")
+        .changed();
+    inputs.toggle_impl |= ui.button("toggle indirect draws (T)").clicked();
+    inputs.toggle_ambient_occlusion |= ui.button("toggle ambient occlusion (O)").clicked();
+    inputs.cycle_debug_view |= ui.button("next debug view (V)").clicked();
+    inputs.toggle_chunk_bounds |= ui.button("toggle chunk bounds (G)").clicked();
+    inputs.detach_frustum |= ui.button("detach the frustum (F)").clicked();
+    inputs.toggle_hud |= ui.button("toggle the debug screen (F3)").clicked();
+}
+
+fn world(ui: &mut egui::Ui, live: &mut LiveConfig) -> bool {
+    [
+        ui.add(egui::Slider::new(&mut live.render_distance, 32.0..=2000.).text("render distance")),
+        ui.add(
+            egui::Slider::new(&mut live.full_detail_distance, 0.0..=1000.)
+                .text("full detail distance"),
+        ),
+        ui.add(
+            egui::Slider::new(&mut live.full_detail_generation_distance, 0.0..=1000.)
+                .text("full detail generation distance"),
+        ),
+        ui.add(egui::Slider::new(&mut live.max_chunks, 1..=200_000).text("max chunks")),
+        ui.add(egui::Slider::new(&mut live.target_tps, 1.0..=200.).text("target tps")),
+        ui.add(
+            egui::Slider::new(&mut live.gpu_mesh_upload_time, 0.0..=0.02)
+                .text("mesh upload time per frame (s)"),
+        ),
+    ]
+    .iter()
+    .any(egui::Response::changed)
+}
+
+fn lighting(ui: &mut egui::Ui, live: &mut LiveConfig) -> bool {
+    let mut changed = [
+        ui.add(
+            egui::Slider::new(&mut live.lighting.sun_intensity, 0.0..=10.).text("sun intensity"),
+        ),
+        ui.add(egui::Slider::new(&mut live.lighting.ambient, 0.0..=1.).text("ambient")),
+        ui.add(egui::Slider::new(&mut live.day_cycle.time_speed, 0.0..=100.).text("time speed")),
+        ui.add(
+            egui::Slider::new(&mut live.day_cycle.sun_azimuth, 0.0..=360.).text("sun azimuth (°)"),
+        ),
+        ui.add(egui::Slider::new(&mut live.day_cycle.sun_tilt, -80.0..=80.).text("sun tilt (°)")),
+    ]
+    .iter()
+    .any(egui::Response::changed);
+
+    let mut fixed = live.day_cycle.fixed_time.is_some();
+    if ui.checkbox(&mut fixed, "fixed time of day").changed() {
+        live.day_cycle.fixed_time = fixed.then_some(live.day_cycle.start_time);
+        changed = true;
+    }
+    if let Some(time) = &mut live.day_cycle.fixed_time {
+        changed |= ui
+            .add(egui::Slider::new(time, 0.0..=1.).text("time of day"))
+            .changed();
+    }
+    changed
+}
+
+/// Bloom and ssao can only be enabled once.
+fn exclusive(effect: &PostEffect) -> Option<Discriminant<PostEffect>> {
+    matches!(effect, PostEffect::Bloom { .. } | PostEffect::Ssao { .. })
+        .then(|| std::mem::discriminant(effect))
+}
+
+fn post_processing(ui: &mut egui::Ui, live: &mut LiveConfig) -> bool {
+    let enabled_exclusive: Vec<_> = live
+        .post_processing
+        .iter()
+        .filter(|effect| effect.enabled)
+        .filter_map(|effect| exclusive(&effect.effect))
+        .collect();
+    let mut changed = false;
+
+    for (index, effect) in live.post_processing.iter_mut().enumerate() {
+        ui.push_id(index, |ui| {
+            let name = match effect.effect {
+                PostEffect::Vignette { .. } => "vignette",
+                PostEffect::Fog { .. } => "fog",
+                PostEffect::Tonemap { .. } => "tone mapping",
+                PostEffect::Bloom { .. } => "bloom",
+                PostEffect::Ssao { .. } => "ssao",
+                PostEffect::ColorGrading { .. } => "color grading",
+            };
+            let can_enable = effect.enabled
+                || exclusive(&effect.effect).is_none_or(|kind| !enabled_exclusive.contains(&kind));
+            changed |= ui
+                .add_enabled(can_enable, egui::Checkbox::new(&mut effect.enabled, name))
+                .changed();
+            if !effect.enabled {
+                return;
+            }
+            ui.indent(index, |ui| {
+                changed |= match &mut effect.effect {
+                    PostEffect::Vignette { strength, radius } => [
+                        ui.add(egui::Slider::new(strength, 0.0..=1.).text("strength")),
+                        ui.add(egui::Slider::new(radius, 0.0..=1.5).text("radius")),
+                    ]
+                    .iter()
+                    .any(egui::Response::changed),
+                    PostEffect::Fog {
+                        start,
+                        end,
+                        density,
+                        max,
+                    } => [
+                        ui.add(egui::Slider::new(start, 0.0..=1.).text("start")),
+                        ui.add(egui::Slider::new(end, 0.0..=1.).text("end")),
+                        ui.add(egui::Slider::new(density, 0.0..=10.).text("density")),
+                        ui.add(egui::Slider::new(max, 0.0..=1.).text("max")),
+                    ]
+                    .iter()
+                    .any(egui::Response::changed),
+                    PostEffect::Tonemap {
+                        exposure,
+                        operator,
+                        auto_exposure,
+                    } => {
+                        let before = *operator;
+                        egui::ComboBox::from_label("operator")
+                            .selected_text(format!("{:?}", operator))
+                            .show_ui(ui, |ui| {
+                                for option in
+                                    [Tonemapper::Exponential, Tonemapper::Aces, Tonemapper::Agx]
+                                {
+                                    ui.selectable_value(operator, option, format!("{:?}", option));
+                                }
+                            });
+                        [
+                            ui.add(egui::Slider::new(exposure, 0.0..=10.).text("exposure")),
+                            ui.checkbox(auto_exposure, "auto exposure"),
+                        ]
+                        .iter()
+                        .any(egui::Response::changed)
+                            || *operator != before
+                    }
+                    PostEffect::Bloom {
+                        threshold,
+                        intensity,
+                    } => [
+                        ui.add(egui::Slider::new(threshold, 0.0..=4.).text("threshold")),
+                        ui.add(egui::Slider::new(intensity, 0.0..=2.).text("intensity")),
+                    ]
+                    .iter()
+                    .any(egui::Response::changed),
+                    PostEffect::Ssao {
+                        radius,
+                        intensity,
+                        samples,
+                    } => [
+                        ui.add(egui::Slider::new(radius, 0.0..=0.2).text("radius")),
+                        ui.add(egui::Slider::new(intensity, 0.0..=4.).text("intensity")),
+                        ui.add(egui::Slider::new(samples, 1..=MAX_SSAO_SAMPLES).text("samples")),
+                    ]
+                    .iter()
+                    .any(egui::Response::changed),
+                    PostEffect::ColorGrading {
+                        contrast,
+                        saturation,
+                        brightness,
+                        tint,
+                    } => [
+                        ui.add(egui::Slider::new(contrast, 0.0..=2.).text("contrast")),
+                        ui.add(egui::Slider::new(saturation, 0.0..=2.).text("saturation")),
+                        ui.add(egui::Slider::new(brightness, -1.0..=1.).text("brightness")),
+                        ui.horizontal(|ui| {
+                            let response = ui.color_edit_button_rgb(tint);
+                            ui.label("tint");
+                            response
+                        })
+                        .inner,
+                    ]
+                    .iter()
+                    .any(egui::Response::changed),
+                };
+            });
+        });
+    }
+    changed
+}
+
+fn display(ui: &mut egui::Ui, live: &mut LiveConfig) -> bool {
+    let display = &mut live.display;
+    let before = display.present_mode;
+    egui::ComboBox::from_label("present mode")
+        .selected_text(format!("{:?}", display.present_mode))
+        .show_ui(ui, |ui| {
+            for mode in [
+                PresentMode::AutoVsync,
+                PresentMode::AutoNoVsync,
+                PresentMode::Fifo,
+                PresentMode::FifoRelaxed,
+                PresentMode::Immediate,
+                PresentMode::Mailbox,
+            ] {
+                ui.selectable_value(&mut display.present_mode, mode, format!("{:?}", mode));
+            }
+        });
+    let mut changed = display.present_mode != before;
+    changed |= ui
+        .add(egui::Slider::new(&mut display.frame_latency, 1..=3).text("frame latency"))
+        .changed();
+
+    let mut capped = display.max_fps.is_some();
+    if ui.checkbox(&mut capped, "cap the frame rate").changed() {
+        display.max_fps = capped.then_some(60.);
+        changed = true;
+    }
+    if let Some(fps) = &mut display.max_fps {
+        changed |= ui
+            .add(egui::Slider::new(fps, 10.0..=500.).text("max fps"))
+            .changed();
+    }
+
+    let resolution = &mut live.dynamic_resolution;
+    changed |= ui
+        .checkbox(&mut resolution.enabled, "dynamic resolution")
+        .changed();
+    if resolution.enabled {
+        let scale_changed = [
+            ui.add(
+                egui::Slider::new(&mut resolution.target_frame_time, 1.0..=50.)
+                    .text("target frame time (ms)"),
+            ),
+            ui.add(egui::Slider::new(&mut resolution.min_scale, 0.1..=1.).text("min scale")),
+            ui.add(egui::Slider::new(&mut resolution.max_scale, 0.1..=1.).text("max scale")),
+        ]
+        .iter()
+        .any(egui::Response::changed);
+        // the config has to keep min-scale <= max-scale
+        resolution.max_scale = resolution.max_scale.max(resolution.min_scale);
+        changed |= scale_changed;
+    }
+    changed
+}
+
+/// Edits the numbers and switches of a config whose fields aren't known here, through its
+/// serialized form.
+fn edit_serialized<T: Serialize + DeserializeOwned>(ui: &mut egui::Ui, value: &mut T) -> bool {
+    let Ok(toml::Value::Table(mut table)) = toml::Value::try_from(&*value) else {
+        return false;
+    };
+    let mut changed = false;
+    for (name, field) in table.iter_mut() {
+        let name = name.replace('-', " ");
+        ui.horizontal(|ui| {
+            changed |= match field {
+                toml::Value::Float(float) => {
+                    ui.add(egui::DragValue::new(float).speed(0.01)).changed()
+                }
+                toml::Value::Integer(integer) => ui.add(egui::DragValue::new(integer)).changed(),
+                toml::Value::Boolean(boolean) => ui.checkbox(boolean, "").changed(),
+                _ => return,
+            };
+            ui.label(name);
+        });
+    }
+    changed
+        && toml::Value::Table(table)
+            .try_into()
+            .map(|edited| *value = edited)
+            .is_ok()
+}
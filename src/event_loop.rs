@@ -1,14 +1,20 @@
+use std::time::Duration;
+
+use web_time::Instant;
 use winit::{
     dpi::PhysicalSize,
-    event::{Event, WindowEvent},
+    event::{Event, StartCause, WindowEvent},
     event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
     window::{WindowBuilder, WindowId},
 };
 
 use crate::gpu::window::Window;
 
+pub const TITLE: &str = "Voxeled";
+
 pub trait EventHandler<'a> {
-    fn new(window: &'a winit::window::Window) -> Self;
+    /// Async because the web can't block on the GPU connection.
+    async fn new(window: &'a winit::window::Window) -> Self;
 
     fn could_handle(
         &mut self,
@@ -19,65 +25,104 @@ pub trait EventHandler<'a> {
 
     fn generate_frame(&mut self, window: &mut Window<'a>, control_flow: &EventLoopWindowTarget<()>);
 
+    /// The least time between two frames, None draws the next one right away.
+    fn frame_interval(&self) -> Option<Duration>;
+
     fn reconfigure(&mut self);
     fn set_window_focus(&mut self, focused: bool);
     fn resize_window(&mut self, new_size: PhysicalSize<u32>);
 }
 
-pub fn make_window<E: EventHandler<'static>>() {
+pub fn make_window<E: EventHandler<'static> + 'static>() {
     let event_loop = EventLoop::new().unwrap();
-    let window = Box::new(
-        WindowBuilder::new()
-            .with_title("Voxeled")
-            .with_inner_size(PhysicalSize::<u32> {
-                width: 2000,
-                height: 2000,
-            }) // this is the window configuration
-            .build(&event_loop)
-            .unwrap(),
-    );
+    let window_builder = WindowBuilder::new()
+        .with_title(TITLE)
+        .with_inner_size(PhysicalSize::<u32> {
+            width: 2000,
+            height: 2000,
+        }); // this is the window configuration
+    // on the web the window is a canvas, which has to be put onto the page
+    #[cfg(target_arch = "wasm32")]
+    let window_builder = {
+        use winit::platform::web::WindowBuilderExtWebSys;
+        window_builder.with_append(true)
+    };
+    let window = Box::new(window_builder.build(&event_loop).unwrap());
     let window: &'static winit::window::Window = Box::leak(window);
 
-    let mut event_handler = E::new(window);
+    #[cfg(not(target_arch = "wasm32"))]
+    run(event_loop, window, pollster::block_on(E::new(window)));
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async move {
+        let event_handler = E::new(window).await;
+        run(event_loop, window, event_handler);
+    });
+}
 
+fn run<E: EventHandler<'static> + 'static>(
+    event_loop: EventLoop<()>,
+    window: &'static winit::window::Window,
+    mut event_handler: E,
+) {
     let mut window = Window::from(window, true);
 
-    event_loop // main event loop
-        .run(|event, control_flow| {
-            if !event_handler.could_handle(&event, window.id(), window.focused()) {
-                match event {
-                    Event::WindowEvent { event, window_id } if window_id == window.id() => {
-                        match event {
-                            WindowEvent::Occluded(occluded) => {
-                                match occluded {
-                                    true => control_flow.set_control_flow(ControlFlow::Wait),
-                                    false => {
-                                        control_flow.set_control_flow(ControlFlow::Poll);
-                                    }
+    let handle_event = move |event, control_flow: &EventLoopWindowTarget<()>| {
+        if !event_handler.could_handle(&event, window.id(), window.focused()) {
+            match event {
+                Event::WindowEvent { event, window_id } if window_id == window.id() => {
+                    match event {
+                        WindowEvent::Occluded(occluded) => {
+                            match occluded {
+                                true => control_flow.set_control_flow(ControlFlow::Wait),
+                                false => {
+                                    control_flow.set_control_flow(ControlFlow::Poll);
+                                    window.request_redraw();
                                 }
-                                event_handler.reconfigure();
-                            }
-                            WindowEvent::Focused(focused) => {
-                                window.set_focus(focused);
-                                event_handler.set_window_focus(focused)
-                            }
-                            WindowEvent::CloseRequested => control_flow.exit(),
-                            WindowEvent::Resized(physical_size) => {
-                                window.resize(physical_size);
-                                event_handler.resize_window(physical_size)
                             }
+                            event_handler.reconfigure();
+                        }
+                        WindowEvent::Focused(focused) => {
+                            window.set_focus(focused);
+                            event_handler.set_window_focus(focused)
+                        }
+                        WindowEvent::CloseRequested => control_flow.exit(),
+                        WindowEvent::Resized(physical_size) => {
+                            window.resize(physical_size);
+                            event_handler.resize_window(physical_size)
+                        }
 
-                            WindowEvent::RedrawRequested => {
-                                event_handler.generate_frame(&mut window, control_flow);
-                                window.request_redraw();
+                        WindowEvent::RedrawRequested => {
+                            let frame_start = Instant::now();
+                            event_handler.generate_frame(&mut window, control_flow);
+                            match event_handler.frame_interval() {
+                                Some(interval) => control_flow.set_control_flow(
+                                    ControlFlow::WaitUntil(frame_start + interval),
+                                ),
+                                None => window.request_redraw(),
                             }
-                            _ => {}
                         }
+                        _ => {}
                     }
-                    Event::Suspended => control_flow.exit(),
-                    _ => {}
                 }
+                // the wait for the next capped frame is over
+                Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                    control_flow.set_control_flow(ControlFlow::Wait);
+                    window.request_redraw();
+                }
+                Event::Suspended => control_flow.exit(),
+                _ => {}
             }
-        })
+        }
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    event_loop // main event loop
+        .run(handle_event)
         .expect("event loop failed");
+    // the browser runs the loop, this returns right away
+    #[cfg(target_arch = "wasm32")]
+    {
+        use winit::platform::web::EventLoopExtWebSys;
+        event_loop.spawn(handle_event);
+    }
 }
@@ -0,0 +1,103 @@
+//! Chooses the backend and the adapter. Both can be set in the config, the environment variables
+//! `WGPU_BACKEND` and `WGPU_ADAPTER_NAME` override it. `--list-adapters` prints what there is to
+//! choose from.
+use crate::config::Backend;
+
+/// The backends tried if none is chosen.
+#[cfg(not(target_arch = "wasm32"))]
+const DEFAULT_BACKENDS: wgpu::Backends = wgpu::Backends::PRIMARY;
+/// The chunk uniforms make the renderer work without push constants, which WebGPU lacks.
+#[cfg(target_arch = "wasm32")]
+const DEFAULT_BACKENDS: wgpu::Backends = wgpu::Backends::BROWSER_WEBGPU;
+
+/// An instance with the backend of `WGPU_BACKEND` or `backend`.
+pub fn instance(backend: Option<Backend>) -> wgpu::Instance {
+    let from_env = std::env::var("WGPU_BACKEND").ok().and_then(|name| {
+        let backend = Backend::from_name(&name);
+        if backend.is_none() {
+            log::warn!("unknown backend {:?} in WGPU_BACKEND", name);
+        }
+        backend
+    });
+    wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: from_env
+            .or(backend)
+            .map_or(DEFAULT_BACKENDS, wgpu::Backends::from),
+        ..Default::default()
+    })
+}
+
+/// The first adapter whose name contains `WGPU_ADAPTER_NAME` or `name`, ignoring the case.
+/// Without a name, or if none matches, the fastest one which can draw onto `surface`.
+pub async fn select(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface<'_>,
+    name: Option<&str>,
+) -> wgpu::Adapter {
+    let name = std::env::var("WGPU_ADAPTER_NAME")
+        .ok()
+        .or_else(|| name.map(str::to_string));
+    if let Some(name) = name {
+        match by_name(instance, surface, &name) {
+            Some(adapter) => return adapter,
+            None => log::warn!("no adapter matches {:?}, see --list-adapters", name),
+        }
+    }
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: Some(surface),
+            force_fallback_adapter: false,
+        })
+        .await
+        .expect("no adapter can draw onto the window")
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn by_name(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface<'_>,
+    name: &str,
+) -> Option<wgpu::Adapter> {
+    let name = name.to_lowercase();
+    instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .filter(|adapter| adapter.is_surface_supported(surface))
+        .find(|adapter| adapter.get_info().name.to_lowercase().contains(&name))
+}
+
+/// The browser offers a single adapter.
+#[cfg(target_arch = "wasm32")]
+fn by_name(
+    _instance: &wgpu::Instance,
+    _surface: &wgpu::Surface<'_>,
+    _name: &str,
+) -> Option<wgpu::Adapter> {
+    None
+}
+
+/// Prints every adapter of the backend of `WGPU_BACKEND`, or of all default ones.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list() {
+    let adapters = instance(None).enumerate_adapters(wgpu::Backends::all());
+    if adapters.is_empty() {
+        println!("no adapters found");
+    }
+    for adapter in adapters {
+        let info = adapter.get_info();
+        println!(
+            "{} ({:?}, {:?}, driver: {} {}){}",
+            info.name,
+            info.backend,
+            info.device_type,
+            info.driver,
+            info.driver_info,
+            if adapter.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+                ""
+            } else {
+                ", without push constants"
+            },
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Classic per-vertex voxel ambient occlusion.
+//!
+//! The meshes from voxine only contain one `u32` per face, so the occlusion is derived from the
+//! faces themselves: a solid voxel next to an air cell always has a face pointing into that cell.
+//! That is enough to know the two side and the corner voxel of every face corner,
+//! except at the chunk border where neighbours are treated as air.
+
+/// The voxine instance stride.
+const MESH_INSTANCE_SIZE: usize = 4;
+const CHUNK_SIZE: i32 = 32;
+
+/// A face as it is uploaded to the GPU, the voxine instance plus 2 bits of occlusion per corner.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+pub struct FaceInstance {
+    kind: u32,
+    ambient_occlusion: u32,
+}
+
+impl FaceInstance {
+    pub const SIZE: u64 = std::mem::size_of::<Self>() as u64;
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![1 => Uint32, 2 => Uint32];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: Self::SIZE,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// Same tables as in projection.wgsl, used to find the cube corner of each quad corner.
+const QUAD_UVS: [[i32; 2]; 4] = [[1, 1], [0, 1], [0, 0], [1, 0]];
+const FACE_ORIGINS: [[i32; 3]; 6] = [
+    [0, 1, 0],
+    [1, 1, 1],
+    [1, 0, 0],
+    [1, 1, 1],
+    [1, 1, 0],
+    [0, 1, 1],
+];
+const FACE_U_AXES: [[i32; 3]; 6] = [
+    [0, 0, 1],
+    [0, 0, -1],
+    [-1, 0, 0],
+    [-1, 0, 0],
+    [-1, 0, 0],
+    [1, 0, 0],
+];
+const FACE_V_AXES: [[i32; 3]; 6] = [
+    [0, -1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+    [0, -1, 0],
+    [0, -1, 0],
+];
+
+/// Orientations are `axis * 2 + positive`: -X, +X, -Y, +Y, -Z, +Z.
+fn orientation_towards(axis: usize, sign: i32) -> usize {
+    axis * 2 + (sign > 0) as usize
+}
+
+fn decode_pos(kind: u32) -> [i32; 3] {
+    [
+        ((kind >> 27) & 31) as i32,
+        ((kind >> 22) & 31) as i32,
+        ((kind >> 17) & 31) as i32,
+    ]
+}
+
+/// One bit per orientation and voxel, set if the voxel has a face with that orientation.
+struct FaceSet {
+    bits: Vec<u64>,
+}
+
+impl FaceSet {
+    fn index(orientation: usize, pos: [i32; 3]) -> Option<usize> {
+        if pos.iter().any(|c| !(0..CHUNK_SIZE).contains(c)) {
+            return None;
+        }
+        let [x, y, z] = pos.map(|c| c as usize);
+        let size = CHUNK_SIZE as usize;
+        Some(((orientation * size + x) * size + y) * size + z)
+    }
+
+    fn insert(&mut self, orientation: usize, pos: [i32; 3]) {
+        if let Some(i) = Self::index(orientation, pos) {
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    fn contains(&self, orientation: usize, pos: [i32; 3]) -> bool {
+        Self::index(orientation, pos).is_some_and(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+}
+
+fn add(a: [i32; 3], b: [i32; 3]) -> [i32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn unit(axis: usize, sign: i32) -> [i32; 3] {
+    let mut v = [0; 3];
+    v[axis] = sign;
+    v
+}
+
+/// Occlusion of all four corners of a face, 3 means unoccluded.
+fn face_occlusion(faces: &FaceSet, orientation: usize, pos: [i32; 3]) -> u32 {
+    let normal_axis = orientation / 2;
+    let normal_sign = if orientation % 2 == 1 { 1 } else { -1 };
+    let air = add(pos, unit(normal_axis, normal_sign));
+    let tangents = [(normal_axis + 1) % 3, (normal_axis + 2) % 3];
+
+    let mut packed = 0;
+    for (corner, uv) in QUAD_UVS.iter().enumerate() {
+        let vertex: [i32; 3] = std::array::from_fn(|i| {
+            FACE_ORIGINS[orientation][i]
+                + FACE_U_AXES[orientation][i] * uv[0]
+                + FACE_V_AXES[orientation][i] * uv[1]
+        });
+        let [(t1, s1), (t2, s2)] = tangents.map(|t| (t, if vertex[t] > 0 { 1 } else { -1 }));
+
+        let side1_pos = add(air, unit(t1, s1));
+        let side2_pos = add(air, unit(t2, s2));
+        let corner_pos = add(side1_pos, unit(t2, s2));
+
+        // a solid side has a face pointing back into the air cell
+        let side1 = faces.contains(orientation_towards(t1, -s1), side1_pos);
+        let side2 = faces.contains(orientation_towards(t2, -s2), side2_pos);
+        let occlusion = if side1 && side2 {
+            0
+        } else {
+            // the corner voxel has a face towards whichever side is air
+            let corner_solid = (!side1 && faces.contains(orientation_towards(t2, -s2), corner_pos))
+                || (!side2 && faces.contains(orientation_towards(t1, -s1), corner_pos));
+            3 - side1 as u32 - side2 as u32 - corner_solid as u32
+        };
+        packed |= occlusion << (2 * corner);
+    }
+    packed
+}
+
+/// Converts a voxine mesh into `FaceInstance`s and returns them with the new per-orientation offsets.
+pub fn extend_mesh(mesh: &[u8], offsets: [u64; 6]) -> (Vec<u8>, [u64; 6]) {
+    let kinds: Vec<u32> = mesh
+        .chunks_exact(MESH_INSTANCE_SIZE)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+    let face_range = |orientation: usize| {
+        let start = offsets[orientation] as usize / MESH_INSTANCE_SIZE;
+        let end = offsets
+            .get(orientation + 1)
+            .map(|end| *end as usize / MESH_INSTANCE_SIZE)
+            .unwrap_or(kinds.len());
+        start..end
+    };
+
+    let mut faces = FaceSet {
+        bits: vec![0; (6 * CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize / 64],
+    };
+    for orientation in 0..6 {
+        for kind in &kinds[face_range(orientation)] {
+            faces.insert(orientation, decode_pos(*kind));
+        }
+    }
+
+    let mut instances = Vec::with_capacity(kinds.len());
+    let mut new_offsets = [0; 6];
+    for (orientation, new_offset) in new_offsets.iter_mut().enumerate() {
+        *new_offset = instances.len() as u64 * FaceInstance::SIZE;
+        for kind in &kinds[face_range(orientation)] {
+            instances.push(FaceInstance {
+                kind: *kind,
+                ambient_occlusion: face_occlusion(&faces, orientation, decode_pos(*kind)),
+            });
+        }
+    }
+    (bytemuck::cast_slice(&instances).to_vec(), new_offsets)
+}
@@ -0,0 +1,255 @@
+//! MSAA and TAA, FXAA is a post-processing pass. With MSAA the main pass draws into
+//! multisampled targets, the color is resolved by the render pass and the depth by a full screen
+//! pass, so the depth pyramid and the post-processing still read a plain depth texture.
+//! The TAA moves the projection by a different sub-pixel offset every frame and blends the image
+//! with the reprojected history of the earlier frames, before the post-processing.
+use glam::Vec2;
+
+use crate::gpu::{render_target_bind_group, texture::Texture};
+
+/// The length of the jitter sequence.
+const TAA_SAMPLES: u32 = 8;
+
+/// Whether the adapter can draw into the HDR target and the depth buffer with this many samples
+/// per pixel.
+pub fn msaa_supported(adapter: &wgpu::Adapter, samples: u32) -> bool {
+    [Texture::HDR_FORMAT, Texture::DEPTH_FORMAT]
+        .iter()
+        .all(|format| {
+            adapter
+                .get_texture_format_features(*format)
+                .flags
+                .sample_count_supported(samples)
+        })
+}
+
+/// The multisampled targets of the main pass.
+pub struct Msaa {
+    sample_count: u32,
+    pub color: wgpu::TextureView,
+    pub depth: wgpu::TextureView,
+    pub depth_layout: wgpu::BindGroupLayout,
+    /// The multisampled depth, read when it is resolved.
+    pub depth_bind_group: wgpu::BindGroup,
+}
+
+impl Msaa {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Self {
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth Resolve Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: true,
+                },
+                count: None,
+            }],
+        });
+        let (color, depth, depth_bind_group) =
+            Self::targets(device, config, sample_count, &depth_layout);
+        Self {
+            sample_count,
+            color,
+            depth,
+            depth_layout,
+            depth_bind_group,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.color, self.depth, self.depth_bind_group) =
+            Self::targets(device, config, self.sample_count, &self.depth_layout);
+    }
+
+    fn targets(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        depth_layout: &wgpu::BindGroupLayout,
+    ) -> (wgpu::TextureView, wgpu::TextureView, wgpu::BindGroup) {
+        let target = |label, format, usage| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: config.width.max(1),
+                        height: config.height.max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let color = target(
+            "Multisampled Rendering Target",
+            Texture::HDR_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let depth = target(
+            "Multisampled Depth Texture",
+            Texture::DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let depth_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Depth Resolve Bind Group"),
+            layout: depth_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth),
+            }],
+        });
+        (color, depth, depth_bind_group)
+    }
+
+    /// Writes the furthest depth of every pixel into `target`.
+    pub fn resolve_depth(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        target: &Texture,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass - Depth Resolve"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &target.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &self.depth_bind_group, &[]);
+        pass.draw(0..6, 0..1);
+    }
+}
+
+/// The history of the TAA, two textures which are read and written in turns.
+pub struct Taa {
+    history: [Texture; 2],
+    bind_groups: [wgpu::BindGroup; 2],
+    /// The history written this frame.
+    current: usize,
+    frame: u32,
+}
+
+impl Taa {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        layout: &wgpu::BindGroupLayout,
+        depth: &Texture,
+    ) -> Self {
+        let (history, bind_groups) = Self::history(device, config, layout, depth);
+        Self {
+            history,
+            bind_groups,
+            current: 0,
+            frame: 0,
+        }
+    }
+
+    /// Drops the history, the next frames start from the plain image again.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        layout: &wgpu::BindGroupLayout,
+        depth: &Texture,
+    ) {
+        (self.history, self.bind_groups) = Self::history(device, config, layout, depth);
+    }
+
+    fn history(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        layout: &wgpu::BindGroupLayout,
+        depth: &Texture,
+    ) -> ([Texture; 2], [wgpu::BindGroup; 2]) {
+        let history = [(); 2].map(|_| Texture {
+            // the reprojected positions fall between the texels
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            ..Texture::create_rendering_target(device, config)
+        });
+        let bind_groups =
+            [0, 1].map(|i| render_target_bind_group(device, layout, &history[i], depth));
+        (history, bind_groups)
+    }
+
+    /// The offset of the projection in this frame, in NDC units.
+    pub fn next_jitter(&mut self, resolution: [u32; 2]) -> Vec2 {
+        self.frame = self.frame % TAA_SAMPLES + 1;
+        let sample = Vec2::new(halton(self.frame, 2), halton(self.frame, 3)) - 0.5;
+        // one pixel is 2 / resolution NDC units wide
+        sample * 2. / Vec2::new(resolution[0] as f32, resolution[1] as f32)
+    }
+
+    /// Blends the scene with the history and returns the bind group of the result,
+    /// which is the input of the post-processing.
+    pub fn resolve(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        scene: &wgpu::BindGroup,
+        camera: &wgpu::BindGroup,
+    ) -> &wgpu::BindGroup {
+        let previous = self.current;
+        self.current = 1 - self.current;
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass - TAA"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.history[self.current].view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, scene, &[]);
+            pass.set_bind_group(1, camera, &[]);
+            pass.set_bind_group(2, &self.bind_groups[previous], &[]);
+            pass.draw(0..6, 0..1);
+        }
+        &self.bind_groups[self.current]
+    }
+}
+
+/// The `index`th element of the Halton sequence, evenly spread points in 0..1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.;
+    let mut fraction = 1.;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
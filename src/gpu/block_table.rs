@@ -0,0 +1,90 @@
+/// Has to match `MAX_BLOCK_TYPES` in projection.wgsl.
+pub const MAX_BLOCK_TYPES: usize = 256;
+
+/// Has to match `BLOCK_TRANSLUCENT` in projection.wgsl.
+const BLOCK_TRANSLUCENT: u32 = 1;
+
+/// The faces in the order of the mesh orientations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    NegX,
+    PosX,
+    Bottom,
+    Top,
+    NegZ,
+    PosZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::NegX,
+        Face::PosX,
+        Face::Bottom,
+        Face::Top,
+        Face::NegZ,
+        Face::PosZ,
+    ];
+    pub const SIDES: [Face; 4] = [Face::NegX, Face::PosX, Face::NegZ, Face::PosZ];
+
+    pub fn orientation(self) -> usize {
+        self as usize
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct BlockFaces {
+    layers: [u32; 6],
+    flags: u32,
+    _padding: u32,
+}
+
+/// Maps every voxel type and face to a layer of the block texture array.
+/// It is uploaded as a uniform buffer and read in `vs_main`.
+pub struct BlockTable {
+    blocks: Vec<BlockFaces>,
+}
+
+impl BlockTable {
+    /// Every block uses the texture layer with its own id on all faces, the blocks beyond the
+    /// last of the `layers` use `fallback` instead.
+    pub fn identity(layers: usize, fallback: u32) -> Self {
+        Self {
+            blocks: (0..MAX_BLOCK_TYPES as u32)
+                .map(|id| BlockFaces {
+                    layers: [if (id as usize) < layers { id } else { fallback }; 6],
+                    flags: 0,
+                    _padding: 0,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn set(&mut self, block: usize, face: Face, layer: u32) {
+        self.blocks[block].layers[face.orientation()] = layer;
+    }
+
+    /// Translucent blocks are drawn after everything else, blended with what is behind them.
+    pub fn set_translucent(&mut self, block: usize, translucent: bool) {
+        if translucent {
+            self.blocks[block].flags |= BLOCK_TRANSLUCENT;
+        } else {
+            self.blocks[block].flags &= !BLOCK_TRANSLUCENT;
+        }
+    }
+
+    /// Whether the translucent pass is needed at all.
+    pub fn has_translucent(&self) -> bool {
+        self.blocks
+            .iter()
+            .any(|block| block.flags & BLOCK_TRANSLUCENT != 0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.blocks)
+    }
+
+    pub fn size() -> u64 {
+        (MAX_BLOCK_TYPES * std::mem::size_of::<BlockFaces>()) as u64
+    }
+}
@@ -0,0 +1,237 @@
+//! The bloom, a chain of ever smaller copies of the bright parts of the scene. Every level is
+//! downsampled from the one before, then they are upsampled and added onto each other from the
+//! smallest one up, which spreads the glow far without a large blur kernel. The post-processing
+//! adds the first level to the scene, see `POST_BLOOM` in post_processing.wgsl.
+use crate::gpu::{pipelines::BloomPipelines, texture::Texture};
+
+/// The most levels of the chain, the first one has half the size of the screen.
+const MAX_LEVELS: u32 = 6;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct BloomUniform {
+    threshold: f32,
+    _padding: [f32; 3],
+}
+
+/// One level of the chain.
+struct Level {
+    /// Drawn into.
+    target: wgpu::TextureView,
+    /// Reads the level.
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct Bloom {
+    levels: Vec<Level>,
+    /// The first level, which the post-processing samples.
+    pub view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    buffer: wgpu::Buffer,
+    settings_bind_group: wgpu::BindGroup,
+    pub level_layout: wgpu::BindGroupLayout,
+    pub settings_layout: wgpu::BindGroupLayout,
+}
+
+impl Bloom {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let level_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Level Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let settings_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Settings Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Bloom Settings Buffer"),
+            size: std::mem::size_of::<BloomUniform>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let settings_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Settings Bind Group"),
+            layout: &settings_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (levels, view) = Self::create_levels(device, config, &level_layout, &sampler);
+        Self {
+            levels,
+            view,
+            sampler,
+            buffer,
+            settings_bind_group,
+            level_layout,
+            settings_layout,
+        }
+    }
+
+    /// Has to be called whenever the surface is resized.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.levels, self.view) =
+            Self::create_levels(device, config, &self.level_layout, &self.sampler);
+    }
+
+    fn create_levels(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> (Vec<Level>, wgpu::TextureView) {
+        let width = (config.width / 2).max(1);
+        let height = (config.height / 2).max(1);
+        // stops before the smaller side would shrink below one texel
+        let level_count = MAX_LEVELS.min(u32::BITS - width.min(height).leading_zeros());
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Bloom Chain"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let level_view = |level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let levels = (0..level_count)
+            .map(|level| {
+                let target = level_view(level);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Bloom Level Bind Group"),
+                    layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&target),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                    ],
+                });
+                Level { target, bind_group }
+            })
+            .collect();
+        (levels, level_view(0))
+    }
+
+    pub fn set_threshold(&self, queue: &wgpu::Queue, threshold: f32) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[BloomUniform {
+                threshold,
+                _padding: [0.; 3],
+            }]),
+        );
+    }
+
+    /// Builds the chain from `scene`, a render target bind group.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipelines: &BloomPipelines,
+        scene: &wgpu::BindGroup,
+    ) {
+        for (i, level) in self.levels.iter().enumerate() {
+            let mut pass = begin_pass(
+                encoder,
+                &level.target,
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                "Render Pass - Bloom Downsample",
+            );
+            if i == 0 {
+                pass.set_pipeline(&pipelines.prefilter);
+                pass.set_bind_group(0, scene, &[]);
+            } else {
+                pass.set_pipeline(&pipelines.downsample);
+                pass.set_bind_group(0, &self.levels[i - 1].bind_group, &[]);
+            }
+            pass.set_bind_group(1, &self.settings_bind_group, &[]);
+            pass.draw(0..6, 0..1);
+        }
+        for i in (1..self.levels.len()).rev() {
+            let mut pass = begin_pass(
+                encoder,
+                &self.levels[i - 1].target,
+                wgpu::LoadOp::Load,
+                "Render Pass - Bloom Upsample",
+            );
+            pass.set_pipeline(&pipelines.upsample);
+            pass.set_bind_group(0, &self.levels[i].bind_group, &[]);
+            pass.set_bind_group(1, &self.settings_bind_group, &[]);
+            pass.draw(0..6, 0..1);
+        }
+    }
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    label: &str,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    })
+}
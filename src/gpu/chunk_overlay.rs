@@ -0,0 +1,158 @@
+//! Debug lines drawn over the scene: the bounding box of every chunk drawn this frame, colored by
+//! its level of detail, and the outline of a detached view frustum.
+use glam::Vec3;
+
+use crate::gpu::projection::{View, frustum_corners};
+
+/// The same colors as `LOD_COLORS` in projection.wgsl.
+const LOD_COLORS: [[f32; 3]; 6] = [
+    [1.0, 0.3, 0.3],
+    [0.3, 1.0, 0.3],
+    [0.3, 0.3, 1.0],
+    [1.0, 1.0, 0.3],
+    [1.0, 0.3, 1.0],
+    [0.3, 1.0, 1.0],
+];
+const FRUSTUM_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// The corners of a box or a frustum which are joined by its 12 edges,
+/// for corners ordered like the ones of `frustum_corners`.
+const EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (0, 2),
+    (1, 3),
+    (4, 5),
+    (6, 7),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+pub struct LineVertex {
+    pos: [f32; 3],
+    color: [f32; 3],
+}
+
+impl LineVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+pub struct ChunkOverlay {
+    /// Whether the chunk bounding boxes are drawn.
+    pub chunk_bounds: bool,
+    /// Whether the frustum the chunks are selected with is drawn,
+    /// only useful if it is detached from the camera.
+    pub frustum: bool,
+
+    vertices: Vec<LineVertex>,
+    buffer: wgpu::Buffer,
+}
+
+impl ChunkOverlay {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            chunk_bounds: false,
+            frustum: false,
+            vertices: vec![],
+            buffer: Self::buffer(device, 1024),
+        }
+    }
+
+    fn buffer(device: &wgpu::Device, vertices: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Overlay Buffer"),
+            size: (vertices * std::mem::size_of::<LineVertex>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    /// Uploads the lines of this frame, `chunks` are the ones drawn
+    /// and `frustum` the outline from `frustum_outline`.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        chunks: &[voxine::ChunkID],
+        frustum: Option<[Vec3; 8]>,
+    ) {
+        self.vertices.clear();
+        if self.chunk_bounds {
+            for chunk in chunks {
+                let size = (32 << chunk.lod) as f32;
+                let min = chunk.total_pos().as_vec3() * 32.;
+                let corners = [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+                    min + Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, (i >> 2) as f32) * size
+                });
+                self.push_edges(&corners, LOD_COLORS[chunk.lod as usize % LOD_COLORS.len()]);
+            }
+        }
+        if let Some(corners) = frustum {
+            self.push_edges(&corners, FRUSTUM_COLOR);
+        }
+
+        if self.vertices.is_empty() {
+            return;
+        }
+        let size = (self.vertices.len() * std::mem::size_of::<LineVertex>()) as u64;
+        if size > self.buffer.size() {
+            self.buffer = Self::buffer(device, self.vertices.len().next_power_of_two());
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+    }
+
+    fn push_edges(&mut self, corners: &[Vec3; 8], color: [f32; 3]) {
+        for (a, b) in EDGES {
+            for corner in [corners[a], corners[b]] {
+                self.vertices.push(LineVertex {
+                    pos: corner.to_array(),
+                    color,
+                });
+            }
+        }
+    }
+
+    /// Draws the lines uploaded by `update`, the pipeline and the camera have to be set.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.draw(0..self.vertices.len() as u32, 0..1);
+    }
+}
+
+/// The corners of the frustum the chunks are selected with, in world space.
+pub fn frustum_outline(frustum: &voxine::Frustum) -> [Vec3; 8] {
+    let view = View::new(
+        frustum.cam_pos * 32.,
+        frustum.direction,
+        if frustum.direction.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        },
+    );
+    frustum_corners(
+        view,
+        frustum.fov,
+        frustum.aspect_ratio,
+        0.,
+        frustum.max_distance * 32.,
+    )
+}
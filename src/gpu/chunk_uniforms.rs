@@ -0,0 +1,120 @@
+//! The fallback for adapters without push constants, like WebGPU and some GL drivers. The
+//! `ChunkPushConstant` of every chunk draw of a frame is collected into uniform buffers and each
+//! draw binds its entry with a dynamic offset. The indirect draws need push constants, without them
+//! the chunks are always drawn by the CPU.
+use crate::gpu::ChunkPushConstant;
+
+/// The group of `chunk_metadata` in projection.wgsl without `PUSH_CONSTANTS`.
+pub const GROUP: u32 = 3;
+
+/// Has to match the size of `ChunkMetadata` in projection.wgsl.
+const ENTRY_SIZE: u64 = 32;
+
+/// The entries per buffer, another one is added when a frame needs more.
+const PAGE_ENTRIES: u64 = 256;
+
+struct Page {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct ChunkUniforms {
+    device: wgpu::Device,
+    pages: Vec<Page>,
+    pub layout: wgpu::BindGroupLayout,
+    /// Fills the group between the shadow cascade and `GROUP` in the shadow passes.
+    pub empty_layout: wgpu::BindGroupLayout,
+    pub empty_bind_group: wgpu::BindGroup,
+    /// The distance between two entries, the offset alignment of the device.
+    stride: u64,
+    /// The entries of this frame, one stride apart, the pages one after another.
+    entries: Vec<u8>,
+}
+
+impl ChunkUniforms {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Chunk Uniform Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(ENTRY_SIZE),
+                },
+                count: None,
+            }],
+        });
+        let empty_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Empty Bind Group Layout"),
+            entries: &[],
+        });
+        let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Empty Bind Group"),
+            layout: &empty_layout,
+            entries: &[],
+        });
+        Self {
+            device: device.clone(),
+            pages: vec![],
+            layout,
+            empty_layout,
+            empty_bind_group,
+            stride: u64::from(device.limits().min_uniform_buffer_offset_alignment).max(ENTRY_SIZE),
+            entries: vec![],
+        }
+    }
+
+    /// Forgets the entries of the last frame.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Binds the entry of the next draw.
+    pub fn set(&mut self, render_pass: &mut wgpu::RenderPass, constant: ChunkPushConstant) {
+        let index = self.entries.len() as u64 / self.stride;
+        let page = (index / PAGE_ENTRIES) as usize;
+        if page == self.pages.len() {
+            self.pages.push(self.create_page());
+        }
+        self.entries
+            .extend_from_slice(bytemuck::bytes_of(&constant));
+        self.entries.resize(((index + 1) * self.stride) as usize, 0);
+        let offset = (index % PAGE_ENTRIES) * self.stride;
+        render_pass.set_bind_group(GROUP, &self.pages[page].bind_group, &[offset as u32]);
+    }
+
+    /// Uploads the entries of this frame, the writes land before the next submit.
+    pub fn upload(&self, queue: &wgpu::Queue) {
+        for (page, entries) in self
+            .pages
+            .iter()
+            .zip(self.entries.chunks((PAGE_ENTRIES * self.stride) as usize))
+        {
+            queue.write_buffer(&page.buffer, 0, entries);
+        }
+    }
+
+    fn create_page(&self) -> Page {
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Uniform Buffer"),
+            size: PAGE_ENTRIES * self.stride,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Chunk Uniform Bind Group"),
+            layout: &self.layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(ENTRY_SIZE),
+                }),
+            }],
+        });
+        Page { buffer, bind_group }
+    }
+}
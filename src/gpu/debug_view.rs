@@ -0,0 +1,68 @@
+//! Debug visualizations of the terrain, cycled at runtime. Most of them only change the color the
+//! terrain shader writes, the overdraw heatmap and the wireframe have their own chunk pipelines.
+
+/// Has to match the `DEBUG_*` constants in projection.wgsl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    /// The normal shading.
+    Off = 0,
+    /// One color per level of detail.
+    Lod = 1,
+    /// A random color per chunk.
+    ChunkId = 2,
+    /// One color per face orientation.
+    FaceOrientation = 3,
+    /// How often every pixel is drawn, without depth test.
+    Overdraw = 4,
+    /// The distance to the camera, bright is close.
+    Depth = 5,
+    /// Only the edges of the faces.
+    Wireframe = 6,
+}
+
+/// The features needed for the wireframe, it is skipped without them.
+pub const WIREFRAME_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE;
+
+impl DebugView {
+    const ALL: [Self; 7] = [
+        Self::Off,
+        Self::Lod,
+        Self::ChunkId,
+        Self::FaceOrientation,
+        Self::Overdraw,
+        Self::Depth,
+        Self::Wireframe,
+    ];
+
+    /// The mode after this one, `Off` after the last.
+    pub fn next(self, wireframe: bool) -> Self {
+        let next = Self::ALL[(self as usize + 1) % Self::ALL.len()];
+        if next == Self::Wireframe && !wireframe {
+            return next.next(wireframe);
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_through_every_mode() {
+        let mut view = DebugView::Off;
+        let mut seen = vec![];
+        for _ in 0..DebugView::ALL.len() {
+            view = view.next(true);
+            seen.push(view);
+        }
+        assert_eq!(view, DebugView::Off);
+        assert!(DebugView::ALL.iter().all(|mode| seen.contains(mode)));
+    }
+
+    #[test]
+    fn wireframe_is_skipped_without_the_feature() {
+        assert_eq!(DebugView::Depth.next(false), DebugView::Off);
+        assert_eq!(DebugView::Depth.next(true), DebugView::Wireframe);
+    }
+}
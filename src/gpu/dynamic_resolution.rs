@@ -0,0 +1,205 @@
+//! Dynamic resolution. The scene and the post-processing are drawn into targets smaller than the
+//! window while the frames take longer than the target frame time, the last post-processing pass
+//! scales them up onto the surface. The scale only moves in coarse steps and the frame time has to
+//! be clearly below the target before it goes up again, so it doesn't jump back and forth.
+use std::time::Duration;
+
+use crate::config::DynamicResolutionConfig;
+
+/// How many frames are averaged before the scale is reconsidered.
+const WINDOW: u32 = 30;
+/// The scale moves in steps of this size, every change recreates the render targets.
+const STEP: f32 = 0.05;
+/// The scale goes down when the frames take this much longer than the target...
+const TOO_SLOW: f64 = 1.05;
+/// ...and up when they are this much faster.
+const FAST_ENOUGH: f64 = 0.8;
+
+pub struct DynamicResolution {
+    config: DynamicResolutionConfig,
+    /// Of both sides, 1 is the window resolution.
+    scale: f32,
+    /// The sum over the frames of the current window.
+    frame_time: Duration,
+    frames: u32,
+}
+
+impl DynamicResolution {
+    pub fn new(config: &DynamicResolutionConfig) -> Self {
+        Self {
+            config: config.clone(),
+            scale: initial_scale(config),
+            frame_time: Duration::ZERO,
+            frames: 0,
+        }
+    }
+
+    /// Returns whether the scale changed. Switching it on or off starts over at the largest scale,
+    /// other changes only keep the scale within the new bounds.
+    pub fn set_config(&mut self, config: &DynamicResolutionConfig) -> bool {
+        if *config == self.config {
+            return false;
+        }
+        let scale = self.scale;
+        if config.enabled != self.config.enabled {
+            *self = Self::new(config);
+        } else {
+            self.config = config.clone();
+            if config.enabled {
+                self.scale = scale.clamp(config.min_scale, config.max_scale);
+            }
+        }
+        self.scale != scale
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// The size of the render targets for a surface of the size of `config`.
+    pub fn scaled(&self, config: &wgpu::SurfaceConfiguration) -> wgpu::SurfaceConfiguration {
+        let scale = |side: u32| ((side as f32 * self.scale).round() as u32).max(1);
+        wgpu::SurfaceConfiguration {
+            width: scale(config.width),
+            height: scale(config.height),
+            ..config.clone()
+        }
+    }
+
+    /// Takes the time of a frame, returns whether the scale changed. The wait for the surface is
+    /// left out, with vsync it pads every frame to the refresh interval.
+    pub fn update(&mut self, frame_time: Duration, surface_wait: Duration) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+        self.frame_time += frame_time.saturating_sub(surface_wait);
+        self.frames += 1;
+        if self.frames < WINDOW {
+            return false;
+        }
+
+        let average = self.frame_time.as_secs_f64() * 1_000. / f64::from(self.frames);
+        self.frame_time = Duration::ZERO;
+        self.frames = 0;
+
+        let target = self.config.target_frame_time;
+        let scale = if average > target * TOO_SLOW {
+            // the cost grows with the pixel count, the square of the scale
+            let wanted = self.scale * (target / average).sqrt() as f32;
+            // at least one step, rounding to the step below
+            ((wanted / STEP).floor() * STEP).min(self.scale - STEP)
+        } else if average < target * FAST_ENOUGH {
+            self.scale + STEP
+        } else {
+            self.scale
+        };
+        let scale = scale.clamp(self.config.min_scale, self.config.max_scale);
+        let changed = (scale - self.scale).abs() > f32::EPSILON;
+        self.scale = scale;
+        changed
+    }
+}
+
+fn initial_scale(config: &DynamicResolutionConfig) -> f32 {
+    if config.enabled { config.max_scale } else { 1. }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> DynamicResolutionConfig {
+        DynamicResolutionConfig {
+            enabled: true,
+            target_frame_time: 10.,
+            min_scale: 0.5,
+            max_scale: 1.,
+        }
+    }
+
+    /// Feeds a whole window of frames taking `ms`, returns whether the scale changed.
+    fn run_window(resolution: &mut DynamicResolution, ms: f64) -> bool {
+        let mut changed = false;
+        for _ in 0..WINDOW {
+            changed |= resolution.update(Duration::from_secs_f64(ms / 1_000.), Duration::ZERO);
+        }
+        changed
+    }
+
+    #[test]
+    fn slow_frames_lower_the_scale_down_to_the_minimum() {
+        let mut resolution = DynamicResolution::new(&config());
+        assert!(run_window(&mut resolution, 20.));
+        // sqrt(10 / 20) rounds down to 0.7
+        assert!((resolution.scale() - 0.7).abs() < 1e-4);
+        for _ in 0..10 {
+            run_window(&mut resolution, 40.);
+        }
+        assert_eq!(resolution.scale(), 0.5);
+    }
+
+    #[test]
+    fn frames_near_the_target_keep_the_scale() {
+        let mut resolution = DynamicResolution::new(&config());
+        run_window(&mut resolution, 20.);
+        let scale = resolution.scale();
+        for ms in [10.4, 8.5, 9.9, 10.2] {
+            assert!(!run_window(&mut resolution, ms));
+        }
+        assert_eq!(resolution.scale(), scale);
+    }
+
+    #[test]
+    fn fast_frames_raise_the_scale_step_by_step() {
+        let mut resolution = DynamicResolution::new(&config());
+        run_window(&mut resolution, 40.);
+        let scale = resolution.scale();
+        assert!(run_window(&mut resolution, 5.));
+        assert!((resolution.scale() - (scale + STEP)).abs() < 1e-4);
+        for _ in 0..20 {
+            run_window(&mut resolution, 5.);
+        }
+        assert_eq!(resolution.scale(), 1.);
+    }
+
+    #[test]
+    fn frames_pinned_at_the_target_by_vsync_raise_the_scale() {
+        let mut resolution = DynamicResolution::new(&config());
+        run_window(&mut resolution, 20.);
+        let scale = resolution.scale();
+        // 10 ms each, 4 of them waiting for the surface
+        let mut changed = false;
+        for _ in 0..WINDOW {
+            changed |= resolution.update(Duration::from_millis(10), Duration::from_millis(4));
+        }
+        assert!(changed);
+        assert!((resolution.scale() - (scale + STEP)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn new_bounds_keep_the_scale_within_them() {
+        let mut resolution = DynamicResolution::new(&config());
+        run_window(&mut resolution, 20.);
+        assert!(!resolution.set_config(&config()));
+        assert!(!resolution.set_config(&DynamicResolutionConfig {
+            target_frame_time: 12.,
+            ..config()
+        }));
+        assert!((resolution.scale() - 0.7).abs() < 1e-4);
+        assert!(resolution.set_config(&DynamicResolutionConfig {
+            max_scale: 0.6,
+            ..config()
+        }));
+        assert_eq!(resolution.scale(), 0.6);
+    }
+
+    #[test]
+    fn disabled_stays_at_the_window_resolution() {
+        let mut resolution = DynamicResolution::new(&DynamicResolutionConfig {
+            enabled: false,
+            ..config()
+        });
+        assert!(!run_window(&mut resolution, 100.));
+        assert_eq!(resolution.scale(), 1.);
+    }
+}
@@ -0,0 +1,81 @@
+//! The average luminance of the scene for the auto exposure of the tone mapping. A full screen
+//! pass into a single texel averages the log luminance of a grid of pixels and blends it with the
+//! value of the earlier frames, so the exposure adapts over time like an eye.
+use web_time::Instant;
+
+/// How fast the exposure adapts, after one second e^-ADAPTATION_SPEED of the difference is left.
+const ADAPTATION_SPEED: f64 = 1.5;
+
+pub struct Exposure {
+    /// The log2 of the average luminance, in a single texel.
+    pub view: wgpu::TextureView,
+    /// None until the first measurement, which is taken as it is.
+    last_measurement: Option<Instant>,
+}
+
+impl Exposure {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Average Luminance"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            view,
+            last_measurement: None,
+        }
+    }
+
+    /// Moves the average luminance towards the one of `scene`.
+    pub fn measure(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        scene: &wgpu::BindGroup,
+    ) {
+        let now = Instant::now();
+        let blend = match self.last_measurement.replace(now) {
+            Some(last) => 1. - (-(now - last).as_secs_f64() * ADAPTATION_SPEED).exp(),
+            None => 1.,
+        };
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass - Average Luminance"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        // the pipeline blends with the constant, how much of the new value is taken
+        pass.set_blend_constant(wgpu::Color {
+            r: blend,
+            g: blend,
+            b: blend,
+            a: blend,
+        });
+        pass.set_bind_group(0, scene, &[]);
+        pass.draw(0..6, 0..1);
+    }
+}
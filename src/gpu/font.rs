@@ -0,0 +1,136 @@
+//! The bitmap font of the HUD, 5x7 pixel glyphs of the upper case letters, the digits and a bit of
+//! punctuation. Lower case letters are drawn upper case. The glyphs are put next to each other
+//! into an atlas texture, each in a cell one pixel larger so the text has gaps without spacing it.
+
+/// The size of a glyph cell in the atlas, has to match `CELL_SIZE` in hud.wgsl.
+pub const CELL_WIDTH: u32 = 6;
+pub const CELL_HEIGHT: u32 = 8;
+
+/// The cell which is completely filled, for solid rectangles.
+pub const SOLID: u32 = 0;
+
+#[rustfmt::skip]
+const GLYPHS: &[(char, [&str; 7])] = &[
+    ('0', [".###.", "#...#", "#..##", "#.#.#", "##..#", "#...#", ".###."]),
+    ('1', ["..#..", ".##..", "..#..", "..#..", "..#..", "..#..", ".###."]),
+    ('2', [".###.", "#...#", "....#", "...#.", "..#..", ".#...", "#####"]),
+    ('3', ["####.", "....#", "....#", ".###.", "....#", "....#", "####."]),
+    ('4', ["...#.", "..##.", ".#.#.", "#..#.", "#####", "...#.", "...#."]),
+    ('5', ["#####", "#....", "####.", "....#", "....#", "#...#", ".###."]),
+    ('6', ["..##.", ".#...", "#....", "####.", "#...#", "#...#", ".###."]),
+    ('7', ["#####", "....#", "...#.", "..#..", ".#...", ".#...", ".#..."]),
+    ('8', [".###.", "#...#", "#...#", ".###.", "#...#", "#...#", ".###."]),
+    ('9', [".###.", "#...#", "#...#", ".####", "....#", "...#.", ".##.."]),
+    ('A', [".###.", "#...#", "#...#", "#####", "#...#", "#...#", "#...#"]),
+    ('B', ["####.", "#...#", "#...#", "####.", "#...#", "#...#", "####."]),
+    ('C', [".###.", "#...#", "#....", "#....", "#....", "#...#", ".###."]),
+    ('D', ["###..", "#..#.", "#...#", "#...#", "#...#", "#..#.", "###.."]),
+    ('E', ["#####", "#....", "#....", "####.", "#....", "#....", "#####"]),
+    ('F', ["#####", "#....", "#....", "####.", "#....", "#....", "#...."]),
+    ('G', [".###.", "#...#", "#....", "#.###", "#...#", "#...#", ".####"]),
+    ('H', ["#...#", "#...#", "#...#", "#####", "#...#", "#...#", "#...#"]),
+    ('I', [".###.", "..#..", "..#..", "..#..", "..#..", "..#..", ".###."]),
+    ('J', ["..###", "...#.", "...#.", "...#.", "...#.", "#..#.", ".##.."]),
+    ('K', ["#...#", "#..#.", "#.#..", "##...", "#.#..", "#..#.", "#...#"]),
+    ('L', ["#....", "#....", "#....", "#....", "#....", "#....", "#####"]),
+    ('M', ["#...#", "##.##", "#.#.#", "#.#.#", "#...#", "#...#", "#...#"]),
+    ('N', ["#...#", "#...#", "##..#", "#.#.#", "#..##", "#...#", "#...#"]),
+    ('O', [".###.", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."]),
+    ('P', ["####.", "#...#", "#...#", "####.", "#....", "#....", "#...."]),
+    ('Q', [".###.", "#...#", "#...#", "#...#", "#.#.#", "#..#.", ".##.#"]),
+    ('R', ["####.", "#...#", "#...#", "####.", "#.#..", "#..#.", "#...#"]),
+    ('S', [".####", "#....", "#....", ".###.", "....#", "....#", "####."]),
+    ('T', ["#####", "..#..", "..#..", "..#..", "..#..", "..#..", "..#.."]),
+    ('U', ["#...#", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."]),
+    ('V', ["#...#", "#...#", "#...#", "#...#", "#...#", ".#.#.", "..#.."]),
+    ('W', ["#...#", "#...#", "#...#", "#.#.#", "#.#.#", "#.#.#", ".#.#."]),
+    ('X', ["#...#", "#...#", ".#.#.", "..#..", ".#.#.", "#...#", "#...#"]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#..", "..#.."]),
+    ('Z', ["#####", "....#", "...#.", "..#..", ".#...", "#....", "#####"]),
+    ('.', [".....", ".....", ".....", ".....", ".....", ".##..", ".##.."]),
+    (',', [".....", ".....", ".....", ".....", ".##..", "..#..", ".#..."]),
+    (':', [".....", ".##..", ".##..", ".....", ".##..", ".##..", "....."]),
+    ('-', [".....", ".....", ".....", "#####", ".....", ".....", "....."]),
+    ('+', [".....", "..#..", "..#..", "#####", "..#..", "..#..", "....."]),
+    ('/', [".....", "....#", "...#.", "..#..", ".#...", "#....", "....."]),
+    ('%', ["##...", "##..#", "...#.", "..#..", ".#...", "#..##", "...##"]),
+    ('(', ["...#.", "..#..", ".#...", ".#...", ".#...", "..#..", "...#."]),
+    (')', [".#...", "..#..", "...#.", "...#.", "...#.", "..#..", ".#..."]),
+    ('=', [".....", ".....", "#####", ".....", "#####", ".....", "....."]),
+    ('_', [".....", ".....", ".....", ".....", ".....", ".....", "#####"]),
+    // for the shader errors
+    ('<', ["...#.", "..#..", ".#...", "#....", ".#...", "..#..", "...#."]),
+    ('>', [".#...", "..#..", "...#.", "....#", "...#.", "..#..", ".#..."]),
+    ('[', [".###.", ".#...", ".#...", ".#...", ".#...", ".#...", ".###."]),
+    (']', [".###.", "...#.", "...#.", "...#.", "...#.", "...#.", ".###."]),
+    ('{', ["..##.", ".#...", ".#...", "#....", ".#...", ".#...", "..##."]),
+    ('}', [".##..", "...#.", "...#.", "....#", "...#.", "...#.", ".##.."]),
+    ('\'', ["..#..", "..#..", ".#...", ".....", ".....", ".....", "....."]),
+    ('"', [".#.#.", ".#.#.", ".....", ".....", ".....", ".....", "....."]),
+    (';', [".....", ".##..", ".##..", ".....", ".##..", "..#..", ".#..."]),
+    ('*', [".....", "#.#.#", ".###.", "#####", ".###.", "#.#.#", "....."]),
+    ('!', ["..#..", "..#..", "..#..", "..#..", "..#..", ".....", "..#.."]),
+    ('&', [".##..", "#..#.", "#.#..", ".#...", "#.#.#", "#..#.", ".##.#"]),
+    ('|', ["..#..", "..#..", "..#..", "..#..", "..#..", "..#..", "..#.."]),
+    ('@', [".###.", "#...#", "#.###", "#.#.#", "#.###", "#....", ".###."]),
+    ('?', [".###.", "#...#", "....#", "...#.", "..#..", ".....", "..#.."]),
+];
+
+/// The cells in the atlas, `SOLID` and the glyphs.
+pub const CELL_COUNT: u32 = GLYPHS.len() as u32 + 1;
+
+/// The cell of `c`, None for spaces. Characters without a glyph are drawn as `?`.
+pub fn cell(c: char) -> Option<u32> {
+    if c.is_whitespace() {
+        return None;
+    }
+    let c = c.to_ascii_uppercase();
+    let index = GLYPHS
+        .iter()
+        .position(|(glyph, _)| *glyph == c)
+        .or_else(|| GLYPHS.iter().position(|(glyph, _)| *glyph == '?'))?;
+    Some(index as u32 + 1)
+}
+
+/// The coverage of every pixel of the atlas, one byte each, `CELL_COUNT` cells wide.
+pub fn atlas() -> Vec<u8> {
+    let width = (CELL_COUNT * CELL_WIDTH) as usize;
+    let mut pixels = vec![0; width * CELL_HEIGHT as usize];
+    for y in 0..CELL_HEIGHT as usize {
+        pixels[y * width..][..CELL_WIDTH as usize].fill(u8::MAX);
+    }
+    for (index, (_, rows)) in GLYPHS.iter().enumerate() {
+        let x0 = (index + 1) * CELL_WIDTH as usize;
+        for (y, row) in rows.iter().enumerate() {
+            for (x, pixel) in row.bytes().enumerate() {
+                if pixel == b'#' {
+                    pixels[y * width + x0 + x] = u8::MAX;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_fit_into_their_cells() {
+        for (c, rows) in GLYPHS {
+            for row in rows {
+                assert_eq!(row.len() as u32, CELL_WIDTH - 1, "glyph {:?}", c);
+                assert!(row.bytes().all(|pixel| pixel == b'#' || pixel == b'.'));
+            }
+        }
+    }
+
+    #[test]
+    fn lower_case_and_unknown_characters_have_cells() {
+        assert_eq!(cell('a'), cell('A'));
+        assert_eq!(cell('~'), cell('?'));
+        assert_eq!(cell(' '), None);
+        assert_ne!(cell('0'), Some(SOLID));
+    }
+}
@@ -148,6 +148,16 @@ impl GPUSlotAllocator {
         )
     }
 
+    /// How many slots each buffer of the slot's size class has.
+    pub fn slots_per_buffer(&self, slot_id: SlotID) -> usize {
+        self.size_classes[slot_id.0].slots_per_buffer
+    }
+
+    /// The buffer the slot lies in.
+    pub fn buffer(&self, slot_id: SlotID) -> &wgpu::Buffer {
+        &self.size_classes[slot_id.0].buffer_pool[slot_id.1]
+    }
+
     fn slot_size_for(&self, required_size: usize) -> usize {
         let requested = required_size.max(1).next_power_of_two();
         requested.max(self.min_slot_size)
@@ -0,0 +1,202 @@
+//! The depth pyramid for the occlusion culling. After the main pass the depth buffer is copied
+//! into the first level, every further level keeps the furthest depth of the 2x2 texels below it.
+//! The culling of the next frame tests the chunk boxes against it, see `occluded` in culling.wgsl.
+use crate::gpu::texture::Texture;
+
+/// Has to match `WORKGROUP_SIZE` in hi_z.wgsl.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The bind group writing one level and the size of it.
+struct Level {
+    bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
+pub struct DepthPyramid {
+    levels: Vec<Level>,
+    pub copy_layout: wgpu::BindGroupLayout,
+    pub downsample_layout: wgpu::BindGroupLayout,
+    /// The whole pyramid, read by the culling.
+    pub cull_layout: wgpu::BindGroupLayout,
+    pub cull_bind_group: wgpu::BindGroup,
+}
+
+impl DepthPyramid {
+    pub fn new(device: &wgpu::Device, depth: &Texture) -> Self {
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let storage_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::R32Float,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let float = wgpu::TextureSampleType::Float { filterable: false };
+
+        let copy_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth Pyramid Copy Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::TextureSampleType::Depth),
+                storage_entry,
+            ],
+        });
+        let downsample_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth Pyramid Downsample Bind Group Layout"),
+            entries: &[storage_entry, texture_entry(2, float)],
+        });
+        let cull_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth Pyramid Bind Group Layout"),
+            entries: &[texture_entry(0, float)],
+        });
+
+        let (levels, cull_bind_group) = Self::create_levels(
+            device,
+            depth,
+            &copy_layout,
+            &downsample_layout,
+            &cull_layout,
+        );
+        Self {
+            levels,
+            copy_layout,
+            downsample_layout,
+            cull_layout,
+            cull_bind_group,
+        }
+    }
+
+    /// Has to be called whenever the depth buffer is recreated.
+    pub fn resize(&mut self, device: &wgpu::Device, depth: &Texture) {
+        (self.levels, self.cull_bind_group) = Self::create_levels(
+            device,
+            depth,
+            &self.copy_layout,
+            &self.downsample_layout,
+            &self.cull_layout,
+        );
+    }
+
+    fn create_levels(
+        device: &wgpu::Device,
+        depth: &Texture,
+        copy_layout: &wgpu::BindGroupLayout,
+        downsample_layout: &wgpu::BindGroupLayout,
+        cull_layout: &wgpu::BindGroupLayout,
+    ) -> (Vec<Level>, wgpu::BindGroup) {
+        let (width, height) = (depth.texture.width(), depth.texture.height());
+        let level_count = u32::BITS - width.max(height).leading_zeros();
+        // zeroed, so nothing is occluded until the first pyramid is built
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Pyramid"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let level_view = |level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let levels = (0..level_count)
+            .map(|level| {
+                let dst = level_view(level);
+                let bind_group = if level == 0 {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("Depth Pyramid Copy Bind Group"),
+                        layout: copy_layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: wgpu::BindingResource::TextureView(&depth.view),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: wgpu::BindingResource::TextureView(&dst),
+                            },
+                        ],
+                    })
+                } else {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("Depth Pyramid Downsample Bind Group"),
+                        layout: downsample_layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: wgpu::BindingResource::TextureView(&dst),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 2,
+                                resource: wgpu::BindingResource::TextureView(&level_view(
+                                    level - 1,
+                                )),
+                            },
+                        ],
+                    })
+                };
+                Level {
+                    bind_group,
+                    width: (width >> level).max(1),
+                    height: (height >> level).max(1),
+                }
+            })
+            .collect();
+
+        let cull_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Depth Pyramid Bind Group"),
+            layout: cull_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                ),
+            }],
+        });
+        (levels, cull_bind_group)
+    }
+
+    /// Builds the pyramid from the depth buffer it was created with.
+    pub fn build(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        copy: &wgpu::ComputePipeline,
+        downsample: &wgpu::ComputePipeline,
+    ) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass - Depth Pyramid"),
+            timestamp_writes: None,
+        });
+        for (i, level) in self.levels.iter().enumerate() {
+            pass.set_pipeline(if i == 0 { copy } else { downsample });
+            pass.set_bind_group(0, &level.bind_group, &[]);
+            pass.dispatch_workgroups(
+                level.width.div_ceil(WORKGROUP_SIZE),
+                level.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+    }
+}
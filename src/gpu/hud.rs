@@ -0,0 +1,430 @@
+//! The debug screen, toggled with F3: the frame rate with a graph of the last frame times, the
+//! position and speed of the camera, the chunk counts, the mesh uploads and the memory of the mesh
+//! allocator. It is drawn onto the surface after the post-processing, so it stays sharp at any
+//! render scale. Everything is a quad showing a cell of the `font` atlas, the background and the
+//! graph bars use the solid one.
+//! A failed shader reload is shown below it, also while the debug screen is hidden.
+use std::collections::VecDeque;
+
+use glam::Vec3;
+use web_time::Instant;
+
+use crate::gpu::{font, gpu_allocator::GPUSlotAllocatorStats, indirect::CullStats};
+
+/// How many frame times the graph shows, one font pixel wide each.
+const GRAPH_FRAMES: usize = 120;
+/// The height of the graph in font pixels.
+const GRAPH_HEIGHT: f32 = 24.;
+/// The frame time at the top of the graph, in milliseconds.
+const GRAPH_MAX: f64 = 1_000. / 30.;
+/// Frames faster than this are drawn green, the slower ones yellow and those beyond the top red.
+const GRAPH_GOOD: f64 = 1_000. / 60.;
+
+const TEXT_COLOR: [f32; 4] = [1., 1., 1., 1.];
+const BACKGROUND_COLOR: [f32; 4] = [0., 0., 0., 0.6];
+const ERROR_BACKGROUND_COLOR: [f32; 4] = [0.5, 0., 0., 0.8];
+const GOOD_COLOR: [f32; 4] = [0.3, 0.9, 0.3, 0.9];
+const SLOW_COLOR: [f32; 4] = [0.9, 0.8, 0.2, 0.9];
+const TOO_SLOW_COLOR: [f32; 4] = [0.9, 0.2, 0.2, 0.9];
+/// The mark of `GRAPH_GOOD`.
+const GUIDE_COLOR: [f32; 4] = [1., 1., 1., 0.3];
+
+/// Around the text, in font pixels.
+const MARGIN: f32 = 3.;
+const LINE_HEIGHT: f32 = font::CELL_HEIGHT as f32 + 2.;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+pub struct HudQuad {
+    /// The top left and the bottom right corner in clip space.
+    rect: [f32; 4],
+    cell: u32,
+    color: [f32; 4],
+}
+
+impl HudQuad {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x4, 1 => Uint32, 2 => Float32x4];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// What the HUD shows of the frame, gathered by `Gpu`.
+pub struct HudStats {
+    /// Selected by the flood fill through the frustum.
+    pub drawn_chunks: usize,
+    /// The drawn ones which have faces facing the camera. With the indirect draws the ones left
+    /// after the culling, a few frames late.
+    pub visible_chunks: u64,
+    /// The chunks with a mesh on the GPU.
+    pub loaded_chunks: usize,
+    pub allocator: GPUSlotAllocatorStats,
+    pub render_scale: f32,
+}
+
+pub struct Hud {
+    pub visible: bool,
+    pub atlas_layout: wgpu::BindGroupLayout,
+    atlas_bind_group: wgpu::BindGroup,
+
+    quads: Vec<HudQuad>,
+    buffer: wgpu::Buffer,
+
+    /// In milliseconds, the latest one last.
+    frame_times: VecDeque<f64>,
+    last_frame: Option<Instant>,
+    last_pos: Option<Vec3>,
+    /// In m/s, smoothed over a few frames.
+    speed: f32,
+    /// The latest results of the occlusion culling, they arrive only every few frames.
+    pub culled: Option<CullStats>,
+    /// The meshes uploaded in the last update.
+    mesh_uploads: u32,
+    /// How many updates in a row ran out of time before all meshes were uploaded.
+    mesh_backlog: u32,
+    /// The error of the last shader reload, None once the shaders compile again.
+    pub shader_error: Option<String>,
+}
+
+impl Hud {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = wgpu::Extent3d {
+            width: font::CELL_COUNT * font::CELL_WIDTH,
+            height: font::CELL_HEIGHT,
+            depth_or_array_layers: 1,
+        };
+        let atlas = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Font Atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &atlas,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &font::atlas(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+
+        let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Font Atlas Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Font Atlas Bind Group"),
+            layout: &atlas_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &atlas.create_view(&wgpu::TextureViewDescriptor::default()),
+                ),
+            }],
+        });
+
+        Self {
+            visible: false,
+            atlas_layout,
+            atlas_bind_group,
+            quads: vec![],
+            buffer: Self::buffer(device, 256),
+            frame_times: VecDeque::with_capacity(GRAPH_FRAMES),
+            last_frame: None,
+            last_pos: None,
+            speed: 0.,
+            culled: None,
+            mesh_uploads: 0,
+            mesh_backlog: 0,
+            shader_error: None,
+        }
+    }
+
+    fn buffer(device: &wgpu::Device, quads: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HUD Buffer"),
+            size: (quads * std::mem::size_of::<HudQuad>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    /// Takes the time since the last frame and the camera position, also while hidden so the graph
+    /// is full when it is shown.
+    pub fn frame(&mut self, pos: Option<Vec3>) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            let seconds = (now - last_frame).as_secs_f64();
+            if self.frame_times.len() == GRAPH_FRAMES {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(seconds * 1_000.);
+
+            if let (Some(pos), Some(last_pos)) = (pos, self.last_pos)
+                && seconds > 0.
+            {
+                let speed = pos.distance(last_pos) / seconds as f32;
+                self.speed += (speed - self.speed) * 0.1;
+            }
+        }
+        self.last_pos = pos;
+    }
+
+    /// Takes the outcome of a mesh update, `done` is false if it ran out of time.
+    pub fn mesh_update(&mut self, uploads: u32, done: bool) {
+        self.mesh_uploads = uploads;
+        self.mesh_backlog = if done { 0 } else { self.mesh_backlog + 1 };
+    }
+
+    /// Whether there is anything to draw.
+    pub fn shown(&self) -> bool {
+        self.visible || self.shader_error.is_some()
+    }
+
+    /// Lays out and uploads the HUD of this frame.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface: &wgpu::SurfaceConfiguration,
+        stats: &HudStats,
+    ) {
+        self.quads.clear();
+        let lines = self.visible.then(|| self.lines(stats));
+
+        let mut layout = Layout {
+            quads: &mut self.quads,
+            // a font pixel grows with the window so the text stays readable
+            scale: (surface.height / 540).max(1) as f32,
+            size: [surface.width as f32, surface.height as f32],
+        };
+        let mut y = 0.;
+        if let Some(lines) = lines {
+            y = stats_box(&mut layout, &self.frame_times, &lines);
+        }
+        if let Some(error) = &self.shader_error {
+            let columns = (layout.size[0] / layout.scale - 2. * MARGIN) / font::CELL_WIDTH as f32;
+            let mut lines = vec!["SHADER ERROR, THE LAST WORKING SHADERS STAY IN USE".to_string()];
+            lines.extend(wrap(error, columns as usize));
+            let width = lines
+                .iter()
+                .map(|line| line.chars().count() as f32 * font::CELL_WIDTH as f32)
+                .fold(0., f32::max);
+            layout.rect(
+                [0., y],
+                [
+                    width + 2. * MARGIN,
+                    lines.len() as f32 * LINE_HEIGHT + 2. * MARGIN,
+                ],
+                font::SOLID,
+                ERROR_BACKGROUND_COLOR,
+            );
+            y += MARGIN;
+            for line in &lines {
+                layout.text([MARGIN, y], line);
+                y += LINE_HEIGHT;
+            }
+        }
+
+        let size = (self.quads.len() * std::mem::size_of::<HudQuad>()) as u64;
+        if size > self.buffer.size() {
+            self.buffer = Self::buffer(device, self.quads.len().next_power_of_two());
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.quads));
+    }
+
+    /// The text, the frame rate first.
+    fn lines(&self, stats: &HudStats) -> Vec<String> {
+        let frames = self.frame_times.len().max(1) as f64;
+        let average = self.frame_times.iter().sum::<f64>() / frames;
+        let max = self.frame_times.iter().copied().fold(0., f64::max);
+        let fps = if average > 0. { 1_000. / average } else { 0. };
+
+        let mut lines = vec![
+            format!("FPS {:.0}  {:.2} MS, MAX {:.2}", fps, average, max),
+            match self.last_pos {
+                Some(pos) => format!("POS {:+.1} {:+.1} {:+.1}", pos.x, pos.y, pos.z),
+                None => "POS -".to_string(),
+            },
+            format!("SPEED {:.1} M/S", self.speed),
+            format!(
+                "CHUNKS {} DRAWN, {} VISIBLE, {} LOADED",
+                stats.drawn_chunks, stats.visible_chunks, stats.loaded_chunks
+            ),
+        ];
+        if let Some(culled) = &self.culled {
+            lines.push(format!(
+                "CULLED {} FRUSTUM, {} OCCLUDED",
+                culled.outside_frustum, culled.occluded
+            ));
+        }
+        lines.push(format!(
+            "MESHES {} UPLOADED, BACKLOG {} FRAMES",
+            self.mesh_uploads, self.mesh_backlog
+        ));
+        let mb = |bytes: usize| bytes as f64 / (1024. * 1024.);
+        lines.push(format!(
+            "VRAM {:.1}/{:.1} MB, {} SLOTS, {} FREE, {} CLASSES",
+            mb(stats.allocator.payload_bytes),
+            mb(stats.allocator.reserved_bytes),
+            stats.allocator.allocated_slots,
+            stats.allocator.free_slots,
+            stats.allocator.size_classes,
+        ));
+        lines.push(format!("SCALE {:.2}", stats.render_scale));
+        lines
+    }
+
+    /// Draws the quads uploaded by `update`, the pipeline has to be set.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.quads.is_empty() {
+            return;
+        }
+        render_pass.set_bind_group(0, &self.atlas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.draw(0..6, 0..self.quads.len() as u32);
+    }
+}
+
+/// The frame rate with the graph below it and the other lines, returns the bottom of the box.
+fn stats_box(layout: &mut Layout, frame_times: &VecDeque<f64>, lines: &[String]) -> f32 {
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count() as f32 * font::CELL_WIDTH as f32)
+        .fold(GRAPH_FRAMES as f32, f32::max);
+    let height = lines.len() as f32 * LINE_HEIGHT + GRAPH_HEIGHT + 2.;
+    layout.rect(
+        [0., 0.],
+        [width + 2. * MARGIN, height + 2. * MARGIN],
+        font::SOLID,
+        BACKGROUND_COLOR,
+    );
+
+    let mut y = MARGIN;
+    layout.text([MARGIN, y], &lines[0]);
+    y += LINE_HEIGHT;
+    let bottom = y + GRAPH_HEIGHT;
+    layout.rect(
+        [
+            MARGIN,
+            bottom - (GRAPH_GOOD / GRAPH_MAX) as f32 * GRAPH_HEIGHT,
+        ],
+        [GRAPH_FRAMES as f32, 1.],
+        font::SOLID,
+        GUIDE_COLOR,
+    );
+    for (x, &ms) in frame_times.iter().enumerate() {
+        let bar = (ms / GRAPH_MAX).min(1.) as f32 * GRAPH_HEIGHT;
+        let color = if ms < GRAPH_GOOD {
+            GOOD_COLOR
+        } else if ms < GRAPH_MAX {
+            SLOW_COLOR
+        } else {
+            TOO_SLOW_COLOR
+        };
+        layout.rect(
+            [MARGIN + x as f32, bottom - bar],
+            [1., bar],
+            font::SOLID,
+            color,
+        );
+    }
+    y = bottom + 2.;
+    for line in &lines[1..] {
+        layout.text([MARGIN, y], line);
+        y += LINE_HEIGHT;
+    }
+    height + 2. * MARGIN
+}
+
+/// Splits the text at its line breaks and wherever a line is longer than `columns`.
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let columns = columns.max(1);
+    text.lines()
+        .flat_map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                return vec![String::new()];
+            }
+            chars
+                .chunks(columns)
+                .map(|chunk| chunk.iter().collect())
+                .collect()
+        })
+        .collect()
+}
+
+/// Places quads in font pixels, from the top left corner of the window.
+struct Layout<'a> {
+    quads: &'a mut Vec<HudQuad>,
+    /// Screen pixels per font pixel.
+    scale: f32,
+    /// Of the window in screen pixels.
+    size: [f32; 2],
+}
+
+impl Layout<'_> {
+    fn rect(&mut self, pos: [f32; 2], size: [f32; 2], cell: u32, color: [f32; 4]) {
+        let clip_x = |x: f32| x * self.scale / self.size[0] * 2. - 1.;
+        let clip_y = |y: f32| 1. - y * self.scale / self.size[1] * 2.;
+        self.quads.push(HudQuad {
+            rect: [
+                clip_x(pos[0]),
+                clip_y(pos[1]),
+                clip_x(pos[0] + size[0]),
+                clip_y(pos[1] + size[1]),
+            ],
+            cell,
+            color,
+        });
+    }
+
+    fn text(&mut self, pos: [f32; 2], text: &str) {
+        let cell_size = [font::CELL_WIDTH as f32, font::CELL_HEIGHT as f32];
+        for (column, c) in text.chars().enumerate() {
+            if let Some(cell) = font::cell(c) {
+                let x = pos[0] + column as f32 * cell_size[0];
+                self.rect([x, pos[1]], cell_size, cell, TEXT_COLOR);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_and_multi_line_errors_are_wrapped() {
+        assert_eq!(
+            wrap("shader.wgsl:3:1: abcdef\nsecond\n\nend", 8),
+            ["shader.w", "gsl:3:1:", " abcdef", "second", "", "end"]
+        );
+    }
+}
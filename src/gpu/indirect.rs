@@ -0,0 +1,613 @@
+//! GPU driven chunk rendering. Every mesh slot has an entry in the chunk table, a storage buffer
+//! with the position and the face offsets of the chunk in it. Each frame the chunks to draw are
+//! uploaded as a list of table indices, the culling compute shader tests them against the frustum
+//! and writes one indirect draw per chunk and orientation, the culled ones with zero instances.
+//! The draws are issued with one `multi_draw_indirect` per run of chunks in the same vertex buffer.
+//! Chunks behind the terrain of the last frame are culled as well, see `hi_z`.
+//! The shadow casters of every cascade follow in the same list and get a dispatch of their own,
+//! culled against the cascade instead of the camera.
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use bytemuck::Zeroable;
+use glam::Vec3;
+
+use crate::{
+    config::MAX_SHADOW_CASCADES,
+    gpu::{
+        ambient_occlusion::FaceInstance,
+        gpu_allocator::{GPUSlotAllocator, SlotID},
+        hi_z::DepthPyramid,
+        texture::Texture,
+    },
+};
+
+/// The adapter features the indirect draws need, without them every chunk is drawn by the CPU.
+pub const FEATURES: wgpu::Features =
+    wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+/// Has to match the workgroup size of `cull_chunks` in culling.wgsl.
+const WORKGROUP_SIZE: u32 = 64;
+
+pub fn supported(adapter: &wgpu::Adapter) -> bool {
+    adapter.features().contains(FEATURES)
+        && adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::VERTEX_STORAGE | wgpu::DownlevelFlags::COMPUTE_SHADERS)
+}
+
+/// Has to match `ChunkEntry` in chunk_table.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct ChunkEntry {
+    pos_lod: [u32; 4],
+    offsets: [u32; 6],
+    first_face: u32,
+    face_count: u32,
+}
+
+/// Has to match `CullParams` in culling.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct CullParams {
+    draw_count: u32,
+    vertices_per_face: u32,
+    opaque_count: u32,
+    first_draw: u32,
+    light_direction: [f32; 3],
+    shadow: u32,
+}
+
+/// Has to match `CullStats` in culling.wgsl, only the opaque draws are counted.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+pub struct CullStats {
+    pub outside_frustum: u32,
+    pub occluded: u32,
+    /// Left after the culling with faces towards the camera.
+    pub visible_chunks: u32,
+    pub visible_faces: u32,
+}
+
+/// The way of results of the GPU back to the CPU, a few frames late. Used for the culling stats
+/// and by `timestamps`.
+pub enum Readback {
+    /// The readback buffer can take the next results.
+    Idle,
+    /// The copy is recorded, the buffer can be mapped once it is submitted.
+    Copied,
+    /// Set when the buffer is mapped.
+    Mapping(Arc<AtomicBool>),
+}
+
+/// Has to match `IndirectMetadata` in projection.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct IndirectPushConstant {
+    table_base: u32,
+    faces_per_slot: u32,
+}
+
+/// Consecutive chunks in the same vertex buffer.
+struct DrawRun {
+    slot: SlotID,
+    metadata: IndirectPushConstant,
+    first: u32,
+    count: u32,
+}
+
+pub struct IndirectDraws {
+    /// A copy of the chunk table, to upload it again when it grows.
+    entries: Vec<ChunkEntry>,
+    /// The entry of the first slot of every vertex buffer, by size class and buffer.
+    table_bases: HashMap<(usize, usize), u32>,
+    table_buffer: wgpu::Buffer,
+
+    draw_list: Vec<u32>,
+    draw_list_buffer: wgpu::Buffer,
+    args_buffer: wgpu::Buffer,
+    /// The `CullParams` of every dispatch, one stride apart.
+    params_buffer: wgpu::Buffer,
+    params_stride: u64,
+    stats_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    readback: Readback,
+    max_draws: usize,
+    opaque: Vec<DrawRun>,
+    translucent: Vec<DrawRun>,
+    /// The casters of every shadow cascade.
+    shadows: Vec<Vec<DrawRun>>,
+    /// The draws of every dispatch, the camera first and then the cascades.
+    dispatches: Vec<Range<u32>>,
+
+    pub chunk_table_layout: wgpu::BindGroupLayout,
+    chunk_table_bind_group: wgpu::BindGroup,
+    pub culling_layout: wgpu::BindGroupLayout,
+    culling_bind_group: wgpu::BindGroup,
+    /// Fills the group between the shadow cascade and the chunk table in the shadow passes.
+    pub empty_layout: wgpu::BindGroupLayout,
+    pub empty_bind_group: wgpu::BindGroup,
+    /// Built from the depth buffer after the main pass, used by the culling of the next frame.
+    pub depth_pyramid: DepthPyramid,
+}
+
+impl IndirectDraws {
+    pub fn new(device: &wgpu::Device, max_chunks: usize, depth_texture: &Texture) -> Self {
+        // every chunk can be drawn once opaque, once translucent and once per shadow cascade
+        let max_draws = max_chunks * (2 + MAX_SHADOW_CASCADES as usize);
+        let table_buffer = Self::table_buffer(device, 1);
+        let draw_list_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Draw List Buffer"),
+            size: (max_draws.max(1) * std::mem::size_of::<u32>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let args_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Indirect Draw Buffer"),
+            size: (max_draws.max(1) * 6 * std::mem::size_of::<wgpu::util::DrawIndirectArgs>())
+                as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+        });
+        let params_stride = u64::from(device.limits().min_uniform_buffer_offset_alignment)
+            .max(std::mem::size_of::<CullParams>() as u64);
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Params Buffer"),
+            size: params_stride * (1 + MAX_SHADOW_CASCADES) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let stats_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Stats Buffer"),
+            size: std::mem::size_of::<CullStats>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Stats Readback Buffer"),
+            size: std::mem::size_of::<CullStats>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let storage = |binding, stages, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: stages,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let chunk_table_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Chunk Table Bind Group Layout"),
+                entries: &[storage(0, wgpu::ShaderStages::VERTEX, true)],
+            });
+        let culling_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Culling Bind Group Layout"),
+            entries: &[
+                storage(0, wgpu::ShaderStages::COMPUTE, true),
+                storage(1, wgpu::ShaderStages::COMPUTE, true),
+                storage(2, wgpu::ShaderStages::COMPUTE, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<CullParams>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                storage(4, wgpu::ShaderStages::COMPUTE, false),
+            ],
+        });
+        let empty_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Empty Bind Group Layout"),
+            entries: &[],
+        });
+        let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Empty Bind Group"),
+            layout: &empty_layout,
+            entries: &[],
+        });
+
+        let (chunk_table_bind_group, culling_bind_group) = Self::bind_groups(
+            device,
+            &chunk_table_layout,
+            &culling_layout,
+            &table_buffer,
+            &draw_list_buffer,
+            &args_buffer,
+            &params_buffer,
+            &stats_buffer,
+        );
+        Self {
+            entries: vec![],
+            table_bases: HashMap::new(),
+            table_buffer,
+            draw_list: Vec::with_capacity(max_draws),
+            draw_list_buffer,
+            args_buffer,
+            params_buffer,
+            params_stride,
+            stats_buffer,
+            readback_buffer,
+            readback: Readback::Idle,
+            max_draws,
+            opaque: vec![],
+            translucent: vec![],
+            shadows: vec![],
+            dispatches: vec![],
+            chunk_table_layout,
+            chunk_table_bind_group,
+            culling_layout,
+            culling_bind_group,
+            empty_layout,
+            empty_bind_group,
+            depth_pyramid: DepthPyramid::new(device, depth_texture),
+        }
+    }
+
+    fn table_buffer(device: &wgpu::Device, entries: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Table Buffer"),
+            size: (entries.max(1) * std::mem::size_of::<ChunkEntry>()) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn bind_groups(
+        device: &wgpu::Device,
+        chunk_table_layout: &wgpu::BindGroupLayout,
+        culling_layout: &wgpu::BindGroupLayout,
+        table_buffer: &wgpu::Buffer,
+        draw_list_buffer: &wgpu::Buffer,
+        args_buffer: &wgpu::Buffer,
+        params_buffer: &wgpu::Buffer,
+        stats_buffer: &wgpu::Buffer,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        (
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Chunk Table Bind Group"),
+                layout: chunk_table_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: table_buffer.as_entire_binding(),
+                }],
+            }),
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Culling Bind Group"),
+                layout: culling_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: table_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: draw_list_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: args_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: params_buffer,
+                            offset: 0,
+                            size: wgpu::BufferSize::new(std::mem::size_of::<CullParams>() as u64),
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: stats_buffer.as_entire_binding(),
+                    },
+                ],
+            }),
+        )
+    }
+
+    /// The chunk table entry of a slot. The first slot seen in a new vertex buffer reserves
+    /// entries for all slots of it.
+    fn table_index(&mut self, vram_cache: &GPUSlotAllocator, slot: SlotID) -> u32 {
+        let base = *self.table_bases.entry((slot.0, slot.1)).or_insert_with(|| {
+            let base = self.entries.len() as u32;
+            self.entries.extend(std::iter::repeat_n(
+                ChunkEntry::zeroed(),
+                vram_cache.slots_per_buffer(slot),
+            ));
+            base
+        });
+        base + slot.2 as u32
+    }
+
+    fn metadata(&mut self, vram_cache: &GPUSlotAllocator, slot: SlotID) -> IndirectPushConstant {
+        IndirectPushConstant {
+            table_base: self.table_index(vram_cache, (slot.0, slot.1, 0)),
+            faces_per_slot: (vram_cache.slot_size(slot) as u64 / FaceInstance::SIZE) as u32,
+        }
+    }
+
+    /// Stores where the mesh of `chunk` lies, has to be called whenever it is uploaded.
+    pub fn set_chunk(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vram_cache: &GPUSlotAllocator,
+        chunk: voxine::ChunkID,
+        (offsets, size, slot): ([u64; 6], u64, SlotID),
+    ) {
+        let index = self.table_index(vram_cache, slot) as usize;
+        let faces_per_slot = vram_cache.slot_size(slot) as u64 / FaceInstance::SIZE;
+        self.entries[index] = ChunkEntry {
+            pos_lod: chunk.bytes(),
+            offsets: offsets.map(|offset| (offset / FaceInstance::SIZE) as u32),
+            first_face: (slot.2 as u64 * faces_per_slot) as u32,
+            face_count: (size / FaceInstance::SIZE) as u32,
+        };
+
+        let table_size = (self.entries.len() * std::mem::size_of::<ChunkEntry>()) as u64;
+        if table_size > self.table_buffer.size() {
+            // a new vertex buffer was allocated
+            self.table_buffer = Self::table_buffer(device, self.entries.len().next_power_of_two());
+            (self.chunk_table_bind_group, self.culling_bind_group) = Self::bind_groups(
+                device,
+                &self.chunk_table_layout,
+                &self.culling_layout,
+                &self.table_buffer,
+                &self.draw_list_buffer,
+                &self.args_buffer,
+                &self.params_buffer,
+                &self.stats_buffer,
+            );
+            queue.write_buffer(&self.table_buffer, 0, bytemuck::cast_slice(&self.entries));
+        } else {
+            queue.write_buffer(
+                &self.table_buffer,
+                (index * std::mem::size_of::<ChunkEntry>()) as u64,
+                bytemuck::cast_slice(&self.entries[index..index + 1]),
+            );
+        }
+    }
+
+    /// Uploads the chunks to draw this frame. The opaque ones and the shadow casters are grouped
+    /// by vertex buffer, the translucent ones keep their order.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        vram_cache: &GPUSlotAllocator,
+        opaque: &[SlotID],
+        translucent: &[SlotID],
+        shadow_casters: &[Vec<SlotID>],
+        light_direction: Vec3,
+        vertices_per_face: u32,
+    ) {
+        self.draw_list.clear();
+        self.opaque.clear();
+        self.translucent.clear();
+        self.shadows.clear();
+        self.dispatches.clear();
+
+        let mut runs = std::mem::take(&mut self.opaque);
+        self.push_grouped_runs(vram_cache, opaque, &mut runs);
+        self.opaque = runs;
+        let opaque_count = self.draw_list.len() as u32;
+        let mut runs = std::mem::take(&mut self.translucent);
+        self.push_runs(vram_cache, translucent, &mut runs);
+        self.translucent = runs;
+        self.dispatches.push(0..self.draw_list.len() as u32);
+        for casters in shadow_casters {
+            let first = self.draw_list.len() as u32;
+            let mut runs = vec![];
+            self.push_grouped_runs(vram_cache, casters, &mut runs);
+            self.shadows.push(runs);
+            self.dispatches.push(first..self.draw_list.len() as u32);
+        }
+
+        queue.write_buffer(
+            &self.draw_list_buffer,
+            0,
+            bytemuck::cast_slice(&self.draw_list),
+        );
+        for (dispatch, draws) in self.dispatches.iter().enumerate() {
+            queue.write_buffer(
+                &self.params_buffer,
+                dispatch as u64 * self.params_stride,
+                bytemuck::cast_slice(&[CullParams {
+                    draw_count: draws.len() as u32,
+                    vertices_per_face,
+                    opaque_count,
+                    first_draw: draws.start,
+                    light_direction: light_direction.to_array(),
+                    shadow: u32::from(dispatch > 0),
+                }]),
+            );
+        }
+    }
+
+    /// Like `push_runs`, but sorted by vertex buffer first, so the slots fall into few runs.
+    fn push_grouped_runs(
+        &mut self,
+        vram_cache: &GPUSlotAllocator,
+        slots: &[SlotID],
+        runs: &mut Vec<DrawRun>,
+    ) {
+        let mut slots = slots.to_vec();
+        slots.sort_unstable_by_key(|slot| (slot.0, slot.1));
+        self.push_runs(vram_cache, &slots, runs);
+    }
+
+    fn push_runs(
+        &mut self,
+        vram_cache: &GPUSlotAllocator,
+        slots: &[SlotID],
+        runs: &mut Vec<DrawRun>,
+    ) {
+        for &slot in slots {
+            if self.draw_list.len() >= self.max_draws {
+                log::warn!("more chunks than max-chunks, some aren't drawn");
+                return;
+            }
+            let index = self.table_index(vram_cache, slot);
+            let draw = self.draw_list.len() as u32;
+            self.draw_list.push(index);
+            match runs.last_mut() {
+                Some(run) if (run.slot.0, run.slot.1) == (slot.0, slot.1) => run.count += 1,
+                _ => runs.push(DrawRun {
+                    slot,
+                    metadata: self.metadata(vram_cache, slot),
+                    first: draw,
+                    count: 1,
+                }),
+            }
+        }
+    }
+
+    /// Writes the indirect draws of the prepared chunks, culled against the camera and the
+    /// shadow casters against the camera bind group of their cascade.
+    pub fn cull(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::ComputePipeline,
+        camera_bind_group: &wgpu::BindGroup,
+        cascade_bind_groups: &[wgpu::BindGroup],
+    ) {
+        if self.draw_list.is_empty() {
+            return;
+        }
+        encoder.clear_buffer(&self.stats_buffer, 0, None);
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass - Chunk Culling"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(2, &self.depth_pyramid.cull_bind_group, &[]);
+            for (dispatch, draws) in self.dispatches.iter().enumerate() {
+                if draws.is_empty() {
+                    continue;
+                }
+                let camera = match dispatch.checked_sub(1) {
+                    Some(cascade) => &cascade_bind_groups[cascade],
+                    None => camera_bind_group,
+                };
+                pass.set_bind_group(
+                    0,
+                    &self.culling_bind_group,
+                    &[(dispatch as u64 * self.params_stride) as u32],
+                );
+                pass.set_bind_group(1, camera, &[]);
+                pass.dispatch_workgroups((draws.len() as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+            }
+        }
+        if let Readback::Idle = self.readback {
+            encoder.copy_buffer_to_buffer(
+                &self.stats_buffer,
+                0,
+                &self.readback_buffer,
+                0,
+                self.stats_buffer.size(),
+            );
+            self.readback = Readback::Copied;
+        }
+    }
+
+    /// Maps the culling stats copied this frame, has to be called after the submit.
+    pub fn after_submit(&mut self) {
+        if let Readback::Copied = self.readback {
+            let mapped = Arc::new(AtomicBool::new(false));
+            let done = mapped.clone();
+            self.readback_buffer.slice(..).map_async(
+                wgpu::MapMode::Read,
+                move |result| match result {
+                    Ok(()) => done.store(true, Ordering::Release),
+                    Err(err) => log::warn!("can't read the culling stats: {}", err),
+                },
+            );
+            self.readback = Readback::Mapping(mapped);
+        }
+    }
+
+    /// The culling stats of an earlier frame, if they arrived since the last call.
+    pub fn culled(&mut self, device: &wgpu::Device) -> Option<CullStats> {
+        let Readback::Mapping(mapped) = &self.readback else {
+            return None;
+        };
+        let _ = device.poll(wgpu::PollType::Poll);
+        if !mapped.load(Ordering::Acquire) {
+            return None;
+        }
+        let stats = *bytemuck::from_bytes(&self.readback_buffer.slice(..).get_mapped_range());
+        self.readback_buffer.unmap();
+        self.readback = Readback::Idle;
+        Some(stats)
+    }
+
+    /// Draws the prepared chunks, the pipeline and the groups 0 to 2 have to be set already.
+    pub fn draw(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        vram_cache: &GPUSlotAllocator,
+        translucent: bool,
+    ) {
+        let runs = if translucent {
+            &self.translucent
+        } else {
+            &self.opaque
+        };
+        self.draw_runs(render_pass, vram_cache, runs);
+    }
+
+    /// Draws the shadow casters of a cascade, like `draw`.
+    pub fn draw_shadow(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        vram_cache: &GPUSlotAllocator,
+        cascade: usize,
+    ) {
+        if let Some(runs) = self.shadows.get(cascade) {
+            self.draw_runs(render_pass, vram_cache, runs);
+        }
+    }
+
+    fn draw_runs(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        vram_cache: &GPUSlotAllocator,
+        runs: &[DrawRun],
+    ) {
+        render_pass.set_bind_group(3, &self.chunk_table_bind_group, &[]);
+        let draw_size = 6 * std::mem::size_of::<wgpu::util::DrawIndirectArgs>() as u64;
+        for run in runs {
+            render_pass.set_push_constants(
+                wgpu::ShaderStages::VERTEX,
+                0,
+                bytemuck::cast_slice(&[run.metadata]),
+            );
+            render_pass.set_vertex_buffer(0, vram_cache.buffer(run.slot).slice(..));
+            render_pass.multi_draw_indirect(
+                &self.args_buffer,
+                run.first as u64 * draw_size,
+                run.count * 6,
+            );
+        }
+    }
+}
@@ -1,28 +1,80 @@
-use std::{collections::HashMap, time::Instant};
+use std::{collections::HashMap, time::Duration};
 
-use glam::Vec3;
+use glam::{Mat4, Vec3};
 use texture::Texture;
+use web_time::Instant;
 use winit::{dpi::PhysicalSize, event_loop::EventLoopWindowTarget};
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gpu::shader::{ShaderError, ShaderWatcher};
 use crate::{
-    config::Config,
+    config::{
+        AntiAliasing, Config, DayCycleConfig, DisplayConfig, DynamicResolutionConfig,
+        LightingConfig, PostEffectConfig, PresentMode,
+    },
     gpu::{
-        gpu_allocator::GPUSlotAllocator,
+        ambient_occlusion::FaceInstance,
+        anti_aliasing::{Msaa, Taa},
+        block_table::BlockTable,
+        bloom::Bloom,
+        chunk_overlay::ChunkOverlay,
+        chunk_uniforms::ChunkUniforms,
+        debug_view::DebugView,
+        dynamic_resolution::DynamicResolution,
+        exposure::Exposure,
+        gpu_allocator::{GPUSlotAllocator, SlotID},
+        hud::{Hud, HudStats},
+        indirect::IndirectDraws,
+        pipelines::{ChunkUniformLayouts, IndirectLayouts, PipelineLayouts, Pipelines},
+        post_processing::{PostProcessing, PostTextures},
         profiling::PerformanceStats,
-        projection::{Projection, View},
+        projection::{CameraUniform, Projection, View},
+        resource_pack::ResourcePack,
+        shader::ShaderLoader,
+        shadows::ShadowMaps,
+        sky::Sky,
+        ssao::Ssao,
+        timestamps::{GpuPass, Timestamps},
+        ui_overlay::UiOverlay,
     },
 };
 
 // pub mod exotic_cameras;
+mod adapter;
+mod ambient_occlusion;
+mod anti_aliasing;
+mod block_table;
+mod bloom;
+mod chunk_overlay;
+mod chunk_uniforms;
+mod debug_view;
+mod dynamic_resolution;
+mod exposure;
+mod font;
 #[allow(dead_code)]
 mod gpu_allocator;
+mod hi_z;
+mod hud;
+mod indirect;
+mod pipelines;
+mod post_processing;
 mod profiling;
 pub mod projection;
+mod resource_pack;
 mod shader;
+mod shadows;
+mod sky;
+mod ssao;
 mod texture;
 pub mod texture_set;
+mod timestamps;
+mod ui_overlay;
 pub mod window;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use adapter::list as list_adapters;
+pub use ui_overlay::UiFrame;
+
 /// Ein Drawer. Der Drawer ist der Zugang zur Graphikkarte. Er ist an ein Fenster genüpft.
 pub struct Gpu<'a> {
     // bind groups:
@@ -34,21 +86,69 @@ pub struct Gpu<'a> {
     device: wgpu::Device,
     queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
+    /// What the surface supports, the configured present mode is checked against it.
+    present_modes: Vec<wgpu::PresentMode>,
+    /// The render targets below are smaller than the surface while it lowers the scale.
+    dynamic_resolution: DynamicResolution,
+    /// The GPU time of the main pass and the post-processing, of the latest measured frame.
+    gpu_frame_time: Option<Duration>,
 
     depth_texture: Texture,
-    depth_texture_bind_group: wgpu::BindGroup,
-    depth_texture_bind_group_layout: wgpu::BindGroupLayout,
 
     render_target_texture: Texture,
     render_target_bind_group: wgpu::BindGroup,
     render_target_bind_group_layout: wgpu::BindGroupLayout,
 
-    render_pipeline: wgpu::RenderPipeline,
-    post_processing_pipeline: wgpu::RenderPipeline,
+    /// The post-processing passes alternate between this and the render target.
+    post_target_texture: Texture,
+    post_target_bind_group: wgpu::BindGroup,
+    post_processing: PostProcessing,
+    exposure: Exposure,
+    bloom: Bloom,
+    ssao: Ssao,
+
+    /// What the config asks for, unless the adapter can't do it.
+    anti_aliasing: AntiAliasing,
+    msaa: Option<Msaa>,
+    taa: Option<Taa>,
+
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    pipelines: Pipelines,
+    /// Without translucent blocks the translucent pass is skipped.
+    translucent_blocks: bool,
+    /// None if the adapter lacks `indirect::FEATURES`.
+    indirect: Option<IndirectDraws>,
+    /// Whether the indirect draws are used, otherwise every chunk is drawn by the CPU.
+    draw_indirect: bool,
+    /// None if the adapter has push constants, which carry the chunk metadata then.
+    chunk_uniforms: Option<ChunkUniforms>,
+
+    // shader hot reload, there are no files to watch on the web
+    #[cfg(not(target_arch = "wasm32"))]
+    shader_loader: ShaderLoader,
+    #[cfg(not(target_arch = "wasm32"))]
+    shader_watcher: Option<ShaderWatcher>,
 
     // camera
     proj: Projection,
-    view_proj_buffer: wgpu::Buffer,
+    view: Option<View>,
+    camera_buffer: wgpu::Buffer,
+    /// The unjittered view projection of the last frame.
+    prev_view_proj: Option<Mat4>,
+    start_time: Instant,
+
+    shading: ShadingUniform,
+    shading_buffer: wgpu::Buffer,
+    debug_view: DebugView,
+    chunk_overlay: ChunkOverlay,
+    hud: Hud,
+    ui_overlay: UiOverlay,
+
+    // lighting
+    lighting: LightingConfig,
+    shadow_maps: ShadowMaps,
+    sky: Sky,
 
     // Asset things:
     vertices_per_face: u32,
@@ -56,7 +156,10 @@ pub struct Gpu<'a> {
     vram_cache: gpu_allocator::GPUSlotAllocator,
     mesh_map: HashMap<voxine::ChunkID, ([u64; 6], u64, gpu_allocator::SlotID)>,
     frustum_allocs: voxine::FrustumAllocations,
+    drawn_chunks: Vec<voxine::ChunkID>,
     perf_stats: PerformanceStats,
+    /// None if the adapter lacks `timestamps::FEATURES`.
+    timestamps: Option<Timestamps>,
 }
 
 #[repr(C)]
@@ -66,43 +169,65 @@ struct ChunkPushConstant {
     orientation: u32,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct ShadingUniform {
+    ambient_occlusion: u32,
+    debug_view: u32,
+    _padding: [u32; 2],
+}
+
 impl<'a> Gpu<'a> {
     /// Diese Funktion erstellt einen Drawer der mit dem aktuellen Fenster verbunden ist.
-    /// Außerdem nimmt sie einen PresentMode entgegen mit dem auf das Fenster gezeichnet werden soll.
-    pub async fn connect_to(
-        window: &'a winit::window::Window,
-        present_mode: wgpu::PresentMode,
-        config: &Config,
-    ) -> Gpu<'a> {
+    /// Der PresentMode und die Latenz kommen aus `config.display`.
+    pub async fn connect_to(window: &'a winit::window::Window, config: &Config) -> Gpu<'a> {
         let PhysicalSize { width, height } = window.inner_size();
 
         // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            #[cfg(not(target_arch = "wasm32"))]
-            backends: wgpu::Backends::PRIMARY,
-            #[cfg(target_arch = "wasm32")]
-            backends: wgpu::Backends::GL,
-            ..Default::default()
-        });
+        let instance = adapter::instance(config.backend);
 
         let surface = instance.create_surface(window).unwrap();
-        let required_features = wgpu::Features::PUSH_CONSTANTS;
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
 
+        let adapter = adapter::select(&instance, &surface, config.adapter.as_deref()).await;
+        log::info!("adapter: {:?}", adapter.get_info());
+
+        let push_constants = adapter.features().contains(wgpu::Features::PUSH_CONSTANTS);
+        if !push_constants {
+            log::info!("the adapter has no push constants, chunks are drawn with uniforms");
+        }
+        // the indirect draws take their metadata from push constants
+        let indirect_draws = push_constants && indirect::supported(&adapter);
+        if !indirect_draws {
+            log::info!("the adapter can't draw indirectly, chunks are drawn one by one");
+        }
+        let required_features = match (push_constants, indirect_draws) {
+            (true, true) => wgpu::Features::PUSH_CONSTANTS | indirect::FEATURES,
+            (true, false) => wgpu::Features::PUSH_CONSTANTS,
+            (false, _) => wgpu::Features::empty(),
+        };
+        // the wireframe debug view is skipped without it
+        let required_features =
+            required_features | (adapter.features() & debug_view::WIREFRAME_FEATURES);
+        // only the CPU timings are reported without it
+        let required_features = required_features | (adapter.features() & timestamps::FEATURES);
+
+        // the defaults ask for more than GL adapters have, the shadow maps and large windows
+        // need the texture size of the adapter
+        let limits = if adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+            wgpu::Limits::downlevel_defaults()
+        } else {
+            wgpu::Limits::downlevel_webgl2_defaults()
+        };
         let device_descriptor = wgpu::DeviceDescriptor {
             required_features,
             required_limits: wgpu::Limits {
-                max_push_constant_size: 20, // chunk metadata = 4 * u32
-                ..Default::default()
+                // chunk metadata = 5 * u32
+                max_push_constant_size: if push_constants { 20 } else { 0 },
+                ..limits.using_resolution(adapter.limits())
             },
             ..Default::default()
         };
@@ -124,12 +249,25 @@ impl<'a> Gpu<'a> {
                 .unwrap_or(surface_caps.formats[0]),
             width: size.width,
             height: size.height,
-            present_mode, // surface_caps.present_modes[0] will select it at runtime
+            present_mode: supported_present_mode(
+                &surface_caps.present_modes,
+                config.display.present_mode,
+            ),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
-            desired_maximum_frame_latency: 2,
+            desired_maximum_frame_latency: config.display.frame_latency,
         };
-        let (device, queue) = future.await.unwrap();
+        let anti_aliasing = match config.anti_aliasing.sample_count() {
+            1 => config.anti_aliasing,
+            samples if anti_aliasing::msaa_supported(&adapter, samples) => config.anti_aliasing,
+            samples => {
+                log::warn!("the adapter can't do {}x MSAA, it is disabled", samples);
+                AntiAliasing::None
+            }
+        };
+        let (device, queue) = future
+            .await
+            .unwrap_or_else(|err| panic!("can't open the adapter: {}", err));
         if surface_config.width > 0 && surface_config.height > 0 {
             surface.configure(&device, &surface_config);
         }
@@ -155,31 +293,75 @@ impl<'a> Gpu<'a> {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
                 label: Some("texture_bind_group_layout"),
             });
 
         let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Camera Buffer"),
-            size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+            size: std::mem::size_of::<CameraUniform>() as u64,
             mapped_at_creation: false,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let shading = ShadingUniform {
+            ambient_occlusion: config.ambient_occlusion as u32,
+            debug_view: DebugView::Off as u32,
+            _padding: [0; 2],
+        };
+        let shading_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shading Buffer"),
+            size: std::mem::size_of::<ShadingUniform>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        queue.write_buffer(&shading_buffer, 0, bytemuck::cast_slice(&[shading]));
+
         let camera_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        // the compute stage is the chunk culling
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT
+                            | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                ],
                 label: Some("Camera Bind Group Layout"),
             });
+        let shadow_maps = ShadowMaps::new(
+            &device,
+            &config.shadows,
+            &camera_bind_group_layout,
+            &shading_buffer,
+        );
+        let sky = Sky::new(&device, &config.day_cycle);
         let render_target_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Texture Bind Group Layout"),
@@ -202,14 +384,9 @@ impl<'a> Gpu<'a> {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), // Normaler Sampler
                         count: None,
                     },
-                ],
-            });
-        let depth_texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Depth Bind Group Layout"),
-                entries: &[
+                    // Binding 2 und 3: Die Tiefe der Szene
                     wgpu::BindGroupLayoutEntry {
-                        binding: 0,
+                        binding: 2,
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Texture {
                             sample_type: wgpu::TextureSampleType::Depth,
@@ -219,31 +396,101 @@ impl<'a> Gpu<'a> {
                         count: None,
                     },
                     wgpu::BindGroupLayoutEntry {
-                        binding: 1,
+                        binding: 3,
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
                 ],
             });
-        let depth_texture = Texture::create_depth_texture(&device, &surface_config);
-        let depth_texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Depth Bind Group"),
-            layout: &depth_texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&depth_texture.sampler),
-                },
-            ],
+        let dynamic_resolution = DynamicResolution::new(&config.dynamic_resolution);
+        let target_config = dynamic_resolution.scaled(&surface_config);
+        let depth_texture = Texture::create_depth_texture(&device, &target_config);
+        let shader_loader =
+            ShaderLoader::new(config.shader_dir.clone(), &config.textures, push_constants);
+        let shaders = shader_loader
+            .compile(&device)
+            .unwrap_or_else(|err| panic!("shader error: {}", err));
+
+        let render_target = Texture::create_rendering_target(&device, &target_config);
+        let post_target = Texture::create_rendering_target(&device, &target_config);
+        let exposure = Exposure::new(&device);
+        let bloom = Bloom::new(&device, &target_config);
+        let ssao = Ssao::new(&device, &target_config);
+        let post_processing = PostProcessing::new(
+            &device,
+            &queue,
+            &config.post_processing,
+            anti_aliasing == AntiAliasing::Fxaa,
+            PostTextures {
+                exposure: &exposure.view,
+                bloom: &bloom.view,
+                ssao: &ssao.view,
+            },
+        );
+        if let Some(threshold) = post_processing.bloom() {
+            bloom.set_threshold(&queue, threshold);
+        }
+        if let Some((radius, samples)) = post_processing.ssao() {
+            ssao.set_settings(&queue, radius, samples);
+        }
+        let msaa = (anti_aliasing.sample_count() > 1)
+            .then(|| Msaa::new(&device, &target_config, anti_aliasing.sample_count()));
+        let taa = (anti_aliasing == AntiAliasing::Taa).then(|| {
+            Taa::new(
+                &device,
+                &target_config,
+                &render_target_bind_group_layout,
+                &depth_texture,
+            )
         });
-        let shader = device.create_shader_module(crate::gpu::shader::make_shader());
+        let indirect =
+            indirect_draws.then(|| IndirectDraws::new(&device, config.max_chunks, &depth_texture));
+        let chunk_uniforms = (!push_constants).then(|| ChunkUniforms::new(&device));
+        let hud = Hud::new(&device, &queue);
+        let ui_overlay = UiOverlay::new(&device, surface_config.format);
+        let pipelines = Pipelines::new(
+            &device,
+            &shaders,
+            &PipelineLayouts {
+                texture: &texture_bind_group_layout,
+                camera: &camera_bind_group_layout,
+                shadow: &shadow_maps.bind_group_layout,
+                sky: &sky.bind_group_layout,
+                render_target: &render_target_bind_group_layout,
+                post_processing: &post_processing.bind_group_layout,
+                bloom_level: &bloom.level_layout,
+                bloom_settings: &bloom.settings_layout,
+                ssao_settings: &ssao.settings_layout,
+                ssao_raw: &ssao.raw_layout,
+                font_atlas: &hud.atlas_layout,
+                msaa_depth: msaa.as_ref().map(|msaa| &msaa.depth_layout),
+                chunk_uniforms: chunk_uniforms.as_ref().map(|uniforms| ChunkUniformLayouts {
+                    metadata: &uniforms.layout,
+                    empty: &uniforms.empty_layout,
+                }),
+                indirect: indirect.as_ref().map(|indirect| IndirectLayouts {
+                    chunk_table: &indirect.chunk_table_layout,
+                    culling: &indirect.culling_layout,
+                    depth_pyramid: &indirect.depth_pyramid.cull_layout,
+                    depth_pyramid_copy: &indirect.depth_pyramid.copy_layout,
+                    depth_pyramid_downsample: &indirect.depth_pyramid.downsample_layout,
+                    empty: &indirect.empty_layout,
+                }),
+            },
+            surface_config.format,
+            anti_aliasing,
+            device.features().contains(debug_view::WIREFRAME_FEATURES),
+        );
 
-        let render_target = Texture::create_rendering_target(&device, &surface_config);
+        let chunk_overlay = ChunkOverlay::new(&device);
+        let timestamps = device
+            .features()
+            .contains(timestamps::FEATURES)
+            .then(|| Timestamps::new(&device, &queue));
+        let resource_pack =
+            ResourcePack::load_or_embedded(config.resource_pack.as_deref(), &device.limits());
+        log::info!("block textures: {}", resource_pack.texture_names.join(", "));
 
         Self {
             proj: Projection::new(width, height, config.fov, config.near_plane),
@@ -251,21 +498,29 @@ impl<'a> Gpu<'a> {
             mesh_map: HashMap::with_capacity(10_000),
             vram_cache: GPUSlotAllocator::new(32 * 32 * 4, 100_000),
             frustum_allocs: voxine::FrustumAllocations::default(config.max_chunks),
+            drawn_chunks: Vec::with_capacity(config.max_chunks),
 
+            translucent_blocks: resource_pack.blocks.has_translucent(),
+            indirect,
+            draw_indirect: indirect_draws,
+            chunk_uniforms,
             diffuse_bind_group: {
                 let texture = Texture::from_images(
                     &device,
                     &queue,
-                    &[
-                        image::load_from_memory(texture_set::Texture::CrackedStone.bytes())
-                            .unwrap(),
-                        image::load_from_memory(texture_set::Texture::Stone.bytes()).unwrap(),
-                        image::load_from_memory(texture_set::Texture::Dirt0.bytes()).unwrap(),
-                        image::load_from_memory(texture_set::Texture::Dirt1.bytes()).unwrap(),
-                        image::load_from_memory(texture_set::Texture::Debug.bytes()).unwrap(),
-                    ],
-                    Some("Stone Texture"),
-                );
+                    &resource_pack.images,
+                    &config.textures,
+                    Some("Block Textures"),
+                )
+                .expect("resource packs are validated when loading");
+                let block_table_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Block Table Buffer"),
+                    size: BlockTable::size(),
+                    mapped_at_creation: false,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+                queue.write_buffer(&block_table_buffer, 0, resource_pack.blocks.as_bytes());
+
                 device.create_bind_group(&wgpu::BindGroupDescriptor {
                     layout: &texture_bind_group_layout,
                     entries: &[
@@ -277,161 +532,85 @@ impl<'a> Gpu<'a> {
                             binding: 1,
                             resource: wgpu::BindingResource::Sampler(&texture.sampler),
                         },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: block_table_buffer.as_entire_binding(),
+                        },
                     ],
                     label: Some("diffuse_bind_group"),
                 })
             },
             camera_bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
                 layout: &camera_bind_group_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: camera_buffer.as_entire_binding(),
-                }],
-                label: Some("camera_bind_group"),
-            }),
-            render_target_bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &render_target_bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&render_target.view),
+                        resource: camera_buffer.as_entire_binding(),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&render_target.sampler),
+                        resource: shading_buffer.as_entire_binding(),
                     },
                 ],
-                label: Some("render target bind group"),
+                label: Some("camera_bind_group"),
             }),
+            render_target_bind_group: render_target_bind_group(
+                &device,
+                &render_target_bind_group_layout,
+                &render_target,
+                &depth_texture,
+            ),
             render_target_texture: render_target,
+            post_target_bind_group: render_target_bind_group(
+                &device,
+                &render_target_bind_group_layout,
+                &post_target,
+                &depth_texture,
+            ),
+            post_target_texture: post_target,
             surface,
             queue,
             depth_texture,
-            depth_texture_bind_group,
-            render_pipeline: device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
-                layout: Some(
-                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                        label: Some("Render Pipeline Layout"),
-                        bind_group_layouts: &[
-                            &texture_bind_group_layout,
-                            &camera_bind_group_layout,
-                        ],
-                        push_constant_ranges: &[wgpu::PushConstantRange {
-                            stages: wgpu::ShaderStages::VERTEX,
-                            range: 0..20,
-                        }],
-                    }),
-                ),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: Some("vs_main"),
-                    buffers: &[voxine::Instance::desc()],
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: Some("fs_main"),
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent {
-                                src_factor: wgpu::BlendFactor::SrcAlpha,
-                                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                operation: wgpu::BlendOperation::Add,
-                            },
-                            alpha: wgpu::BlendComponent {
-                                src_factor: wgpu::BlendFactor::One,
-                                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                operation: wgpu::BlendOperation::Add,
-                            },
-                        }),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleStrip,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Cw,
-                    cull_mode: None, // Some(wgpu::Face::Back), // DEBUG: Culling komplett deaktiviert
-                    // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    // Requires Features::DEPTH_CLIP_CONTROL
-                    unclipped_depth: false,
-                    // Requires Features::CONSERVATIVE_RASTERIZATION
-                    conservative: false,
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: texture::Texture::DEPTH_FORMAT,
-                    depth_write_enabled: true, // Wichtig für Transparenz
-                    depth_compare: wgpu::CompareFunction::Greater,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
-                cache: None,
-            }),
-            post_processing_pipeline: device.create_render_pipeline(
-                &wgpu::RenderPipelineDescriptor {
-                    label: Some("Post Processing Pipeline"),
-                    layout: Some(
-                        &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                            label: Some("Pipeline Layout"),
-                            bind_group_layouts: &[
-                                &render_target_bind_group_layout,
-                                &depth_texture_bind_group_layout,
-                            ], // Bind Group für die Textur
-                            push_constant_ranges: &[],
-                        }),
-                    ),
-                    vertex: wgpu::VertexState {
-                        module: &shader,
-                        entry_point: Some("full_screen_quat"),
-                        buffers: &[], // Keine Vertex-Daten, weil wir ein Fullscreen-Quad generieren
-                        compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &shader,
-                        entry_point: Some("post_processing"),
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: surface_config.format, // Das Renderziel (z. B. Swapchain-Format)
-                            blend: Some(wgpu::BlendState::REPLACE), // Einfaches Overwriting
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                        compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList, // Wir rendern ein Quad aus 2 Dreiecken
-                        strip_index_format: None,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: Some(wgpu::Face::Back),
-                        polygon_mode: wgpu::PolygonMode::Fill,
-                        unclipped_depth: false,
-                        conservative: false,
-                    },
-                    depth_stencil: None, // Keine Depth-Tests nötig, weil wir nur das Bild verarbeiten
-                    multisample: wgpu::MultisampleState {
-                        count: 1,
-                        mask: !0,
-                        alpha_to_coverage_enabled: false,
-                    },
-                    multiview: None,
-                    cache: None,
-                },
-            ),
-            depth_texture_bind_group_layout,
             render_target_bind_group_layout,
+            texture_bind_group_layout,
+            camera_bind_group_layout,
+            pipelines,
+            #[cfg(not(target_arch = "wasm32"))]
+            shader_watcher: shader_loader.dir().and_then(|dir| {
+                ShaderWatcher::new(dir)
+                    .map_err(|err| log::warn!("shader hot reload is disabled: {}", err))
+                    .ok()
+            }),
+            #[cfg(not(target_arch = "wasm32"))]
+            shader_loader,
             device,
             config: surface_config,
-            view_proj_buffer: camera_buffer,
+            present_modes: surface_caps.present_modes,
+            dynamic_resolution,
+            gpu_frame_time: None,
+            camera_buffer,
+            start_time: Instant::now(),
+            view: None,
+            shading,
+            shading_buffer,
+            debug_view: DebugView::Off,
+            chunk_overlay,
+            hud,
+            ui_overlay,
+            lighting: config.lighting.clone(),
+            shadow_maps,
+            sky,
+            post_processing,
+            exposure,
+            bloom,
+            ssao,
+            anti_aliasing,
+            msaa,
+            taa,
+            prev_view_proj: None,
             vertices_per_face: 4,
             perf_stats: PerformanceStats::new(),
+            timestamps,
         }
     }
     /// Eine Methode welche die Fenstergröße anpasst.
@@ -443,58 +622,228 @@ impl<'a> Gpu<'a> {
             self.config.height = height;
 
             self.proj.resize(width, height);
-
-            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config);
-            self.depth_texture_bind_group =
-                self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("Depth Bind Group"),
-                    layout: &self.depth_texture_bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&self.depth_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&self.depth_texture.sampler),
-                        },
-                    ],
-                });
-            self.render_target_texture =
-                texture::Texture::create_rendering_target(&self.device, &self.config);
-            self.render_target_bind_group =
-                self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: &self.render_target_bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(
-                                &self.render_target_texture.view,
-                            ),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(
-                                &self.render_target_texture.sampler,
-                            ),
-                        },
-                    ],
-                    label: Some("render target bind group"),
-                });
+            self.resize_targets();
             self.surface.configure(&self.device, &self.config);
         }
     }
 
+    /// Recreates everything with the size of the render targets, the surface scaled by the
+    /// dynamic resolution.
+    fn resize_targets(&mut self) {
+        let config = self.dynamic_resolution.scaled(&self.config);
+        self.depth_texture = texture::Texture::create_depth_texture(&self.device, &config);
+        self.render_target_texture =
+            texture::Texture::create_rendering_target(&self.device, &config);
+        self.render_target_bind_group = render_target_bind_group(
+            &self.device,
+            &self.render_target_bind_group_layout,
+            &self.render_target_texture,
+            &self.depth_texture,
+        );
+        self.post_target_texture = texture::Texture::create_rendering_target(&self.device, &config);
+        self.post_target_bind_group = render_target_bind_group(
+            &self.device,
+            &self.render_target_bind_group_layout,
+            &self.post_target_texture,
+            &self.depth_texture,
+        );
+        if let Some(indirect) = &mut self.indirect {
+            indirect
+                .depth_pyramid
+                .resize(&self.device, &self.depth_texture);
+        }
+        self.bloom.resize(&self.device, &config);
+        self.ssao.resize(&self.device, &config);
+        self.post_processing.set_textures(
+            &self.device,
+            PostTextures {
+                exposure: &self.exposure.view,
+                bloom: &self.bloom.view,
+                ssao: &self.ssao.view,
+            },
+        );
+        if let Some(msaa) = &mut self.msaa {
+            msaa.resize(&self.device, &config);
+        }
+        if let Some(taa) = &mut self.taa {
+            taa.resize(
+                &self.device,
+                &config,
+                &self.render_target_bind_group_layout,
+                &self.depth_texture,
+            );
+        }
+    }
+
     pub fn reconfigure(&mut self) {
         self.surface.configure(&self.device, &self.config);
     }
 
+    /// Rebuilds the pipelines if a shader file changed and returns whether that worked.
+    /// On an error the last working pipelines stay in use.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn reload_shaders(&mut self) -> Option<Result<(), ShaderError>> {
+        if !self.shader_watcher.as_ref()?.changed() {
+            return None;
+        }
+        // naga accepts things wgpu doesn't, e.g. bindings not matching the layouts
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shaders = match self.shader_loader.compile(&self.device) {
+            Ok(shaders) => shaders,
+            Err(err) => {
+                pollster::block_on(self.device.pop_error_scope());
+                return Some(Err(err));
+            }
+        };
+        let pipelines = Pipelines::new(
+            &self.device,
+            &shaders,
+            &PipelineLayouts {
+                texture: &self.texture_bind_group_layout,
+                camera: &self.camera_bind_group_layout,
+                shadow: &self.shadow_maps.bind_group_layout,
+                sky: &self.sky.bind_group_layout,
+                render_target: &self.render_target_bind_group_layout,
+                post_processing: &self.post_processing.bind_group_layout,
+                bloom_level: &self.bloom.level_layout,
+                bloom_settings: &self.bloom.settings_layout,
+                ssao_settings: &self.ssao.settings_layout,
+                ssao_raw: &self.ssao.raw_layout,
+                font_atlas: &self.hud.atlas_layout,
+                msaa_depth: self.msaa.as_ref().map(|msaa| &msaa.depth_layout),
+                chunk_uniforms: self
+                    .chunk_uniforms
+                    .as_ref()
+                    .map(|uniforms| ChunkUniformLayouts {
+                        metadata: &uniforms.layout,
+                        empty: &uniforms.empty_layout,
+                    }),
+                indirect: self.indirect.as_ref().map(|indirect| IndirectLayouts {
+                    chunk_table: &indirect.chunk_table_layout,
+                    culling: &indirect.culling_layout,
+                    depth_pyramid: &indirect.depth_pyramid.cull_layout,
+                    depth_pyramid_copy: &indirect.depth_pyramid.copy_layout,
+                    depth_pyramid_downsample: &indirect.depth_pyramid.downsample_layout,
+                    empty: &indirect.empty_layout,
+                }),
+            },
+            self.config.format,
+            self.anti_aliasing,
+            self.device
+                .features()
+                .contains(debug_view::WIREFRAME_FEATURES),
+        );
+        if let Some(err) = pollster::block_on(self.device.pop_error_scope()) {
+            return Some(Err(ShaderError {
+                path: None,
+                line: 0,
+                column: 0,
+                msg: err.to_string(),
+            }));
+        }
+        self.pipelines = pipelines;
+        Some(Ok(()))
+    }
+
+    /// Shows the error of the last shader reload on screen, None clears it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_shader_error(&mut self, err: Option<&ShaderError>) {
+        self.hud.shader_error = err.map(ShaderError::to_string);
+    }
+
     /// Eine Funktion um den Status Quo zu verändern.
     pub fn update_view(&mut self, view: View) {
+        self.view = Some(view);
+    }
+
+    pub fn set_lighting(&mut self, lighting: LightingConfig, day_cycle: DayCycleConfig) {
+        self.lighting = lighting;
+        self.sky.set_config(day_cycle);
+    }
+
+    pub fn set_post_processing(&mut self, effects: &[PostEffectConfig]) {
+        self.post_processing.set_effects(&self.queue, effects);
+        if let Some(threshold) = self.post_processing.bloom() {
+            self.bloom.set_threshold(&self.queue, threshold);
+        }
+        if let Some((radius, samples)) = self.post_processing.ssao() {
+            self.ssao.set_settings(&self.queue, radius, samples);
+        }
+    }
+
+    /// Reconfigures the surface if the present mode or the frame latency changed.
+    pub fn set_display(&mut self, display: &DisplayConfig) {
+        let present_mode = supported_present_mode(&self.present_modes, display.present_mode);
+        if present_mode != self.config.present_mode
+            || display.frame_latency != self.config.desired_maximum_frame_latency
+        {
+            self.config.present_mode = present_mode;
+            self.config.desired_maximum_frame_latency = display.frame_latency;
+            self.reconfigure();
+        }
+    }
+
+    pub fn set_dynamic_resolution(&mut self, config: &DynamicResolutionConfig) {
+        if self.dynamic_resolution.set_config(config) {
+            self.resize_targets();
+        }
+    }
+
+    /// Switches between ambient occlusion and flat shading.
+    pub fn toggle_ambient_occlusion(&mut self) {
+        self.shading.ambient_occlusion ^= 1;
+        self.queue.write_buffer(
+            &self.shading_buffer,
+            0,
+            bytemuck::cast_slice(&[self.shading]),
+        );
+    }
+
+    /// Switches to the next debug view, see `debug_view`.
+    pub fn cycle_debug_view(&mut self) {
+        self.debug_view = self
+            .debug_view
+            .next(self.pipelines.chunks.wireframe.is_some());
+        log::info!("debug view: {:?}", self.debug_view);
+        self.shading.debug_view = self.debug_view as u32;
         self.queue.write_buffer(
-            &self.view_proj_buffer,
+            &self.shading_buffer,
             0,
-            bytemuck::cast_slice(&self.proj.calc_matrix(view)),
+            bytemuck::cast_slice(&[self.shading]),
+        );
+    }
+
+    /// Shows or hides the bounding boxes of the drawn chunks.
+    pub fn toggle_chunk_bounds(&mut self) {
+        self.chunk_overlay.chunk_bounds = !self.chunk_overlay.chunk_bounds;
+    }
+
+    /// Shows or hides the debug screen.
+    pub fn toggle_hud(&mut self) {
+        self.hud.visible = !self.hud.visible;
+    }
+
+    /// The debug UI to draw over the next frame.
+    pub fn set_ui(&mut self, frame: UiFrame) {
+        self.ui_overlay.set_frame(frame);
+    }
+
+    /// Whether the outline of the frustum passed to `draw` is drawn,
+    /// for when it is detached from the camera.
+    pub fn show_frustum(&mut self, show: bool) {
+        self.chunk_overlay.frustum = show;
+    }
+
+    /// Switches between the indirect draws and drawing every chunk from the CPU, if both are available.
+    pub fn toggle_indirect_draws(&mut self) {
+        self.draw_indirect = !self.draw_indirect && self.indirect.is_some();
+        log::info!(
+            "chunks are drawn {}",
+            if self.draw_indirect {
+                "indirectly"
+            } else {
+                "one by one"
+            }
         );
     }
 
@@ -504,38 +853,46 @@ impl<'a> Gpu<'a> {
         allowed_time: f64,
     ) {
         let now = Instant::now();
+        let mut uploads = 0;
         while let Ok((chunk_id, mesh)) = mesh_recv.pop() {
             let upload_start = Instant::now();
-            let mesh_len = mesh.len();
-            if let Some((offsets, slot_size, slot_id)) = self.mesh_map.get_mut(&chunk_id) {
+            let (faces, face_offsets) = ambient_occlusion::extend_mesh(&mesh.view(), mesh.offsets);
+            let mesh_len = faces.len() as u64;
+            let mesh = if let Some((offsets, slot_size, slot_id)) = self.mesh_map.get_mut(&chunk_id)
+            {
                 let updated_slot =
                     self.vram_cache
-                        .write_slot(&self.device, &self.queue, *slot_id, &mesh.view());
+                        .write_slot(&self.device, &self.queue, *slot_id, &faces);
                 *slot_id = updated_slot;
                 *slot_size = mesh_len;
-                *offsets = mesh.offsets
+                *offsets = face_offsets;
+                (face_offsets, mesh_len, updated_slot)
             } else {
                 let allocated_slot = self
                     .vram_cache
                     .allocate_slot(&self.device, (mesh_len as usize) << 2);
-                let updated_slot = self.vram_cache.write_slot(
-                    &self.device,
-                    &self.queue,
-                    allocated_slot,
-                    &mesh.view(),
-                );
+                let updated_slot =
+                    self.vram_cache
+                        .write_slot(&self.device, &self.queue, allocated_slot, &faces);
 
                 self.mesh_map
-                    .insert(chunk_id, (mesh.offsets, mesh_len, updated_slot));
+                    .insert(chunk_id, (face_offsets, mesh_len, updated_slot));
+                (face_offsets, mesh_len, updated_slot)
+            };
+            if let Some(indirect) = &mut self.indirect {
+                indirect.set_chunk(&self.device, &self.queue, &self.vram_cache, chunk_id, mesh);
             }
             self.perf_stats.mesh_updates += 1;
             self.perf_stats.uploaded_bytes += mesh_len;
             self.perf_stats.mesh_update_time.add(upload_start.elapsed());
             self.perf_stats.maybe_report();
+            uploads += 1;
             if now.elapsed().as_secs_f64() >= allowed_time {
+                self.hud.mesh_update(uploads, false);
                 return;
             }
         }
+        self.hud.mesh_update(uploads, true);
     }
 
     /// Eine Funktion die den Drawer einen neuen Frame zeichnen lässt.
@@ -561,10 +918,12 @@ impl<'a> Gpu<'a> {
     }
 
     fn try_draw(&mut self, frustum: voxine::Frustum) -> Result<(), wgpu::SurfaceError> {
+        self.hud.frame(self.view.map(|view| view.pos()));
         let draw_start = Instant::now();
         let acquire_start = Instant::now();
         let output = self.surface.get_current_texture()?;
-        self.perf_stats.acquire_time.add(acquire_start.elapsed());
+        let acquire_time = acquire_start.elapsed();
+        self.perf_stats.acquire_time.add(acquire_time);
         let output_view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -575,16 +934,197 @@ impl<'a> Gpu<'a> {
                 label: Some("Render Encoder"),
             });
 
-        // Erster Render-Pass: Szene auf Render-Target zeichnen
-        let main_pass_start = Instant::now();
+        if let Some(stats) = self
+            .indirect
+            .as_mut()
+            .and_then(|indirect| indirect.culled(&self.device))
+        {
+            self.perf_stats.cull_samples += 1;
+            self.perf_stats.outside_frustum_chunks += u64::from(stats.outside_frustum);
+            self.perf_stats.occluded_chunks += u64::from(stats.occluded);
+            self.hud.culled = Some(stats);
+        }
+        if let Some(timings) = self
+            .timestamps
+            .as_mut()
+            .and_then(|timestamps| timestamps.timings(&self.device))
+        {
+            self.perf_stats.add_gpu_timings(&timings);
+            self.gpu_frame_time = Some(Duration::from_secs_f64(
+                (timings.main_pass + timings.post_processing) / 1_000.,
+            ));
+        }
+
+        // the frustum may be detached from the camera
+        let cam_pos = self.view.map_or(frustum.cam_pos, |view| view.pos() / 32.);
+        let frustum_outline = self
+            .chunk_overlay
+            .frustum
+            .then(|| chunk_overlay::frustum_outline(&frustum));
+        self.drawn_chunks.clear();
+        self.drawn_chunks
+            .extend(frustum.flood_fill(&mut self.frustum_allocs, &self.mesh_map));
+        self.chunk_overlay.update(
+            &self.device,
+            &self.queue,
+            &self.drawn_chunks,
+            frustum_outline,
+        );
+
+        if let Some(view) = self.view {
+            let resolution = [
+                self.render_target_texture.texture.width(),
+                self.render_target_texture.texture.height(),
+            ];
+            if let Some(taa) = &mut self.taa {
+                self.proj.jitter = taa.next_jitter(resolution);
+            }
+            let view_proj = self.proj.unjittered_matrix(view);
+            let camera = CameraUniform::new(
+                &self.proj,
+                view,
+                self.prev_view_proj.unwrap_or(view_proj),
+                resolution,
+                self.start_time.elapsed().as_secs_f32(),
+            );
+            self.prev_view_proj = Some(view_proj);
+            self.queue
+                .write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera]));
+        }
+
+        if let Some(uniforms) = &mut self.chunk_uniforms {
+            uniforms.clear();
+        }
+
+        let light = self.sky.update(&self.queue, &self.lighting);
+        self.shadow_maps
+            .update(&self.queue, self.view, &self.proj, &light);
+        // empty without a view
+        let casters = self
+            .shadow_maps
+            .casters(&self.drawn_chunks, self.mesh_map.keys().copied());
+
+        // translucent faces are drawn last, from the furthest chunk to the closest
+        let translucent_chunks = if self.translucent_blocks {
+            let mut chunks = self.drawn_chunks.clone();
+            back_to_front(&mut chunks, cam_pos);
+            chunks
+        } else {
+            vec![]
+        };
+
         let mut visible_chunks = 0_u64;
         let mut visible_faces = 0_u64;
+        let mut indirect = match (&mut self.indirect, &self.pipelines.indirect) {
+            (Some(draws), Some(pipelines)) if self.draw_indirect => Some((draws, pipelines)),
+            _ => None,
+        };
+        if let Some((draws, pipelines)) = &mut indirect {
+            // the culling decides which of them are visible
+            let meshes = |chunks: &[voxine::ChunkID]| -> Vec<SlotID> {
+                chunks
+                    .iter()
+                    .filter_map(|chunk| self.mesh_map.get(chunk))
+                    .filter(|(_, size, _)| *size != 0)
+                    .map(|(_, _, slot)| *slot)
+                    .collect()
+            };
+            let opaque = meshes(&self.drawn_chunks);
+            let translucent = meshes(&translucent_chunks);
+            let shadow_casters: Vec<_> = casters.iter().map(|chunks| meshes(chunks)).collect();
+            // the results of the culling arrive a few frames late
+            if let Some(culled) = &self.hud.culled {
+                visible_chunks = u64::from(culled.visible_chunks);
+                visible_faces = u64::from(culled.visible_faces);
+            }
+            draws.prepare(
+                &self.queue,
+                &self.vram_cache,
+                &opaque,
+                &translucent,
+                &shadow_casters,
+                light.direction,
+                self.vertices_per_face,
+            );
+            draws.cull(
+                &mut encoder,
+                &pipelines.culling,
+                &self.camera_bind_group,
+                &self.shadow_maps.cascade_bind_groups,
+            );
+        }
+
+        // Schatten-Passes: die Tiefe aus Sicht der Sonne, ein Pass pro Kaskade
+        let shadow_pass_start = Instant::now();
+        let lit_axes = FACE_NORMALS.map(|normal| normal.dot(light.direction) > 0.);
+        for (cascade, ((layer_view, cascade_bind_group), cascade_casters)) in self
+            .shadow_maps
+            .layer_views
+            .iter()
+            .zip(&self.shadow_maps.cascade_bind_groups)
+            .zip(&casters)
+            .enumerate()
+        {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass - Shadow Cascade"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: layer_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            shadow_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+            shadow_pass.set_bind_group(1, cascade_bind_group, &[]);
+
+            if let Some((draws, pipelines)) = &indirect {
+                shadow_pass.set_pipeline(&pipelines.shadow);
+                shadow_pass.set_bind_group(2, &draws.empty_bind_group, &[]);
+                draws.draw_shadow(&mut shadow_pass, &self.vram_cache, cascade);
+            } else {
+                shadow_pass.set_pipeline(&self.pipelines.shadow);
+                if let Some(uniforms) = &self.chunk_uniforms {
+                    shadow_pass.set_bind_group(2, &uniforms.empty_bind_group, &[]);
+                }
+                for chunk in cascade_casters {
+                    let Some(mesh) = self.mesh_map.get(chunk).cloned() else {
+                        continue;
+                    };
+                    for axis in (0..6).filter(|axis| lit_axes[*axis]) {
+                        draw_chunk_axis(
+                            &mut shadow_pass,
+                            &self.vram_cache,
+                            *chunk,
+                            mesh,
+                            axis,
+                            self.vertices_per_face,
+                            self.chunk_uniforms.as_mut(),
+                        );
+                    }
+                }
+            }
+        }
+        self.perf_stats
+            .shadow_pass_time
+            .add(shadow_pass_start.elapsed());
+
+        // Erster Render-Pass: Szene auf Render-Target zeichnen
+        let main_pass_start = Instant::now();
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass - Main Scene"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &self.render_target_texture.view, // Render auf Textur
-                    resolve_target: None,
+                    // mit MSAA wird in die Render-Textur aufgelöst
+                    view: match &self.msaa {
+                        Some(msaa) => &msaa.color,
+                        None => &self.render_target_texture.view, // Render auf Textur
+                    },
+                    resolve_target: self.msaa.as_ref().map(|_| &self.render_target_texture.view),
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
                             r: 0.0,
@@ -596,7 +1136,10 @@ impl<'a> Gpu<'a> {
                     },
                 })],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
+                    view: match &self.msaa {
+                        Some(msaa) => &msaa.depth,
+                        None => &self.depth_texture.view,
+                    },
                     depth_ops: Some(wgpu::Operations {
                         load: wgpu::LoadOp::Clear(0.0),
                         store: wgpu::StoreOp::Store,
@@ -604,57 +1147,104 @@ impl<'a> Gpu<'a> {
                     stencil_ops: None,
                 }),
                 occlusion_query_set: None,
-                timestamp_writes: None,
+                timestamp_writes: self
+                    .timestamps
+                    .as_ref()
+                    .and_then(|timestamps| timestamps.writes(GpuPass::Main, true, true)),
             });
-            render_pass.set_pipeline(&self.render_pipeline);
-
             render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.shadow_maps.bind_group, &[]);
 
-            let cam_pos = frustum.cam_pos;
-            let chunks = frustum.flood_fill(&mut self.frustum_allocs, &self.mesh_map);
-
-            for chunk in chunks {
-                let Some((offsets, size, slot_id)) = self.mesh_map.get(&chunk).cloned() else {
-                    continue;
-                };
-                if size == 0 {
-                    continue;
+            if let Some((draws, pipelines)) = &indirect {
+                render_pass.set_pipeline(pipelines.chunks.opaque(self.debug_view));
+                draws.draw(&mut render_pass, &self.vram_cache, false);
+            } else {
+                render_pass.set_pipeline(self.pipelines.chunks.opaque(self.debug_view));
+                for chunk in &self.drawn_chunks {
+                    let Some(mesh) = self.mesh_map.get(chunk).cloned() else {
+                        continue;
+                    };
+                    if mesh.1 == 0 {
+                        continue;
+                    }
+                    for_every_axis(
+                        cam_pos,
+                        chunk.total_pos().as_vec3(),
+                        (1 << chunk.lod) as f32,
+                        |axis| {
+                            let face_count = draw_chunk_axis(
+                                &mut render_pass,
+                                &self.vram_cache,
+                                *chunk,
+                                mesh,
+                                axis,
+                                self.vertices_per_face,
+                                self.chunk_uniforms.as_mut(),
+                            );
+                            if face_count > 0 {
+                                visible_chunks += 1;
+                                visible_faces += face_count;
+                            }
+                        },
+                    );
                 }
-                for_every_axis(
-                    cam_pos,
-                    chunk.total_pos().as_vec3(),
-                    (1 << chunk.lod) as f32,
-                    |axis| {
-                        let current_offset = offsets[axis];
-                        let next_offset = offsets.get(axis + 1).cloned().unwrap_or(size);
-                        if current_offset == next_offset {
-                            return; // size is zero
-                        }
+            }
 
-                        let chunk_bytes = ChunkPushConstant {
-                            coord: chunk.bytes(),
-                            orientation: axis as u32,
-                        }; // push constant
-                        render_pass.set_push_constants(
-                            wgpu::ShaderStages::VERTEX,
-                            0,
-                            bytemuck::cast_slice(&[chunk_bytes]),
-                        );
+            // the overdraw heatmap already has every face in it and no depth to draw the sky behind
+            if self.debug_view != DebugView::Overdraw {
+                render_pass.set_pipeline(&self.pipelines.sky);
+                render_pass.set_bind_group(2, &self.sky.bind_group, &[]);
+                render_pass.draw(0..6, 0..1);
+            }
 
-                        let (buffer, offset) = self.vram_cache.buffer_and_offset(slot_id);
-                        render_pass.set_vertex_buffer(
-                            0,
-                            buffer.slice(offset + current_offset..offset + next_offset),
+            if self.translucent_blocks && self.debug_view != DebugView::Overdraw {
+                render_pass.set_bind_group(2, &self.shadow_maps.bind_group, &[]);
+                if let Some((draws, pipelines)) = &indirect {
+                    render_pass.set_pipeline(&pipelines.chunks.translucent);
+                    draws.draw(&mut render_pass, &self.vram_cache, true);
+                } else {
+                    render_pass.set_pipeline(&self.pipelines.chunks.translucent);
+                    for chunk in &translucent_chunks {
+                        let Some(mesh) = self.mesh_map.get(chunk).cloned() else {
+                            continue;
+                        };
+                        if mesh.1 == 0 {
+                            continue;
+                        }
+                        for_every_axis(
+                            cam_pos,
+                            chunk.total_pos().as_vec3(),
+                            (1 << chunk.lod) as f32,
+                            |axis| {
+                                draw_chunk_axis(
+                                    &mut render_pass,
+                                    &self.vram_cache,
+                                    *chunk,
+                                    mesh,
+                                    axis,
+                                    self.vertices_per_face,
+                                    self.chunk_uniforms.as_mut(),
+                                );
+                            },
                         );
-
-                        let face_count = (next_offset - current_offset) >> 2;
-                        render_pass.draw(0..self.vertices_per_face, 0..face_count as u32);
-                        visible_chunks += 1;
-                        visible_faces += face_count;
-                    },
-                );
+                    }
+                }
             }
+
+            render_pass.set_pipeline(&self.pipelines.lines);
+            self.chunk_overlay.draw(&mut render_pass);
+        }
+        if let (Some(msaa), Some(pipeline)) = (&self.msaa, &self.pipelines.depth_resolve) {
+            msaa.resolve_depth(&mut encoder, pipeline, &self.depth_texture);
+        }
+        if let Some((draws, pipelines)) = &indirect {
+            // for the culling of the next frame
+            draws.depth_pyramid.build(
+                &mut encoder,
+                &pipelines.depth_pyramid_copy,
+                &pipelines.depth_pyramid_downsample,
+            );
         }
         self.perf_stats
             .main_pass_time
@@ -662,13 +1252,51 @@ impl<'a> Gpu<'a> {
         self.perf_stats.visible_chunks += visible_chunks;
         self.perf_stats.visible_faces += visible_faces;
 
-        // Zweiter Render-Pass: Post-Processing mit der ersten Textur als Input
+        // Post-Processing: ein Pass pro Effekt, abwechselnd zwischen den beiden Texturen,
+        // der letzte rendert auf den Bildschirm
         let post_process_start = Instant::now();
-        {
+        let scene = match (&mut self.taa, &self.pipelines.taa) {
+            (Some(taa), Some(pipeline)) => taa.resolve(
+                &mut encoder,
+                pipeline,
+                &self.render_target_bind_group,
+                &self.camera_bind_group,
+            ),
+            _ => &self.render_target_bind_group,
+        };
+        if self.post_processing.auto_exposure() {
+            self.exposure
+                .measure(&mut encoder, &self.pipelines.exposure, scene);
+        }
+        if self.post_processing.bloom().is_some() {
+            self.bloom
+                .render(&mut encoder, &self.pipelines.bloom, scene);
+        }
+        if self.post_processing.ssao().is_some() {
+            self.ssao.render(
+                &mut encoder,
+                &self.pipelines.ssao,
+                scene,
+                &self.camera_bind_group,
+            );
+        }
+        // every pass reads what the one before wrote
+        let sources = [&self.post_target_bind_group, &self.render_target_bind_group];
+        let targets = [
+            &self.post_target_texture.view,
+            &self.render_target_texture.view,
+        ];
+        let pass_count = self.post_processing.passes().count();
+        for (pass, offset) in self.post_processing.passes().enumerate() {
+            let (target, pipeline) = if pass + 1 == pass_count {
+                (&output_view, &self.pipelines.post_processing_output)
+            } else {
+                (targets[pass % 2], &self.pipelines.post_processing)
+            };
             let mut post_process_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass - Post Processing"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &output_view, // Jetzt auf den Bildschirm rendern
+                    view: target,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -682,33 +1310,211 @@ impl<'a> Gpu<'a> {
                 })],
                 depth_stencil_attachment: None, // Kein Depth-Buffer nötig
                 occlusion_query_set: None,
-                timestamp_writes: None,
+                timestamp_writes: self.timestamps.as_ref().and_then(|timestamps| {
+                    timestamps.writes(GpuPass::PostProcessing, pass == 0, pass + 1 == pass_count)
+                }),
             });
 
-            post_process_pass.set_pipeline(&self.post_processing_pipeline);
-            post_process_pass.set_bind_group(0, &self.render_target_bind_group, &[]); // Die Szene als Textur-Input
-            post_process_pass.set_bind_group(1, &self.depth_texture_bind_group, &[]);
+            post_process_pass.set_pipeline(pipeline);
+            let source = if pass == 0 {
+                scene
+            } else {
+                sources[(pass - 1) % 2]
+            };
+            post_process_pass.set_bind_group(0, source, &[]); // Das vorherige Bild als Textur-Input
+            post_process_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            post_process_pass.set_bind_group(2, &self.sky.bind_group, &[]);
+            post_process_pass.set_bind_group(3, &self.post_processing.bind_group, &[offset]);
             post_process_pass.draw(0..6, 0..1); // Fullscreen-Quad mit 6 Vertices
         }
         self.perf_stats
             .post_process_time
             .add(post_process_start.elapsed());
 
+        // the debug screen, over the finished frame
+        if self.hud.shown() {
+            self.hud.update(
+                &self.device,
+                &self.queue,
+                &self.config,
+                &HudStats {
+                    drawn_chunks: self.drawn_chunks.len(),
+                    visible_chunks,
+                    loaded_chunks: self.mesh_map.len(),
+                    allocator: self.vram_cache.stats(),
+                    render_scale: self.dynamic_resolution.scale(),
+                },
+            );
+            let mut hud_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass - HUD"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &output_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            hud_pass.set_pipeline(&self.pipelines.hud);
+            self.hud.draw(&mut hud_pass);
+        }
+        self.ui_overlay.draw(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &output_view,
+            [self.config.width, self.config.height],
+        );
+        if let Some(timestamps) = &mut self.timestamps {
+            timestamps.resolve(&mut encoder);
+        }
+
         // Sende die Commands an die GPU
         let submit_start = Instant::now();
+        if let Some(uniforms) = &self.chunk_uniforms {
+            uniforms.upload(&self.queue);
+        }
         self.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(indirect) = &mut self.indirect {
+            indirect.after_submit();
+        }
+        if let Some(timestamps) = &mut self.timestamps {
+            timestamps.after_submit();
+        }
         output.present(); // Ausgabe auf den Bildschirm
         self.perf_stats
             .submit_present_time
             .add(submit_start.elapsed());
         self.perf_stats.frames += 1;
         self.perf_stats.total_draw_time.add(draw_start.elapsed());
+        // on the GPU only the passes the scale affects count, measured a few frames late
+        let scale_changed = match self.gpu_frame_time {
+            Some(gpu_frame_time) => self
+                .dynamic_resolution
+                .update(gpu_frame_time, Duration::ZERO),
+            None => self
+                .dynamic_resolution
+                .update(draw_start.elapsed(), acquire_time),
+        };
+        if scale_changed {
+            self.resize_targets();
+        }
+        self.perf_stats.render_scale = self.dynamic_resolution.scale();
         self.perf_stats.maybe_report();
 
         Ok(())
     }
 }
 
+/// The normals of the mesh orientations.
+const FACE_NORMALS: [Vec3; 6] = [
+    Vec3::NEG_X,
+    Vec3::X,
+    Vec3::NEG_Y,
+    Vec3::Y,
+    Vec3::NEG_Z,
+    Vec3::Z,
+];
+
+/// `mode` if the surface supports it, otherwise vsync, which every surface can do.
+fn supported_present_mode(supported: &[wgpu::PresentMode], mode: PresentMode) -> wgpu::PresentMode {
+    let mode = wgpu::PresentMode::from(mode);
+    match mode {
+        // wgpu picks a supported mode for them
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => mode,
+        mode if supported.contains(&mode) => mode,
+        mode => {
+            log::warn!("the surface can't present with {:?}, vsync is used", mode);
+            wgpu::PresentMode::AutoVsync
+        }
+    }
+}
+
+/// Draws the faces of a chunk pointing along `axis` and returns how many there were.
+fn draw_chunk_axis(
+    render_pass: &mut wgpu::RenderPass,
+    vram_cache: &GPUSlotAllocator,
+    chunk: voxine::ChunkID,
+    (offsets, size, slot_id): ([u64; 6], u64, SlotID),
+    axis: usize,
+    vertices_per_face: u32,
+    chunk_uniforms: Option<&mut ChunkUniforms>,
+) -> u64 {
+    let current_offset = offsets[axis];
+    let next_offset = offsets.get(axis + 1).cloned().unwrap_or(size);
+    if current_offset == next_offset {
+        return 0; // size is zero
+    }
+
+    let chunk_bytes = ChunkPushConstant {
+        coord: chunk.bytes(),
+        orientation: axis as u32,
+    };
+    match chunk_uniforms {
+        Some(uniforms) => uniforms.set(render_pass, chunk_bytes),
+        None => render_pass.set_push_constants(
+            wgpu::ShaderStages::VERTEX,
+            0,
+            bytemuck::cast_slice(&[chunk_bytes]),
+        ),
+    }
+
+    let (buffer, offset) = vram_cache.buffer_and_offset(slot_id);
+    render_pass.set_vertex_buffer(
+        0,
+        buffer.slice(offset + current_offset..offset + next_offset),
+    );
+
+    let face_count = (next_offset - current_offset) / FaceInstance::SIZE;
+    render_pass.draw(0..vertices_per_face, 0..face_count as u32);
+    face_count
+}
+
+fn render_target_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    target: &Texture,
+    depth: &Texture,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&target.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&target.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&depth.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&depth.sampler),
+            },
+        ],
+        label: Some("render target bind group"),
+    })
+}
+
+/// Sorts chunks from the furthest to the closest, the order translucent faces are blended in.
+fn back_to_front(chunks: &mut [voxine::ChunkID], cam_pos: Vec3) {
+    chunks.sort_by(|a, b| {
+        let distance = |chunk: &voxine::ChunkID| {
+            let chunk_size = (1 << chunk.lod) as f32;
+            (chunk.total_pos().as_vec3() + chunk_size * 0.5).distance_squared(cam_pos)
+        };
+        distance(b).total_cmp(&distance(a))
+    });
+}
+
 fn for_every_axis(cam_pos: Vec3, chunk_pos: Vec3, chunk_size: f32, mut closure: impl FnMut(usize)) {
     if cam_pos.x <= chunk_pos.x + chunk_size {
         closure(0);
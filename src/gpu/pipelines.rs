@@ -0,0 +1,838 @@
+use crate::{
+    config::AntiAliasing,
+    gpu::{
+        ambient_occlusion::FaceInstance, chunk_overlay::LineVertex, debug_view::DebugView,
+        exposure::Exposure, hud::HudQuad, ssao::Ssao, texture,
+    },
+};
+
+/// The bind group layouts the pipelines are built against.
+pub(super) struct PipelineLayouts<'a> {
+    pub texture: &'a wgpu::BindGroupLayout,
+    pub camera: &'a wgpu::BindGroupLayout,
+    pub shadow: &'a wgpu::BindGroupLayout,
+    pub sky: &'a wgpu::BindGroupLayout,
+    pub render_target: &'a wgpu::BindGroupLayout,
+    pub post_processing: &'a wgpu::BindGroupLayout,
+    pub bloom_level: &'a wgpu::BindGroupLayout,
+    pub bloom_settings: &'a wgpu::BindGroupLayout,
+    pub ssao_settings: &'a wgpu::BindGroupLayout,
+    pub ssao_raw: &'a wgpu::BindGroupLayout,
+    pub font_atlas: &'a wgpu::BindGroupLayout,
+    /// The multisampled depth buffer, only with MSAA.
+    pub msaa_depth: Option<&'a wgpu::BindGroupLayout>,
+    /// None if the adapter can't draw indirectly.
+    pub indirect: Option<IndirectLayouts<'a>>,
+    /// None if the adapter has push constants, see `chunk_uniforms`.
+    pub chunk_uniforms: Option<ChunkUniformLayouts<'a>>,
+}
+
+pub(super) struct ChunkUniformLayouts<'a> {
+    pub metadata: &'a wgpu::BindGroupLayout,
+    /// For the unused group of the shadow passes.
+    pub empty: &'a wgpu::BindGroupLayout,
+}
+
+pub(super) struct IndirectLayouts<'a> {
+    pub chunk_table: &'a wgpu::BindGroupLayout,
+    pub culling: &'a wgpu::BindGroupLayout,
+    pub depth_pyramid: &'a wgpu::BindGroupLayout,
+    pub depth_pyramid_copy: &'a wgpu::BindGroupLayout,
+    pub depth_pyramid_downsample: &'a wgpu::BindGroupLayout,
+    /// For the unused group of the shadow passes.
+    pub empty: &'a wgpu::BindGroupLayout,
+}
+
+/// The compiled shader entry modules.
+pub(super) struct ShaderModules {
+    pub terrain: wgpu::ShaderModule,
+    pub sky: wgpu::ShaderModule,
+    pub post_processing: wgpu::ShaderModule,
+    pub culling: wgpu::ShaderModule,
+    pub hi_z: wgpu::ShaderModule,
+    pub taa: wgpu::ShaderModule,
+    pub depth_resolve: wgpu::ShaderModule,
+    pub lines: wgpu::ShaderModule,
+    pub exposure: wgpu::ShaderModule,
+    pub bloom: wgpu::ShaderModule,
+    pub ssao: wgpu::ShaderModule,
+    pub hud: wgpu::ShaderModule,
+}
+
+/// All pipelines created from the shaders, they are rebuilt together when one is reloaded.
+pub(super) struct Pipelines {
+    pub chunks: ChunkPipelines,
+    pub indirect: Option<IndirectPipelines>,
+    pub shadow: wgpu::RenderPipeline,
+    pub sky: wgpu::RenderPipeline,
+    /// Every pass of the post-processing stack but the last one.
+    pub post_processing: wgpu::RenderPipeline,
+    /// The last pass of the post-processing stack, which draws onto the surface.
+    pub post_processing_output: wgpu::RenderPipeline,
+    /// Measures the average luminance for the auto exposure.
+    pub exposure: wgpu::RenderPipeline,
+    pub bloom: BloomPipelines,
+    pub ssao: SsaoPipelines,
+    /// Only with TAA.
+    pub taa: Option<wgpu::RenderPipeline>,
+    /// Only with MSAA.
+    pub depth_resolve: Option<wgpu::RenderPipeline>,
+    /// The debug lines, see `chunk_overlay`.
+    pub lines: wgpu::RenderPipeline,
+    /// The debug screen, drawn onto the surface.
+    pub hud: wgpu::RenderPipeline,
+}
+
+/// Everything the chunks are drawn with, either from the CPU or indirectly.
+pub(super) struct ChunkPipelines {
+    pub render: wgpu::RenderPipeline,
+    /// Drawn after the opaque chunks and the sky.
+    pub translucent: wgpu::RenderPipeline,
+    /// Replaces `render` for the overdraw heatmap, see `debug_view`.
+    pub overdraw: wgpu::RenderPipeline,
+    /// Replaces `render` for the wireframe, None if the adapter can't draw lines.
+    pub wireframe: Option<wgpu::RenderPipeline>,
+}
+
+impl ChunkPipelines {
+    /// The pipeline of the opaque chunks in this debug view.
+    pub fn opaque(&self, debug_view: DebugView) -> &wgpu::RenderPipeline {
+        match (debug_view, &self.wireframe) {
+            (DebugView::Overdraw, _) => &self.overdraw,
+            (DebugView::Wireframe, Some(wireframe)) => wireframe,
+            _ => &self.render,
+        }
+    }
+}
+
+/// The pipelines building the bloom chain, see `bloom`.
+pub(super) struct BloomPipelines {
+    /// Reads the scene into the first level.
+    pub prefilter: wgpu::RenderPipeline,
+    pub downsample: wgpu::RenderPipeline,
+    /// Blends a level onto the one above.
+    pub upsample: wgpu::RenderPipeline,
+}
+
+/// The pipelines of the ambient occlusion, see `ssao`.
+pub(super) struct SsaoPipelines {
+    pub occlusion: wgpu::RenderPipeline,
+    pub blur: wgpu::RenderPipeline,
+}
+
+/// The chunk pipelines for the indirect draws, see `indirect`.
+pub(super) struct IndirectPipelines {
+    pub chunks: ChunkPipelines,
+    pub shadow: wgpu::RenderPipeline,
+    pub culling: wgpu::ComputePipeline,
+    pub depth_pyramid_copy: wgpu::ComputePipeline,
+    pub depth_pyramid_downsample: wgpu::ComputePipeline,
+}
+
+impl Pipelines {
+    pub fn new(
+        device: &wgpu::Device,
+        shaders: &ShaderModules,
+        layouts: &PipelineLayouts,
+        surface_format: wgpu::TextureFormat,
+        anti_aliasing: AntiAliasing,
+        wireframe: bool,
+    ) -> Self {
+        // everything before the last post-processing pass draws into the HDR targets
+        let format = texture::Texture::HDR_FORMAT;
+        let samples = anti_aliasing.sample_count();
+        let chunks = |chunk_table: Option<&wgpu::BindGroupLayout>| ChunkPipelines {
+            render: chunk_pipeline(
+                device,
+                shaders,
+                layouts,
+                format,
+                samples,
+                ChunkPass::Opaque,
+                chunk_table,
+            ),
+            translucent: chunk_pipeline(
+                device,
+                shaders,
+                layouts,
+                format,
+                samples,
+                ChunkPass::Translucent,
+                chunk_table,
+            ),
+            overdraw: chunk_pipeline(
+                device,
+                shaders,
+                layouts,
+                format,
+                samples,
+                ChunkPass::Overdraw,
+                chunk_table,
+            ),
+            wireframe: wireframe.then(|| {
+                chunk_pipeline(
+                    device,
+                    shaders,
+                    layouts,
+                    format,
+                    samples,
+                    ChunkPass::Wireframe,
+                    chunk_table,
+                )
+            }),
+        };
+        Self {
+            chunks: chunks(None),
+            indirect: layouts.indirect.as_ref().map(|indirect| IndirectPipelines {
+                chunks: chunks(Some(indirect.chunk_table)),
+                shadow: shadow_pipeline(device, shaders, layouts, Some(indirect.chunk_table)),
+                culling: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Culling Pipeline"),
+                    layout: Some(
+                        &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                            label: Some("Culling Pipeline Layout"),
+                            bind_group_layouts: &[
+                                indirect.culling,
+                                layouts.camera,
+                                indirect.depth_pyramid,
+                            ],
+                            push_constant_ranges: &[],
+                        }),
+                    ),
+                    module: &shaders.culling,
+                    entry_point: Some("cull_chunks"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    cache: None,
+                }),
+                depth_pyramid_copy: hi_z_pipeline(
+                    device,
+                    shaders,
+                    indirect.depth_pyramid_copy,
+                    "copy_depth",
+                ),
+                depth_pyramid_downsample: hi_z_pipeline(
+                    device,
+                    shaders,
+                    indirect.depth_pyramid_downsample,
+                    "downsample",
+                ),
+            }),
+            shadow: shadow_pipeline(device, shaders, layouts, None),
+            // der Himmel füllt alles, wo nach dem Main-Pass noch keine Tiefe steht
+            sky: device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Sky Pipeline"),
+                layout: Some(
+                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("Sky Pipeline Layout"),
+                        // groups 0 and 1 stay bound from the chunks
+                        bind_group_layouts: &[layouts.texture, layouts.camera, layouts.sky],
+                        push_constant_ranges: &[],
+                    }),
+                ),
+                vertex: wgpu::VertexState {
+                    module: &shaders.sky,
+                    entry_point: Some("full_screen_quat"),
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shaders.sky,
+                    entry_point: Some("sky_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                // the quad lies on the far plane (0 with reverse z), just like the cleared depth
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Equal,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: samples,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+                cache: None,
+            }),
+            post_processing: full_screen_pipeline(
+                device,
+                "Post Processing Pipeline",
+                &[
+                    layouts.render_target,
+                    layouts.camera,
+                    layouts.sky,
+                    layouts.post_processing,
+                ],
+                &shaders.post_processing,
+                "post_processing",
+                format,
+                wgpu::BlendState::REPLACE,
+            ),
+            post_processing_output: full_screen_pipeline(
+                device,
+                "Post Processing Output Pipeline",
+                &[
+                    layouts.render_target,
+                    layouts.camera,
+                    layouts.sky,
+                    layouts.post_processing,
+                ],
+                &shaders.post_processing,
+                "post_processing",
+                surface_format,
+                wgpu::BlendState::REPLACE,
+            ),
+            // blends the new measurement with the earlier ones by the blend constant
+            exposure: full_screen_pipeline(
+                device,
+                "Exposure Pipeline",
+                &[layouts.render_target],
+                &shaders.exposure,
+                "average_luminance",
+                Exposure::FORMAT,
+                wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::Constant,
+                        dst_factor: wgpu::BlendFactor::OneMinusConstant,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent::REPLACE,
+                },
+            ),
+            bloom: BloomPipelines {
+                prefilter: full_screen_pipeline(
+                    device,
+                    "Bloom Prefilter Pipeline",
+                    &[layouts.render_target, layouts.bloom_settings],
+                    &shaders.bloom,
+                    "bloom_prefilter",
+                    format,
+                    wgpu::BlendState::REPLACE,
+                ),
+                downsample: full_screen_pipeline(
+                    device,
+                    "Bloom Downsample Pipeline",
+                    &[layouts.bloom_level, layouts.bloom_settings],
+                    &shaders.bloom,
+                    "bloom_downsample",
+                    format,
+                    wgpu::BlendState::REPLACE,
+                ),
+                upsample: full_screen_pipeline(
+                    device,
+                    "Bloom Upsample Pipeline",
+                    &[layouts.bloom_level, layouts.bloom_settings],
+                    &shaders.bloom,
+                    "bloom_upsample",
+                    format,
+                    wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    },
+                ),
+            },
+            ssao: SsaoPipelines {
+                occlusion: full_screen_pipeline(
+                    device,
+                    "SSAO Pipeline",
+                    &[layouts.render_target, layouts.camera, layouts.ssao_settings],
+                    &shaders.ssao,
+                    "ssao",
+                    Ssao::FORMAT,
+                    wgpu::BlendState::REPLACE,
+                ),
+                blur: full_screen_pipeline(
+                    device,
+                    "SSAO Blur Pipeline",
+                    &[layouts.render_target, layouts.camera, layouts.ssao_raw],
+                    &shaders.ssao,
+                    "ssao_blur",
+                    Ssao::FORMAT,
+                    wgpu::BlendState::REPLACE,
+                ),
+            },
+            taa: (anti_aliasing == AntiAliasing::Taa).then(|| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("TAA Pipeline"),
+                    layout: Some(
+                        &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                            label: Some("TAA Pipeline Layout"),
+                            // the scene, the camera and the history
+                            bind_group_layouts: &[
+                                layouts.render_target,
+                                layouts.camera,
+                                layouts.render_target,
+                            ],
+                            push_constant_ranges: &[],
+                        }),
+                    ),
+                    vertex: wgpu::VertexState {
+                        module: &shaders.taa,
+                        entry_point: Some("full_screen_quat"),
+                        buffers: &[],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shaders.taa,
+                        entry_point: Some("taa_resolve"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        cull_mode: Some(wgpu::Face::Back),
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                    cache: None,
+                })
+            }),
+            depth_resolve: layouts.msaa_depth.map(|msaa_depth| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Depth Resolve Pipeline"),
+                    layout: Some(
+                        &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                            label: Some("Depth Resolve Pipeline Layout"),
+                            bind_group_layouts: &[msaa_depth],
+                            push_constant_ranges: &[],
+                        }),
+                    ),
+                    vertex: wgpu::VertexState {
+                        module: &shaders.depth_resolve,
+                        entry_point: Some("full_screen_quat"),
+                        buffers: &[],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shaders.depth_resolve,
+                        entry_point: Some("resolve_depth"),
+                        targets: &[],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        cull_mode: Some(wgpu::Face::Back),
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: texture::Texture::DEPTH_FORMAT,
+                        depth_write_enabled: true,
+                        depth_compare: wgpu::CompareFunction::Always,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                    cache: None,
+                })
+            }),
+            lines: device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Line Pipeline"),
+                layout: Some(
+                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("Line Pipeline Layout"),
+                        // the textures are unused, they keep the camera in group 1
+                        bind_group_layouts: &[layouts.texture, layouts.camera],
+                        push_constant_ranges: &[],
+                    }),
+                ),
+                vertex: wgpu::VertexState {
+                    module: &shaders.lines,
+                    entry_point: Some("vs_lines"),
+                    buffers: &[LineVertex::desc()],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shaders.lines,
+                    entry_point: Some("fs_lines"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                // hidden by the terrain in front of them, without hiding anything themselves
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::GreaterEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: samples,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+                cache: None,
+            }),
+            hud: device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("HUD Pipeline"),
+                layout: Some(
+                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("HUD Pipeline Layout"),
+                        bind_group_layouts: &[layouts.font_atlas],
+                        push_constant_ranges: &[],
+                    }),
+                ),
+                vertex: wgpu::VertexState {
+                    module: &shaders.hud,
+                    entry_point: Some("vs_hud"),
+                    buffers: &[HudQuad::desc()],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shaders.hud,
+                    entry_point: Some("fs_hud"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: surface_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            }),
+        }
+    }
+}
+
+/// What a chunk pipeline draws.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ChunkPass {
+    Opaque,
+    Translucent,
+    /// Every face, added up without a depth test.
+    Overdraw,
+    /// The edges of the opaque faces.
+    Wireframe,
+}
+
+impl ChunkPass {
+    /// The `FACE_FILTER` of projection.wgsl: all faces, the opaque ones or the translucent ones.
+    fn face_filter(self) -> f64 {
+        match self {
+            ChunkPass::Overdraw => 0.,
+            ChunkPass::Opaque | ChunkPass::Wireframe => 1.,
+            ChunkPass::Translucent => 2.,
+        }
+    }
+}
+
+/// The chunks, either the opaque faces, the translucent ones or one of the debug views.
+/// With a chunk table the chunks are drawn indirectly and read from it instead of push constants.
+fn chunk_pipeline(
+    device: &wgpu::Device,
+    shaders: &ShaderModules,
+    layouts: &PipelineLayouts,
+    format: wgpu::TextureFormat,
+    samples: u32,
+    pass: ChunkPass,
+    chunk_table: Option<&wgpu::BindGroupLayout>,
+) -> wgpu::RenderPipeline {
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    // the chunk table of the indirect draws or the chunk uniforms
+    let chunk_group = chunk_table.or(layouts
+        .chunk_uniforms
+        .as_ref()
+        .map(|uniforms| uniforms.metadata));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(match pass {
+            ChunkPass::Opaque => "Render Pipeline",
+            ChunkPass::Translucent => "Translucent Pipeline",
+            ChunkPass::Overdraw => "Overdraw Pipeline",
+            ChunkPass::Wireframe => "Wireframe Pipeline",
+        }),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &match chunk_group {
+                    Some(group) => vec![layouts.texture, layouts.camera, layouts.shadow, group],
+                    None => vec![layouts.texture, layouts.camera, layouts.shadow],
+                },
+                push_constant_ranges: chunk_push_constants(layouts),
+            }),
+        ),
+        vertex: wgpu::VertexState {
+            module: &shaders.terrain,
+            entry_point: Some(if chunk_table.is_some() {
+                "vs_indirect"
+            } else {
+                "vs_main"
+            }),
+            buffers: &[FaceInstance::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &[("FACE_FILTER", pass.face_filter())],
+                ..Default::default()
+            },
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shaders.terrain,
+            entry_point: Some(match pass {
+                ChunkPass::Opaque | ChunkPass::Wireframe => "fs_main",
+                ChunkPass::Translucent => "fs_translucent",
+                ChunkPass::Overdraw => "fs_overdraw",
+            }),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(match pass {
+                    ChunkPass::Translucent => wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    },
+                    ChunkPass::Overdraw => wgpu::BlendState {
+                        color: additive,
+                        alpha: additive,
+                    },
+                    ChunkPass::Opaque | ChunkPass::Wireframe => wgpu::BlendState::REPLACE,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Cw,
+            cull_mode: None, // Some(wgpu::Face::Back), // DEBUG: Culling komplett deaktiviert
+            // Line requires Features::POLYGON_MODE_LINE, see debug_view::WIREFRAME_FEATURES
+            polygon_mode: if pass == ChunkPass::Wireframe {
+                wgpu::PolygonMode::Line
+            } else {
+                wgpu::PolygonMode::Fill
+            },
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            // translucent faces must not hide each other, they are sorted instead
+            depth_write_enabled: matches!(pass, ChunkPass::Opaque | ChunkPass::Wireframe),
+            depth_compare: if pass == ChunkPass::Overdraw {
+                wgpu::CompareFunction::Always
+            } else {
+                wgpu::CompareFunction::Greater
+            },
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: samples,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Draws the depth of the chunks into a shadow cascade.
+/// With a chunk table the chunks are drawn indirectly, like in `chunk_pipeline`.
+fn shadow_pipeline(
+    device: &wgpu::Device,
+    shaders: &ShaderModules,
+    layouts: &PipelineLayouts,
+    chunk_table: Option<&wgpu::BindGroupLayout>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Shadow Pipeline"),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow Pipeline Layout"),
+                bind_group_layouts: &match (chunk_table, &layouts.indirect, &layouts.chunk_uniforms)
+                {
+                    (Some(chunk_table), Some(indirect), _) => {
+                        vec![layouts.texture, layouts.camera, indirect.empty, chunk_table]
+                    }
+                    (None, _, Some(uniforms)) => vec![
+                        layouts.texture,
+                        layouts.camera,
+                        uniforms.empty,
+                        uniforms.metadata,
+                    ],
+                    _ => vec![layouts.texture, layouts.camera],
+                },
+                push_constant_ranges: chunk_push_constants(layouts),
+            }),
+        ),
+        vertex: wgpu::VertexState {
+            module: &shaders.terrain,
+            entry_point: Some(if chunk_table.is_some() {
+                "vs_indirect"
+            } else {
+                "vs_main"
+            }),
+            buffers: &[FaceInstance::desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None, // nur Tiefe
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Cw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            // against shadow acne
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// The `ChunkPushConstant`, unless the chunk uniforms replace it.
+fn chunk_push_constants(layouts: &PipelineLayouts) -> &'static [wgpu::PushConstantRange] {
+    const RANGES: &[wgpu::PushConstantRange] = &[wgpu::PushConstantRange {
+        stages: wgpu::ShaderStages::VERTEX,
+        range: 0..20,
+    }];
+    if layouts.chunk_uniforms.is_some() {
+        &[]
+    } else {
+        RANGES
+    }
+}
+
+/// A pass drawing one full screen quad without depth, like the post-processing.
+fn full_screen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    blend: wgpu::BlendState,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            }),
+        ),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: Some("full_screen_quat"),
+            buffers: &[],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: Some(entry_point),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// One step of building the depth pyramid, see `hi_z`.
+fn hi_z_pipeline(
+    device: &wgpu::Device,
+    shaders: &ShaderModules,
+    layout: &wgpu::BindGroupLayout,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Depth Pyramid Pipeline"),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Depth Pyramid Pipeline Layout"),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            }),
+        ),
+        module: &shaders.hi_z,
+        entry_point: Some(entry_point),
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        cache: None,
+    })
+}
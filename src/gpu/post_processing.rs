@@ -0,0 +1,310 @@
+//! The post-processing stack. Every enabled effect is one full screen pass, reading the output of
+//! the previous one. The parameters of all passes live in one uniform buffer which is bound with a
+//! different dynamic offset per pass. FXAA is not part of the configured stack, with
+//! `anti-aliasing = "fxaa"` it runs before the other passes.
+//! The tone mapping, the bloom and the SSAO also read textures built before the stack, by
+//! `exposure`, `bloom` and `ssao`.
+use crate::config::{MAX_POST_EFFECTS, PostEffect, PostEffectConfig};
+
+/// The distance between two passes in the uniform buffer, the largest offset alignment wgpu allows.
+const PASS_STRIDE: u64 = 256;
+
+/// Has to match the `POST_*` constants in post_processing.wgsl.
+const POST_COPY: u32 = 0;
+const POST_FXAA: u32 = 1;
+const POST_VIGNETTE: u32 = 2;
+const POST_FOG: u32 = 3;
+const POST_TONEMAP: u32 = 4;
+const POST_COLOR_GRADING: u32 = 5;
+const POST_BLOOM: u32 = 6;
+const POST_SSAO: u32 = 7;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct PostEffectUniform {
+    kind: u32,
+    _padding: [u32; 3],
+    params: [[f32; 4]; 2],
+}
+
+impl PostEffectUniform {
+    fn new(kind: u32, params: [[f32; 4]; 2]) -> Self {
+        Self {
+            kind,
+            _padding: [0; 3],
+            params,
+        }
+    }
+}
+
+impl From<&PostEffect> for PostEffectUniform {
+    fn from(value: &PostEffect) -> Self {
+        match *value {
+            PostEffect::Vignette { strength, radius } => {
+                Self::new(POST_VIGNETTE, [[strength, radius, 0., 0.], [0.; 4]])
+            }
+            PostEffect::Fog {
+                start,
+                end,
+                density,
+                max,
+            } => Self::new(POST_FOG, [[start, end, density, max], [0.; 4]]),
+            PostEffect::Tonemap {
+                exposure,
+                operator,
+                auto_exposure,
+            } => Self::new(
+                POST_TONEMAP,
+                [
+                    [
+                        exposure,
+                        operator as u32 as f32,
+                        auto_exposure as u32 as f32,
+                        0.,
+                    ],
+                    [0.; 4],
+                ],
+            ),
+            PostEffect::Bloom {
+                threshold,
+                intensity,
+            } => Self::new(POST_BLOOM, [[threshold, intensity, 0., 0.], [0.; 4]]),
+            PostEffect::Ssao { intensity, .. } => {
+                Self::new(POST_SSAO, [[intensity, 0., 0., 0.], [0.; 4]])
+            }
+            PostEffect::ColorGrading {
+                contrast,
+                saturation,
+                brightness,
+                tint: [r, g, b],
+            } => Self::new(
+                POST_COLOR_GRADING,
+                [[contrast, saturation, brightness, 0.], [r, g, b, 0.]],
+            ),
+        }
+    }
+}
+
+/// The textures the effects read besides the scene.
+pub struct PostTextures<'a> {
+    pub exposure: &'a wgpu::TextureView,
+    pub bloom: &'a wgpu::TextureView,
+    pub ssao: &'a wgpu::TextureView,
+}
+
+pub struct PostProcessing {
+    buffer: wgpu::Buffer,
+    pass_count: usize,
+    fxaa: bool,
+    /// Whether an enabled tone mapping needs the average luminance.
+    auto_exposure: bool,
+    /// The threshold of the enabled bloom.
+    bloom: Option<f32>,
+    /// The radius and the sample count of the enabled SSAO.
+    ssao: Option<(f32, u32)>,
+    /// Samples the bloom, which is smaller than the screen, and the SSAO.
+    sampler: wgpu::Sampler,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl PostProcessing {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        effects: &[PostEffectConfig],
+        fxaa: bool,
+        textures: PostTextures,
+    ) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Processing Buffer"),
+            // one more for the FXAA pass
+            size: PASS_STRIDE * (MAX_POST_EFFECTS + 1) as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Processing Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<PostEffectUniform>() as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                // the average luminance and the bloom
+                texture_entry(1),
+                texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // the ambient occlusion
+                texture_entry(4),
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Processing Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, &buffer, &sampler, textures);
+
+        let mut post_processing = Self {
+            buffer,
+            pass_count: 0,
+            fxaa,
+            auto_exposure: false,
+            bloom: None,
+            ssao: None,
+            sampler,
+            bind_group_layout,
+            bind_group,
+        };
+        post_processing.set_effects(queue, effects);
+        post_processing
+    }
+
+    /// Has to be called whenever one of the textures is recreated.
+    pub fn set_textures(&mut self, device: &wgpu::Device, textures: PostTextures) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.buffer,
+            &self.sampler,
+            textures,
+        );
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+        textures: PostTextures,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Processing Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(
+                            std::mem::size_of::<PostEffectUniform>() as u64
+                        ),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(textures.exposure),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(textures.bloom),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(textures.ssao),
+                },
+            ],
+        })
+    }
+
+    /// Uploads the enabled effects, in order.
+    /// Without any the scene is still copied onto the screen by one pass.
+    pub fn set_effects(&mut self, queue: &wgpu::Queue, effects: &[PostEffectConfig]) {
+        let fxaa = self
+            .fxaa
+            .then(|| PostEffectUniform::new(POST_FXAA, [[0.; 4]; 2]));
+        let enabled = || effects.iter().filter(|effect| effect.enabled);
+        self.auto_exposure = enabled().any(|effect| {
+            matches!(
+                effect.effect,
+                PostEffect::Tonemap {
+                    auto_exposure: true,
+                    ..
+                }
+            )
+        });
+        self.bloom = enabled().find_map(|effect| match effect.effect {
+            PostEffect::Bloom { threshold, .. } => Some(threshold),
+            _ => None,
+        });
+        self.ssao = enabled().find_map(|effect| match effect.effect {
+            PostEffect::Ssao {
+                radius, samples, ..
+            } => Some((radius, samples)),
+            _ => None,
+        });
+
+        let mut passes: Vec<PostEffectUniform> = fxaa
+            .into_iter()
+            .chain(enabled().map(|effect| (&effect.effect).into()))
+            .collect();
+        if passes.is_empty() {
+            passes.push(PostEffectUniform::new(POST_COPY, [[0.; 4]; 2]));
+        }
+
+        for (i, pass) in passes.iter().enumerate() {
+            queue.write_buffer(
+                &self.buffer,
+                i as u64 * PASS_STRIDE,
+                bytemuck::cast_slice(&[*pass]),
+            );
+        }
+        self.pass_count = passes.len();
+    }
+
+    /// Whether the average luminance has to be measured before the stack runs.
+    pub fn auto_exposure(&self) -> bool {
+        self.auto_exposure
+    }
+
+    /// The threshold of the bloom, if it has to be built before the stack runs.
+    pub fn bloom(&self) -> Option<f32> {
+        self.bloom
+    }
+
+    /// The radius and the sample count of the SSAO, if it has to be computed before the stack
+    /// runs.
+    pub fn ssao(&self) -> Option<(f32, u32)> {
+        self.ssao
+    }
+
+    /// The dynamic offset of every pass.
+    pub fn passes(&self) -> impl Iterator<Item = u32> {
+        (0..self.pass_count).map(|pass| (pass as u64 * PASS_STRIDE) as u32)
+    }
+}
+
+fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
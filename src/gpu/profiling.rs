@@ -1,6 +1,6 @@
-use std::time::Instant;
+use web_time::Instant;
 
-use voxine::print_info;
+use crate::gpu::timestamps::GpuTimings;
 
 #[derive(Default)]
 pub struct TimingAccumulator {
@@ -10,7 +10,10 @@ pub struct TimingAccumulator {
 
 impl TimingAccumulator {
     pub fn add(&mut self, duration: std::time::Duration) {
-        let ms = duration.as_secs_f64() * 1_000.0;
+        self.add_ms(duration.as_secs_f64() * 1_000.0);
+    }
+
+    pub fn add_ms(&mut self, ms: f64) {
         self.total += ms;
         self.max = self.max.max(ms);
     }
@@ -27,12 +30,23 @@ pub struct PerformanceStats {
     pub uploaded_bytes: u64,
     pub visible_chunks: u64,
     pub visible_faces: u64,
+    /// How many culling results arrived, they are read back only every few frames.
+    pub cull_samples: u32,
+    pub outside_frustum_chunks: u64,
+    pub occluded_chunks: u64,
     pub mesh_update_time: TimingAccumulator,
     pub acquire_time: TimingAccumulator,
+    pub shadow_pass_time: TimingAccumulator,
     pub main_pass_time: TimingAccumulator,
     pub post_process_time: TimingAccumulator,
     pub submit_present_time: TimingAccumulator,
     pub total_draw_time: TimingAccumulator,
+    /// How many GPU timings arrived, they are read back only every few frames.
+    gpu_samples: u32,
+    gpu_main_pass_time: TimingAccumulator,
+    gpu_post_process_time: TimingAccumulator,
+    /// The current scale of the dynamic resolution.
+    pub render_scale: f32,
 }
 
 impl PerformanceStats {
@@ -44,15 +58,30 @@ impl PerformanceStats {
             uploaded_bytes: 0,
             visible_chunks: 0,
             visible_faces: 0,
+            cull_samples: 0,
+            outside_frustum_chunks: 0,
+            occluded_chunks: 0,
             mesh_update_time: TimingAccumulator::default(),
             acquire_time: TimingAccumulator::default(),
+            shadow_pass_time: TimingAccumulator::default(),
             main_pass_time: TimingAccumulator::default(),
             post_process_time: TimingAccumulator::default(),
             submit_present_time: TimingAccumulator::default(),
             total_draw_time: TimingAccumulator::default(),
+            gpu_samples: 0,
+            gpu_main_pass_time: TimingAccumulator::default(),
+            gpu_post_process_time: TimingAccumulator::default(),
+            render_scale: 1.0,
         }
     }
 
+    pub fn add_gpu_timings(&mut self, timings: &GpuTimings) {
+        self.gpu_samples += 1;
+        self.gpu_main_pass_time.add_ms(timings.main_pass);
+        self.gpu_post_process_time.add_ms(timings.post_processing);
+    }
+
+    /// Logs the averages about once a second, with `RUST_LOG=debug`.
     pub fn maybe_report(&mut self) {
         let elapsed = self.last_report.elapsed();
         if elapsed.as_secs_f64() < 1.0 {
@@ -83,10 +112,28 @@ impl PerformanceStats {
         } else {
             self.visible_faces / self.visible_chunks
         };
+        let (avg_outside_frustum, avg_occluded) = if self.cull_samples == 0 {
+            (0, 0)
+        } else {
+            (
+                self.outside_frustum_chunks / u64::from(self.cull_samples),
+                self.occluded_chunks / u64::from(self.cull_samples),
+            )
+        };
         let faces_per_second = self.visible_faces as f64 / seconds;
+        // missing without timestamp queries
+        let gpu_times = if self.gpu_samples == 0 {
+            "-".to_string()
+        } else {
+            format!(
+                "{:.3}/{:.3}",
+                avg(&self.gpu_main_pass_time, self.gpu_samples),
+                avg(&self.gpu_post_process_time, self.gpu_samples),
+            )
+        };
 
-        print_info!(
-            "perf fps:{:.1} faces/frame:{} chunks/frame:{} faces/chunk:{} faces/s:{:.0} draw_ms:{:.3}/{:.3} main:{:.3} mesh_updates:{}",
+        log::debug!(
+            "perf fps:{:.1} faces/frame:{} chunks/frame:{} faces/chunk:{} faces/s:{:.0} draw_ms:{:.3}/{:.3} shadow:{:.3} main:{:.3} post:{:.3} gpu main/post:{} scale:{:.2} mesh_updates:{} culled frustum/occluded:{}/{}",
             fps,
             avg_visible_faces,
             avg_visible_chunks,
@@ -94,8 +141,14 @@ impl PerformanceStats {
             faces_per_second,
             avg(&self.total_draw_time, self.frames),
             self.total_draw_time.max,
+            avg(&self.shadow_pass_time, self.frames),
             avg(&self.main_pass_time, self.frames),
+            avg(&self.post_process_time, self.frames),
+            gpu_times,
+            self.render_scale,
             self.mesh_updates,
+            avg_outside_frustum,
+            avg_occluded,
         );
 
         self.last_report = Instant::now();
@@ -104,11 +157,18 @@ impl PerformanceStats {
         self.uploaded_bytes = 0;
         self.visible_chunks = 0;
         self.visible_faces = 0;
+        self.cull_samples = 0;
+        self.outside_frustum_chunks = 0;
+        self.occluded_chunks = 0;
         self.mesh_update_time.reset();
         self.acquire_time.reset();
+        self.shadow_pass_time.reset();
         self.main_pass_time.reset();
         self.post_process_time.reset();
         self.submit_present_time.reset();
         self.total_draw_time.reset();
+        self.gpu_samples = 0;
+        self.gpu_main_pass_time.reset();
+        self.gpu_post_process_time.reset();
     }
 }
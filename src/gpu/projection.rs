@@ -1,39 +1,99 @@
-use glam::{Mat4, Vec3, Vec4};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 
 pub struct Projection {
     pub aspect: f32,
     pub fov: f32,
     pub znear: f32,
+    /// A sub-pixel offset in NDC units, for the TAA.
+    pub jitter: Vec2,
 }
 
-const OPENGL_TO_WGPU_MATRIX: Mat4 = Mat4::from_cols(
-    Vec4::new(1.0, 0.0, 0.0, 0.0),
-    Vec4::new(0.0, 1.0, 0.0, 0.0),
-    Vec4::new(0.0, 0.0, 0.5, 0.0),
-    Vec4::new(0.0, 0.0, 0.5, 1.0),
-);
-
 impl Projection {
     pub fn new<F: Into<f32>>(width: u32, height: u32, fov: F, znear: f32) -> Self {
         let aspect = width as f32 / height as f32;
         let fov = fov.into();
 
-        Self { aspect, fov, znear }
+        Self {
+            aspect,
+            fov,
+            znear,
+            jitter: Vec2::ZERO,
+        }
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         self.aspect = width as f32 / height as f32;
     }
 
+    /// The jittered view projection matrix, which the scene is drawn with.
     pub fn calc_matrix(&self, view: View) -> [[f32; 4]; 4] {
-        (proj_matrix(self.fov, self.aspect, self.znear) * view.calc_matrix()).to_cols_array_2d()
+        // moves every vertex by the same offset after the perspective division
+        (Mat4::from_translation(self.jitter.extend(0.)) * self.unjittered_matrix(view))
+            .to_cols_array_2d()
+    }
+
+    pub fn unjittered_matrix(&self, view: View) -> Mat4 {
+        self.proj_matrix() * view.calc_matrix()
+    }
+
+    pub fn proj_matrix(&self) -> Mat4 {
+        proj_matrix(self.fov, self.aspect, self.znear)
+    }
+}
+
+/// The camera as every shader sees it, `camera` in the WGSL code.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+pub struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    proj: [[f32; 4]; 4],
+    inverse_proj: [[f32; 4]; 4],
+    inverse_view_proj: [[f32; 4]; 4],
+    /// The unjittered view projection of the last frame, to find pixels in the TAA history.
+    prev_view_proj: [[f32; 4]; 4],
+    position: [f32; 4],
+    resolution: [f32; 2],
+    near: f32,
+    /// Seconds since startup.
+    time: f32,
+    jitter: [f32; 2],
+    _padding: [f32; 2],
+}
+
+impl CameraUniform {
+    pub fn new(
+        proj: &Projection,
+        view: View,
+        prev_view_proj: Mat4,
+        resolution: [u32; 2],
+        time: f32,
+    ) -> Self {
+        let view_matrix = view.calc_matrix();
+        let proj_matrix = proj.proj_matrix();
+        let view_proj = Mat4::from_cols_array_2d(&proj.calc_matrix(view));
+        Self {
+            view_proj: view_proj.to_cols_array_2d(),
+            view: view_matrix.to_cols_array_2d(),
+            proj: proj_matrix.to_cols_array_2d(),
+            inverse_proj: proj_matrix.inverse().to_cols_array_2d(),
+            inverse_view_proj: view_proj.inverse().to_cols_array_2d(),
+            prev_view_proj: prev_view_proj.to_cols_array_2d(),
+            position: view.pos.extend(1.).to_array(),
+            resolution: resolution.map(|x| x as f32),
+            near: proj.znear,
+            time,
+            jitter: proj.jitter.to_array(),
+            _padding: [0.; 2],
+        }
     }
 }
 
 fn proj_matrix(fov: f32, aspect: f32, znear: f32) -> Mat4 {
-    OPENGL_TO_WGPU_MATRIX * perspective_reverse_z(fov, aspect, znear)
+    perspective_reverse_z(fov, aspect, znear)
 }
 
+/// Maps the near plane to a depth of 1 and infinity to 0, already in wgpu's 0..1 depth range.
 fn perspective_reverse_z(fovy: f32, aspect: f32, near: f32) -> Mat4 {
     let f = 1.0 / (fovy * 0.5).tan();
 
@@ -45,6 +105,29 @@ fn perspective_reverse_z(fovy: f32, aspect: f32, near: f32) -> Mat4 {
     )
 }
 
+/// The corners of the frustum slice between `near` and `far`, the near ones first.
+pub fn frustum_corners(view: View, fovy: f32, aspect: f32, near: f32, far: f32) -> [Vec3; 8] {
+    let dir = view.dir().normalize();
+    let right = dir.cross(view.up()).normalize();
+    let up = right.cross(dir);
+
+    let tan_half_fov = (fovy * 0.5).tan();
+    let mut corners = [Vec3::ZERO; 8];
+    for (i, depth) in [near, far].into_iter().enumerate() {
+        let center = view.pos() + dir * depth;
+        let half_height = depth * tan_half_fov;
+        let half_width = half_height * aspect;
+        for (j, (x, y)) in [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)]
+            .into_iter()
+            .enumerate()
+        {
+            corners[i * 4 + j] = center + right * (x * half_width) + up * (y * half_height);
+        }
+    }
+    corners
+}
+
+#[derive(Clone, Copy)]
 pub struct View {
     pos: Vec3,
     dir: Vec3,
@@ -59,4 +142,104 @@ impl View {
     pub fn calc_matrix(&self) -> Mat4 {
         Mat4::look_to_rh(self.pos, self.dir, self.up)
     }
+
+    pub fn pos(&self) -> Vec3 {
+        self.pos
+    }
+
+    pub fn dir(&self) -> Vec3 {
+        self.dir
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.up
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3, Vec3Swizzles, Vec4Swizzles};
+
+    use super::*;
+
+    /// Mirrors `linearize_depth` in post_processing.wgsl.
+    fn linearize_depth(depth: f32, near: f32) -> f32 {
+        near / depth.max(1e-7)
+    }
+
+    /// Mirrors `view_distance` in post_processing.wgsl.
+    fn view_distance(ndc: Vec2, depth: f32, inverse_proj: Mat4) -> f32 {
+        let view_pos = inverse_proj * ndc.extend(depth).extend(1.);
+        (view_pos.xyz() / view_pos.w).length()
+    }
+
+    fn project(proj: Mat4, view_pos: Vec3) -> Vec3 {
+        let clip = proj * view_pos.extend(1.);
+        clip.xyz() / clip.w
+    }
+
+    #[test]
+    fn depth_round_trip() {
+        for near in [0.1, 0.5, 2.] {
+            let proj = Projection::new(1920, 1080, 1.2_f32, near);
+            let proj_matrix = proj.proj_matrix();
+            for view_pos in [
+                Vec3::new(0., 0., -near),
+                Vec3::new(0., 0., -10.),
+                Vec3::new(3., -2., -57.),
+                Vec3::new(-40., 25., -1500.),
+            ] {
+                let ndc = project(proj_matrix, view_pos);
+                // reverse z, the near plane is at 1 and infinity at 0
+                assert!(ndc.z > 0. && ndc.z <= 1. + 1e-6);
+
+                let linear = linearize_depth(ndc.z, near);
+                assert!((linear + view_pos.z).abs() < view_pos.z.abs() * 1e-4);
+
+                let distance = view_distance(ndc.xy(), ndc.z, proj_matrix.inverse());
+                assert!((distance - view_pos.length()).abs() < view_pos.length() * 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn jitter_moves_by_a_constant_offset() {
+        let mut proj = Projection::new(1920, 1080, 1.2_f32, 0.5);
+        let view = View::new(Vec3::new(10., 20., 30.), Vec3::new(0.3, -0.2, -1.), Vec3::Y);
+        let jitter = Vec2::new(0.7 / 1920., -0.3 / 1080.);
+        let unjittered = Mat4::from_cols_array_2d(&proj.calc_matrix(view));
+        proj.jitter = jitter;
+        let jittered = Mat4::from_cols_array_2d(&proj.calc_matrix(view));
+        assert_eq!(proj.unjittered_matrix(view), unjittered);
+
+        for world_pos in [Vec3::new(12., 19., 20.), Vec3::new(-300., 80., -2000.)] {
+            let a = project(unjittered, world_pos);
+            let b = project(jittered, world_pos);
+            assert!((b.xy() - a.xy() - jitter).abs().max_element() < 1e-6);
+            assert_eq!(a.z, b.z);
+        }
+    }
+
+    #[test]
+    fn far_plane_is_infinitely_far() {
+        let proj = Projection::new(800, 600, 1.2_f32, 0.5);
+        assert!(project(proj.proj_matrix(), Vec3::new(0., 0., -1e7)).z < 1e-6);
+        assert!(linearize_depth(0., 0.5) > 1e6);
+    }
+
+    #[test]
+    fn frustum_corners_are_on_the_screen_corners() {
+        let proj = Projection::new(1920, 1080, 1.2_f32, 0.5);
+        let view = View::new(Vec3::new(10., 20., 30.), Vec3::new(0.3, -0.2, -1.), Vec3::Y);
+        let corners = frustum_corners(view, proj.fov, proj.aspect, 2., 100.);
+        let view_proj = proj.unjittered_matrix(view);
+        for (i, corner) in corners.into_iter().enumerate() {
+            let ndc = project(view_proj, corner);
+            let expected = Vec2::new(
+                if i & 1 == 0 { -1. } else { 1. },
+                if i & 2 == 0 { -1. } else { 1. },
+            );
+            assert!((ndc.xy() - expected).abs().max_element() < 1e-4);
+        }
+    }
 }
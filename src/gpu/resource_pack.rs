@@ -0,0 +1,302 @@
+//! Resource packs are directories containing a `manifest.toml` and the images it refers to.
+//!
+//! ```toml
+//! [[textures]]
+//! name = "stone"
+//! file = "stone.png"
+//!
+//! [[textures]]
+//! name = "dirt"
+//! file = "blocks/dirt.png"
+//! ```
+//!
+//! Every texture becomes one layer of the block texture array, in the order they are listed.
+//! All images of a pack need to have the same size.
+//!
+//! Blocks use the layer with their own id on every face unless the manifest says otherwise.
+//! Ids without a layer of their own use the texture called `missing`, or the first one without it.
+//! More specific faces override less specific ones (`all` < `side` < `neg-x`, ...):
+//!
+//! ```toml
+//! [[blocks]]
+//! id = 2
+//! all = "dirt"
+//! top = "grass"
+//! side = "grass-side"
+//! ```
+//!
+//! Blocks like water or glass are blended with what is behind them if they are `translucent`,
+//! the alpha channel of their textures is the opacity:
+//!
+//! ```toml
+//! [[blocks]]
+//! id = 7
+//! all = "water"
+//! translucent = true
+//! ```
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::gpu::{
+    block_table::{BlockTable, Face, MAX_BLOCK_TYPES},
+    texture::{self, TextureError},
+    texture_set,
+};
+
+pub const MANIFEST_FILE: &str = "manifest.toml";
+/// Used by the blocks without a texture of their own.
+const MISSING_TEXTURE: &str = "missing";
+
+#[derive(Debug)]
+pub enum ResourcePackError {
+    FileNotFound {
+        path: PathBuf,
+    },
+    PermissionError {
+        path: PathBuf,
+    },
+    UnknownError {
+        path: PathBuf,
+    },
+
+    TomlError {
+        err: toml::de::Error,
+    },
+    ImageError {
+        path: PathBuf,
+        err: image::ImageError,
+    },
+    TextureError {
+        err: TextureError,
+    },
+
+    DuplicateTexture {
+        name: String,
+    },
+    UnknownTexture {
+        name: String,
+    },
+    BlockIdOutOfRange {
+        id: usize,
+    },
+}
+
+impl Display for ResourcePackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ResourcePackError::*;
+        match self {
+            FileNotFound { path } => write!(f, "{} was not found", path.display()),
+            PermissionError { path } => write!(
+                f,
+                "the process didnt had the permission to access {}",
+                path.display()
+            ),
+            UnknownError { path } => write!(
+                f,
+                "an unknown error occured while reading {}",
+                path.display()
+            ),
+
+            TomlError { err } => write!(f, "invalid manifest: {}", err.message()),
+            ImageError { path, err } => write!(f, "{}: {}", path.display(), err),
+            TextureError { err } => write!(f, "{}", err),
+
+            DuplicateTexture { name } => {
+                write!(f, "the texture \"{}\" is defined more than once", name)
+            }
+            UnknownTexture { name } => write!(f, "there is no texture called \"{}\"", name),
+            BlockIdOutOfRange { id } => write!(
+                f,
+                "block id {} is out of range, the maximum is {}",
+                id,
+                MAX_BLOCK_TYPES - 1
+            ),
+        }
+    }
+}
+
+impl ResourcePackError {
+    fn io(path: &Path, err: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match err.kind() {
+            ErrorKind::NotFound => Self::FileNotFound { path },
+            ErrorKind::PermissionDenied => Self::PermissionError { path },
+            _ => Self::UnknownError { path },
+        }
+    }
+}
+
+impl From<toml::de::Error> for ResourcePackError {
+    fn from(value: toml::de::Error) -> Self {
+        Self::TomlError { err: value }
+    }
+}
+
+impl From<TextureError> for ResourcePackError {
+    fn from(value: TextureError) -> Self {
+        Self::TextureError { err: value }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Manifest {
+    textures: Vec<TextureEntry>,
+    #[serde(default)]
+    blocks: Vec<BlockEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct TextureEntry {
+    name: String,
+    file: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct BlockEntry {
+    id: usize,
+    all: Option<String>,
+    side: Option<String>,
+    top: Option<String>,
+    bottom: Option<String>,
+    neg_x: Option<String>,
+    pos_x: Option<String>,
+    neg_z: Option<String>,
+    pos_z: Option<String>,
+    #[serde(default)]
+    translucent: bool,
+}
+
+impl BlockEntry {
+    /// The texture names per face, from least to most specific.
+    fn faces(&self) -> Vec<(Face, &String)> {
+        let mut faces = vec![];
+        if let Some(all) = &self.all {
+            faces.extend(Face::ALL.map(|face| (face, all)));
+        }
+        if let Some(side) = &self.side {
+            faces.extend(Face::SIDES.map(|face| (face, side)));
+        }
+        for (face, name) in [
+            (Face::Top, &self.top),
+            (Face::Bottom, &self.bottom),
+            (Face::NegX, &self.neg_x),
+            (Face::PosX, &self.pos_x),
+            (Face::NegZ, &self.neg_z),
+            (Face::PosZ, &self.pos_z),
+        ] {
+            if let Some(name) = name {
+                faces.push((face, name));
+            }
+        }
+        faces
+    }
+}
+
+pub struct ResourcePack {
+    /// The names of the textures, index = array layer.
+    pub texture_names: Vec<String>,
+    pub images: Vec<image::DynamicImage>,
+    pub blocks: BlockTable,
+}
+
+impl ResourcePack {
+    /// The pack which is compiled into the binary.
+    pub fn embedded() -> Self {
+        Self {
+            texture_names: texture_set::Texture::ALL
+                .iter()
+                .map(|texture| texture.name().to_string())
+                .collect(),
+            images: texture_set::Texture::ALL
+                .iter()
+                .map(|texture| {
+                    image::load_from_memory(texture.bytes()).expect("embedded texture is valid")
+                })
+                .collect(),
+            blocks: BlockTable::identity(texture_set::Texture::ALL.len(), 0),
+        }
+    }
+
+    /// Loads the pack at `dir` and checks that it can be uploaded as a texture array within
+    /// `limits`.
+    pub fn load(dir: &Path, limits: &wgpu::Limits) -> Result<Self, ResourcePackError> {
+        let manifest_path = dir.join(MANIFEST_FILE);
+        let manifest = fs::read_to_string(&manifest_path)
+            .map_err(|err| ResourcePackError::io(&manifest_path, err))?;
+        let manifest: Manifest = toml::from_str(&manifest)?;
+
+        let mut texture_names = Vec::with_capacity(manifest.textures.len());
+        let mut images = Vec::with_capacity(manifest.textures.len());
+        for TextureEntry { name, file } in manifest.textures {
+            if texture_names.contains(&name) {
+                return Err(ResourcePackError::DuplicateTexture { name });
+            }
+            let path = dir.join(file);
+            let bytes = fs::read(&path).map_err(|err| ResourcePackError::io(&path, err))?;
+            let image = image::load_from_memory(&bytes)
+                .map_err(|err| ResourcePackError::ImageError { path, err })?;
+
+            texture_names.push(name);
+            images.push(image);
+        }
+        texture::array_dimensions(&images, limits)?;
+
+        let fallback = texture_names
+            .iter()
+            .position(|name| name == MISSING_TEXTURE)
+            .unwrap_or(0) as u32;
+        let mut pack = Self {
+            blocks: BlockTable::identity(texture_names.len(), fallback),
+            texture_names,
+            images,
+        };
+        for block in &manifest.blocks {
+            if block.id >= MAX_BLOCK_TYPES {
+                return Err(ResourcePackError::BlockIdOutOfRange { id: block.id });
+            }
+            for (face, name) in block.faces() {
+                let layer = pack
+                    .layer(name)
+                    .ok_or_else(|| ResourcePackError::UnknownTexture { name: name.clone() })?;
+                pack.blocks.set(block.id, face, layer);
+            }
+            pack.blocks.set_translucent(block.id, block.translucent);
+        }
+        Ok(pack)
+    }
+
+    /// Loads the configured pack, falling back to the embedded one if there is none or it is broken.
+    pub fn load_or_embedded(dir: Option<&Path>, limits: &wgpu::Limits) -> Self {
+        let Some(dir) = dir else {
+            return Self::embedded();
+        };
+        match Self::load(dir, limits) {
+            Ok(pack) => pack,
+            Err(err) => {
+                log::error!(
+                    "couldn't load resource pack {}: {}, using the embedded one",
+                    dir.display(),
+                    err
+                );
+                Self::embedded()
+            }
+        }
+    }
+
+    /// Returns the array layer of the texture with the given name.
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.texture_names
+            .iter()
+            .position(|texture| texture == name)
+            .map(|layer| layer as u32)
+    }
+}
@@ -1,37 +1,517 @@
+//! Loads the shader modules. Every pipeline is built from an entry file, which pulls in the files
+//! it needs with `#include "path"`, relative to the shader directory. A file is included at most
+//! once per module. `#define NAME value` replaces every later use of `NAME` and
+//! `#ifdef`/`#ifndef`/`#else`/`#endif` remove code depending on whether something is defined.
+//!
+//! The files are embedded into the binary. If a shader directory is configured, the files in it
+//! are used instead and watched for changes. The web has no file system, there only the embedded
+//! files are used.
+use std::collections::{HashMap, HashSet};
+#[cfg(not(target_arch = "wasm32"))]
 use std::ffi::OsString;
+use std::fmt::Display;
 use std::fs;
 use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{path::Path, sync::mpsc};
 
-pub(super) fn make_shader() -> wgpu::ShaderModuleDescriptor<'static> {
-    wgpu::ShaderModuleDescriptor {
-        label: None,
-        source: wgpu::ShaderSource::Wgsl(
-            (collect_shader(std::path::PathBuf::from("./src"))
-                + &format!(
-                    "const RENDER_DISTANCE: f32 = {:.3};",
-                    crate::RENDER_DISTANCE
-                ))
-                .into(),
-        ),
-    }
-}
-fn collect_shader(path: PathBuf) -> String {
-    let mut shader_code = String::new();
-    for dir_entry in if let Ok(dir_iter) = fs::read_dir(&path) {
-        dir_iter
-    } else {
-        panic!("wrong working directory");
-    }
-    .flatten()
-    {
-        let entry_path = dir_entry.path();
-        if entry_path.is_dir() && entry_path.file_name() != Some(&OsString::from("target")) {
-            shader_code += &collect_shader(entry_path)
-        } else if entry_path.extension() == Some(&OsString::from("wgsl"))
-            && let Ok(file_content) = fs::read_to_string(entry_path)
+#[cfg(not(target_arch = "wasm32"))]
+use notify::Watcher;
+
+use crate::{config::TextureConfig, gpu::pipelines::ShaderModules};
+
+/// Every shader file with its path relative to the shader directory.
+const EMBEDDED_SHADERS: &[(&str, &str)] = &[
+    ("bloom.wgsl", include_str!("shader/bloom.wgsl")),
+    ("camera.wgsl", include_str!("shader/camera.wgsl")),
+    ("chunk_table.wgsl", include_str!("shader/chunk_table.wgsl")),
+    ("culling.wgsl", include_str!("shader/culling.wgsl")),
+    (
+        "depth_resolve.wgsl",
+        include_str!("shader/depth_resolve.wgsl"),
+    ),
+    ("effects.wgsl", include_str!("shader/effects.wgsl")),
+    ("exposure.wgsl", include_str!("shader/exposure.wgsl")),
+    ("full_screen.wgsl", include_str!("shader/full_screen.wgsl")),
+    ("fxaa.wgsl", include_str!("shader/fxaa.wgsl")),
+    ("hi_z.wgsl", include_str!("shader/hi_z.wgsl")),
+    ("hud.wgsl", include_str!("shader/hud.wgsl")),
+    ("lines.wgsl", include_str!("shader/lines.wgsl")),
+    (
+        "post_processing.wgsl",
+        include_str!("shader/post_processing.wgsl"),
+    ),
+    ("projection.wgsl", include_str!("shader/projection.wgsl")),
+    ("sky.wgsl", include_str!("shader/sky.wgsl")),
+    ("sky_pass.wgsl", include_str!("shader/sky_pass.wgsl")),
+    ("ssao.wgsl", include_str!("shader/ssao.wgsl")),
+    ("taa.wgsl", include_str!("shader/taa.wgsl")),
+    (
+        "helpers/mathematics.wgsl",
+        include_str!("shader/helpers/mathematics.wgsl"),
+    ),
+    (
+        "helpers/randomness.wgsl",
+        include_str!("shader/helpers/randomness.wgsl"),
+    ),
+    (
+        "helpers/sdfs.wgsl",
+        include_str!("shader/helpers/sdfs.wgsl"),
+    ),
+];
+
+/// The files the shader modules are built from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ShaderEntry {
+    /// The chunks and the shadow maps.
+    Terrain,
+    Sky,
+    PostProcessing,
+    /// The compute shader writing the indirect chunk draws.
+    Culling,
+    /// The depth pyramid for the occlusion culling.
+    HiZ,
+    Taa,
+    /// Resolves the multisampled depth buffer.
+    DepthResolve,
+    /// The chunk bounds and the frustum outline.
+    Lines,
+    /// The average luminance for the auto exposure.
+    Exposure,
+    Bloom,
+    /// The screen space ambient occlusion and its blur.
+    Ssao,
+    /// The text and the frame time graph of the debug screen.
+    Hud,
+}
+
+impl ShaderEntry {
+    fn path(self) -> &'static str {
+        match self {
+            Self::Terrain => "projection.wgsl",
+            Self::Sky => "sky_pass.wgsl",
+            Self::PostProcessing => "post_processing.wgsl",
+            Self::Culling => "culling.wgsl",
+            Self::HiZ => "hi_z.wgsl",
+            Self::Taa => "taa.wgsl",
+            Self::DepthResolve => "depth_resolve.wgsl",
+            Self::Lines => "lines.wgsl",
+            Self::Exposure => "exposure.wgsl",
+            Self::Bloom => "bloom.wgsl",
+            Self::Ssao => "ssao.wgsl",
+            Self::Hud => "hud.wgsl",
+        }
+    }
+}
+
+/// A preprocessed shader module.
+pub(super) struct ShaderSource {
+    label: &'static str,
+    code: String,
+    files: Vec<PathBuf>,
+    /// The file (index into `files`) and the 0 based line every line of `code` came from.
+    origins: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    /// None if the error has no location.
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub msg: String,
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => write!(
+                f,
+                "{}:{}:{}: {}",
+                path.display(),
+                self.line,
+                self.column,
+                self.msg
+            ),
+            None => write!(f, "{}", self.msg),
+        }
+    }
+}
+
+pub(super) struct ShaderLoader {
+    dir: Option<PathBuf>,
+    defines: HashMap<String, String>,
+}
+
+impl ShaderLoader {
+    /// Without `push_constants` the chunk metadata is read from a uniform, see `chunk_uniforms`.
+    pub fn new(dir: Option<PathBuf>, textures: &TextureConfig, push_constants: bool) -> Self {
+        if let Some(dir) = &dir
+            && !dir.is_dir()
+        {
+            log::warn!(
+                "shader directory {} not found, using the embedded shaders",
+                dir.display()
+            );
+        }
+        let mut defines = HashMap::from([
+            (
+                "RENDER_DISTANCE".to_string(),
+                format!("{:.3}", crate::RENDER_DISTANCE),
+            ),
+            ("MIP_BIAS".to_string(), format!("{:.3}", textures.mip_bias)),
+        ]);
+        if push_constants {
+            defines.insert("PUSH_CONSTANTS".to_string(), String::new());
+        }
+        Self { dir, defines }
+    }
+
+    /// The directory overriding the embedded shaders.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Loads, validates and compiles all entry modules.
+    pub fn compile(&self, device: &wgpu::Device) -> Result<ShaderModules, ShaderError> {
+        let compile = |entry| {
+            let source = self.load(entry)?;
+            source.validate()?;
+            Ok(device.create_shader_module(source.descriptor()))
+        };
+        Ok(ShaderModules {
+            terrain: compile(ShaderEntry::Terrain)?,
+            sky: compile(ShaderEntry::Sky)?,
+            post_processing: compile(ShaderEntry::PostProcessing)?,
+            culling: compile(ShaderEntry::Culling)?,
+            hi_z: compile(ShaderEntry::HiZ)?,
+            taa: compile(ShaderEntry::Taa)?,
+            depth_resolve: compile(ShaderEntry::DepthResolve)?,
+            lines: compile(ShaderEntry::Lines)?,
+            exposure: compile(ShaderEntry::Exposure)?,
+            bloom: compile(ShaderEntry::Bloom)?,
+            ssao: compile(ShaderEntry::Ssao)?,
+            hud: compile(ShaderEntry::Hud)?,
+        })
+    }
+
+    /// Resolves the includes of `entry` and applies the defines.
+    pub fn load(&self, entry: ShaderEntry) -> Result<ShaderSource, ShaderError> {
+        let mut preprocessor = Preprocessor {
+            loader: self,
+            defines: self.defines.clone(),
+            included: HashSet::new(),
+            source: ShaderSource {
+                label: entry.path(),
+                code: String::new(),
+                files: vec![],
+                origins: vec![],
+            },
+        };
+        preprocessor.include(entry.path(), None)?;
+        Ok(preprocessor.source)
+    }
+
+    /// Reads a file from the shader directory or, if it isn't there, from the binary.
+    /// Returns the path errors are reported with.
+    fn read(&self, path: &str) -> Option<(PathBuf, String)> {
+        if let Some(dir) = &self.dir
+            && let Ok(content) = fs::read_to_string(dir.join(path))
         {
-            shader_code += &file_content
+            return Some((dir.join(path), content));
+        }
+        EMBEDDED_SHADERS
+            .iter()
+            .find(|(embedded, _)| *embedded == path)
+            .map(|(_, content)| (PathBuf::from(path), content.to_string()))
+    }
+}
+
+struct Preprocessor<'a> {
+    loader: &'a ShaderLoader,
+    defines: HashMap<String, String>,
+    included: HashSet<String>,
+    source: ShaderSource,
+}
+
+impl Preprocessor<'_> {
+    /// `from` is the file and line of the `#include`.
+    fn include(&mut self, path: &str, from: Option<(usize, usize)>) -> Result<(), ShaderError> {
+        if !self.included.insert(path.to_string()) {
+            return Ok(());
+        }
+        let Some((file_path, content)) = self.loader.read(path) else {
+            let msg = format!("shader file {} not found", path);
+            return Err(match from {
+                Some((file, line)) => self.error(file, line, msg),
+                None => ShaderError {
+                    path: None,
+                    line: 0,
+                    column: 0,
+                    msg,
+                },
+            });
+        };
+        let file = self.source.files.len();
+        self.source.files.push(file_path);
+
+        // one entry per open #ifdef: whether its code is used
+        let mut conditions: Vec<bool> = vec![];
+        for (line, text) in content.lines().enumerate() {
+            let active = conditions.iter().all(|&active| active);
+            let Some(directive) = text.trim().strip_prefix('#') else {
+                if active {
+                    self.push_line(file, line, text);
+                }
+                continue;
+            };
+            let (directive, argument) = directive
+                .split_once(char::is_whitespace)
+                .map(|(directive, argument)| (directive, argument.trim()))
+                .unwrap_or((directive, ""));
+            match directive {
+                "include" if active => {
+                    let Some(included) = argument
+                        .strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                    else {
+                        return Err(self.error(file, line, "expected #include \"path\"".into()));
+                    };
+                    self.include(included, Some((file, line)))?;
+                }
+                "define" if active => {
+                    let (name, value) = argument
+                        .split_once(char::is_whitespace)
+                        .unwrap_or((argument, ""));
+                    if name.is_empty() {
+                        return Err(self.error(file, line, "expected #define NAME value".into()));
+                    }
+                    self.defines
+                        .insert(name.to_string(), value.trim().to_string());
+                }
+                "include" | "define" => {}
+                "ifdef" => conditions.push(self.defines.contains_key(argument)),
+                "ifndef" => conditions.push(!self.defines.contains_key(argument)),
+                "else" => match conditions.last_mut() {
+                    Some(active) => *active = !*active,
+                    None => return Err(self.error(file, line, "#else without #ifdef".into())),
+                },
+                "endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(self.error(file, line, "#endif without #ifdef".into()));
+                    }
+                }
+                _ => {
+                    return Err(self.error(
+                        file,
+                        line,
+                        format!("unknown directive #{}", directive),
+                    ));
+                }
+            }
+        }
+        if !conditions.is_empty() {
+            return Err(self.error(
+                file,
+                content.lines().count().saturating_sub(1),
+                "missing #endif".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn push_line(&mut self, file: usize, line: usize, text: &str) {
+        self.source.code += &replace_defines(text, &self.defines);
+        self.source.code.push('\n');
+        self.source.origins.push((file, line));
+    }
+
+    fn error(&self, file: usize, line: usize, msg: String) -> ShaderError {
+        ShaderError {
+            path: Some(self.source.files[file].clone()),
+            line: line + 1,
+            column: 1,
+            msg,
+        }
+    }
+}
+
+/// Replaces every identifier which is defined with its value.
+fn replace_defines(line: &str, defines: &HashMap<String, String>) -> String {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(is_identifier) {
+        result += &rest[..start];
+        rest = &rest[start..];
+        let end = rest.find(|c| !is_identifier(c)).unwrap_or(rest.len());
+        let word = &rest[..end];
+        result += defines.get(word).map(String::as_str).unwrap_or(word);
+        rest = &rest[end..];
+    }
+    result + rest
+}
+
+impl ShaderSource {
+    pub fn descriptor(&self) -> wgpu::ShaderModuleDescriptor<'_> {
+        wgpu::ShaderModuleDescriptor {
+            label: Some(self.label),
+            source: wgpu::ShaderSource::Wgsl(self.code.as_str().into()),
+        }
+    }
+
+    /// Parses and validates the code with naga, so errors can be reported without wgpu panicking.
+    pub fn validate(&self) -> Result<(), ShaderError> {
+        let module = naga::front::wgsl::parse_str(&self.code)
+            .map_err(|err| self.error(err.location(&self.code), err.message().to_string()))?;
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::PUSH_CONSTANT,
+        )
+        .validate(&module)
+        .map_err(|err| self.error(err.location(&self.code), err.as_inner().to_string()))?;
+        Ok(())
+    }
+
+    fn error(&self, location: Option<naga::SourceLocation>, msg: String) -> ShaderError {
+        let Some(location) = location else {
+            return ShaderError {
+                path: Some(PathBuf::from(self.label)),
+                line: 0,
+                column: 0,
+                msg,
+            };
+        };
+        let (path, line) = self.locate(location.line_number as usize - 1);
+        ShaderError {
+            path,
+            line: line + 1,
+            column: location.line_position as usize,
+            msg,
+        }
+    }
+
+    /// Maps a line of the preprocessed code to the file it came from and the line in that file.
+    fn locate(&self, line: usize) -> (Option<PathBuf>, usize) {
+        self.origins
+            .get(line)
+            .map(|&(file, line)| (Some(self.files[file].clone()), line))
+            .unwrap_or((None, line))
+    }
+}
+
+/// Watches the shader directory for changes.
+#[cfg(not(target_arch = "wasm32"))]
+pub(super) struct ShaderWatcher {
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ShaderWatcher {
+    pub fn new(dir: &Path) -> Result<Self, notify::Error> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(dir, notify::RecursiveMode::Recursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Whether a shader file was written since the last call.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) => {
+                    changed |= !event.kind.is_access()
+                        && event
+                            .paths
+                            .iter()
+                            .any(|path| path.extension() == Some(&OsString::from("wgsl")))
+                }
+                Err(err) => log::warn!("shader watcher: {}", err),
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loader() -> ShaderLoader {
+        loader_with(true)
+    }
+
+    fn loader_with(push_constants: bool) -> ShaderLoader {
+        ShaderLoader::new(
+            None,
+            &TextureConfig {
+                mipmaps: true,
+                mip_bias: 0.,
+                anisotropy: 1,
+            },
+            push_constants,
+        )
+    }
+
+    #[test]
+    fn embedded_modules_are_valid() {
+        for entry in [
+            ShaderEntry::Terrain,
+            ShaderEntry::Sky,
+            ShaderEntry::PostProcessing,
+            ShaderEntry::Culling,
+            ShaderEntry::HiZ,
+            ShaderEntry::Taa,
+            ShaderEntry::DepthResolve,
+            ShaderEntry::Lines,
+            ShaderEntry::Exposure,
+            ShaderEntry::Bloom,
+            ShaderEntry::Ssao,
+            ShaderEntry::Hud,
+        ] {
+            let source = loader().load(entry).unwrap();
+            if let Err(err) = source.validate() {
+                panic!("{:?}: {}", entry, err);
+            }
         }
     }
-    shader_code
+
+    #[test]
+    fn terrain_is_valid_without_push_constants() {
+        let source = loader_with(false).load(ShaderEntry::Terrain).unwrap();
+        assert!(!source.code.contains("push_constant"));
+        assert!(!source.code.contains("vs_indirect"));
+        if let Err(err) = source.validate() {
+            panic!("{}", err);
+        }
+    }
+
+    #[test]
+    fn errors_point_into_the_included_file() {
+        let source = loader().load(ShaderEntry::Sky).unwrap();
+        let line = source
+            .code
+            .lines()
+            .position(|line| line.starts_with("struct SkyUniform"))
+            .unwrap();
+        let (path, line) = source.locate(line);
+        assert_eq!(path, Some(PathBuf::from("sky.wgsl")));
+        assert!(
+            EMBEDDED_SHADERS
+                .iter()
+                .find(|(path, _)| *path == "sky.wgsl")
+                .unwrap()
+                .1
+                .lines()
+                .nth(line)
+                .unwrap()
+                .starts_with("struct SkyUniform")
+        );
+    }
 }
@@ -0,0 +1,61 @@
+// The bloom chain, see bloom.rs. The first level is downsampled from the scene, keeping only what
+// is brighter than the threshold, every further level from the one before. The upsampling adds
+// every level onto the one above it.
+#include "full_screen.wgsl"
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_s: sampler;
+
+struct BloomSettings {
+    threshold: f32,
+};
+
+@group(1) @binding(0) var<uniform> settings: BloomSettings;
+
+// Keeps single very bright pixels from turning into flickering blobs.
+const MAX_BRIGHTNESS: f32 = 64.0;
+
+@fragment fn bloom_prefilter(in: PostProcessingOutput) -> @location(0) vec4<f32> {
+    let color = min(downsample(in.tex_coords), vec3(MAX_BRIGHTNESS));
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - settings.threshold, 0.0) / max(brightness, 1e-4);
+    return vec4(color * contribution, 1.0);
+}
+
+@fragment fn bloom_downsample(in: PostProcessingOutput) -> @location(0) vec4<f32> {
+    return vec4(downsample(in.tex_coords), 1.0);
+}
+
+// Blended onto the level above.
+@fragment fn bloom_upsample(in: PostProcessingOutput) -> @location(0) vec4<f32> {
+    return vec4(upsample(in.tex_coords), 1.0);
+}
+
+// The center and four diagonal taps, every one of them filters 2x2 texels of the source.
+fn downsample(pos: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    var sum = tap(pos) * 4.0;
+    sum += tap(pos + texel * vec2(-1.0, -1.0));
+    sum += tap(pos + texel * vec2(1.0, -1.0));
+    sum += tap(pos + texel * vec2(-1.0, 1.0));
+    sum += tap(pos + texel * vec2(1.0, 1.0));
+    return sum / 8.0;
+}
+
+// A tent filter over the source, which has half the size of the target.
+fn upsample(pos: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    var sum = tap(pos + texel * vec2(-1.0, 0.0));
+    sum += tap(pos + texel * vec2(1.0, 0.0));
+    sum += tap(pos + texel * vec2(0.0, -1.0));
+    sum += tap(pos + texel * vec2(0.0, 1.0));
+    sum += tap(pos + texel * vec2(-0.5, -0.5)) * 2.0;
+    sum += tap(pos + texel * vec2(0.5, -0.5)) * 2.0;
+    sum += tap(pos + texel * vec2(-0.5, 0.5)) * 2.0;
+    sum += tap(pos + texel * vec2(0.5, 0.5)) * 2.0;
+    return sum / 12.0;
+}
+
+fn tap(pos: vec2<f32>) -> vec3<f32> {
+    return textureSample(source, source_s, pos).rgb;
+}
@@ -0,0 +1,18 @@
+// Shared by all passes, see projection::CameraUniform.
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    inverse_proj: mat4x4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+    // unjittered, of the last frame
+    prev_view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+    resolution: vec2<f32>,
+    near: f32,
+    time: f32, // seconds since startup
+    // the sub-pixel offset of the TAA in NDC units
+    jitter: vec2<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
@@ -0,0 +1,7 @@
+// One entry per mesh slot, has to match indirect::ChunkEntry.
+struct ChunkEntry {
+    pos_lod: vec4<i32>,
+    offsets: array<u32, 6>, // the first face of every orientation, relative to the slot
+    first_face: u32, // the first face of the slot in its vertex buffer
+    face_count: u32,
+};
@@ -0,0 +1,180 @@
+#include "camera.wgsl"
+#include "chunk_table.wgsl"
+
+// Has to match indirect::CullParams. One per dispatch, the camera and every shadow cascade.
+struct CullParams {
+    draw_count: u32,
+    vertices_per_face: u32,
+    // the translucent draws follow, they aren't counted in the stats
+    opaque_count: u32,
+    // where the draws of this dispatch start in the draw list
+    first_draw: u32,
+    // points towards the light, only for the shadow cascades
+    light_direction: vec3<f32>,
+    // whether the camera is a shadow cascade
+    shadow: u32,
+};
+
+// Has to match indirect::CullStats.
+struct CullStats {
+    outside_frustum: atomic<u32>,
+    occluded: atomic<u32>,
+    // the opaque chunks with faces left after the culling
+    visible_chunks: atomic<u32>,
+    visible_faces: atomic<u32>,
+};
+
+const FACE_NORMALS: array<vec3<f32>, 6> = array(
+    vec3(-1.0, 0.0, 0.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(0.0, 0.0, 1.0),
+);
+
+struct DrawIndirectArgs {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+};
+
+@group(0) @binding(0) var<storage, read> chunk_table: array<ChunkEntry>;
+// the chunk table index of every chunk to draw
+@group(0) @binding(1) var<storage, read> draw_list: array<u32>;
+// six draws per chunk, one per orientation
+@group(0) @binding(2) var<storage, read_write> draws: array<DrawIndirectArgs>;
+@group(0) @binding(3) var<uniform> params: CullParams;
+@group(0) @binding(4) var<storage, read_write> stats: CullStats;
+
+// group 1 is the camera or the light of a shadow cascade
+
+// The depth pyramid of the last frame, see hi_z.wgsl.
+@group(2) @binding(0) var depth_pyramid: texture_2d<f32>;
+
+// Whether the box intersects the view frustum. Also fits the orthographic cascades, whose depth
+// runs from 0 to 1 instead.
+fn in_frustum(low: vec3<f32>, high: vec3<f32>) -> bool {
+    let m = transpose(camera.view_proj);
+    // left, right, bottom, top, near (reverse z) and the far plane, which is infinitely far away
+    var planes = array<vec4<f32>, 6>(
+        m[3] + m[0],
+        m[3] - m[0],
+        m[3] + m[1],
+        m[3] - m[1],
+        m[3] - m[2],
+        m[2],
+    );
+    for (var i = 0u; i < 6u; i++) {
+        let plane = planes[i];
+        // the corner furthest along the normal
+        let corner = select(low, high, plane.xyz > vec3(0.0));
+        if dot(plane.xyz, corner) + plane.w < 0.0 {
+            return false;
+        }
+    }
+    return true;
+}
+
+// Whether the box is behind the terrain of the last frame. Chunks which just came into view
+// can be missing for a frame because of that.
+fn occluded(low: vec3<f32>, high: vec3<f32>) -> bool {
+    var rect_min = vec2(1.0);
+    var rect_max = vec2(0.0);
+    var nearest = 0.0;
+    for (var i = 0u; i < 8u; i++) {
+        let corner = select(low, high, vec3((i & 1u) != 0u, (i & 2u) != 0u, (i & 4u) != 0u));
+        let clip = camera.view_proj * vec4(corner, 1.0);
+        if clip.w <= camera.near {
+            return false; // the box reaches the camera
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        rect_min = min(rect_min, uv);
+        rect_max = max(rect_max, uv);
+        nearest = max(nearest, ndc.z); // reverse z
+    }
+    rect_min = clamp(rect_min, vec2(0.0), vec2(1.0));
+    rect_max = clamp(rect_max, vec2(0.0), vec2(1.0));
+
+    // the level where the box covers at most 2x2 texels
+    let extent = (rect_max - rect_min) * vec2<f32>(textureDimensions(depth_pyramid));
+    let level = min(
+        u32(ceil(log2(max(max(extent.x, extent.y), 1.0)))),
+        textureNumLevels(depth_pyramid) - 1u,
+    );
+    let size = vec2<f32>(textureDimensions(depth_pyramid, level));
+    let low_texel = vec2<u32>(min(rect_min * size, size - 1.0));
+    let high_texel = vec2<u32>(min(rect_max * size, size - 1.0));
+    let furthest = min(
+        min(
+            textureLoad(depth_pyramid, low_texel, level).r,
+            textureLoad(depth_pyramid, vec2(high_texel.x, low_texel.y), level).r,
+        ),
+        min(
+            textureLoad(depth_pyramid, vec2(low_texel.x, high_texel.y), level).r,
+            textureLoad(depth_pyramid, high_texel, level).r,
+        ),
+    );
+    return nearest < furthest;
+}
+
+@compute @workgroup_size(64) fn cull_chunks(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.draw_count {
+        return;
+    }
+    let draw = params.first_draw + id.x;
+    let chunk = chunk_table[draw_list[draw]];
+    let size = 32.0 * f32(1u << u32(chunk.pos_lod.w));
+    let low = vec3<f32>(chunk.pos_lod.xyz) * size;
+    let high = low + size;
+    let shadow = params.shadow != 0u;
+    let inside_frustum = in_frustum(low, high);
+    // the depth pyramid is only of the camera
+    let visible = inside_frustum && (shadow || !occluded(low, high));
+    let counted = !shadow && draw < params.opaque_count;
+    if counted {
+        if !inside_frustum {
+            atomicAdd(&stats.outside_frustum, 1u);
+        } else if !visible {
+            atomicAdd(&stats.occluded, 1u);
+        }
+    }
+
+    // faces can only be seen from the side they point to, like gpu::for_every_axis,
+    // and only cast shadows if they face the light
+    let cam = camera.position.xyz;
+    var facing = array<bool, 6>(
+        cam.x <= high.x,
+        low.x <= cam.x,
+        cam.y <= high.y,
+        low.y <= cam.y,
+        cam.z <= high.z,
+        low.z <= cam.z,
+    );
+    if shadow {
+        for (var axis = 0u; axis < 6u; axis++) {
+            facing[axis] = dot(FACE_NORMALS[axis], params.light_direction) > 0.0;
+        }
+    }
+    var faces = 0u;
+    for (var axis = 0u; axis < 6u; axis++) {
+        let start = chunk.offsets[axis];
+        var end = chunk.face_count;
+        if axis < 5u {
+            end = chunk.offsets[axis + 1u];
+        }
+        var args: DrawIndirectArgs;
+        args.vertex_count = params.vertices_per_face;
+        args.instance_count = select(0u, end - start, visible && facing[axis]);
+        args.first_vertex = 0u;
+        args.first_instance = chunk.first_face + start;
+        draws[draw * 6u + axis] = args;
+        faces += args.instance_count;
+    }
+    if counted && faces > 0u {
+        atomicAdd(&stats.visible_chunks, 1u);
+        atomicAdd(&stats.visible_faces, faces);
+    }
+}
@@ -0,0 +1,14 @@
+// Resolves the multisampled depth of the main pass, see anti_aliasing::Msaa.
+#include "full_screen.wgsl"
+
+@group(0) @binding(0) var depth_samples: texture_depth_multisampled_2d;
+
+// The furthest sample, which keeps the occlusion culling conservative.
+@fragment fn resolve_depth(in: PostProcessingOutput) -> @builtin(frag_depth) f32 {
+    let pixel = vec2<i32>(in.position.xy);
+    var furthest = 1.0;
+    for (var i = 0u; i < textureNumSamples(depth_samples); i++) {
+        furthest = min(furthest, textureLoad(depth_samples, pixel, i32(i)));
+    }
+    return furthest;
+}
@@ -1,36 +1,93 @@
-const VIEW_DISTANCE: f32 = RENDER_DISTANCE * 32.0;
-const SKY_COLOR: vec3<f32> = vec3(0.1, 0.3, 0.5); // Deep cave void
-const FOG_COLOR_LOW: vec3<f32> = vec3(0.02, 0.025, 0.03);
-const FOG_COLOR_HIGH: vec3<f32> = vec3(0.05, 0.055, 0.06);
-
-const FOG_START: f32 = 0.03;
-const FOG_END: f32 = 0.85;
-const FOG_DENSITY: f32 = 60.0;
-const FOG_MAX: f32 = 10.; //0.85;
+#include "fxaa.wgsl"
 
-fn apply_effects(pos: vec2<f32>, color: vec3<f32>, depth: f32) -> vec3<f32> {
-    let anti_aliased = fxaa(pos, color);
+const VIEW_DISTANCE: f32 = RENDER_DISTANCE * 32.0;
 
-    // Create vignette effect (darker at the edges)
+// Darkens the edges, `radius` is the distance from the center where it starts.
+fn vignette(pos: vec2<f32>, color: vec3<f32>, strength: f32, radius: f32) -> vec3<f32> {
     let screenCenter = vec2<f32>(0.5, 0.5); // Mittelpunkt des Bildschirms für Vignette
     let center_dst = distance(pos, screenCenter);
-    let vignette = smoothstep(0.5, 0.2, center_dst - 0.25) * 0.85 + 0.15;
-
-    // Combine all effects
-    let final_color = anti_aliased * sqrt(vignette);
+    let vignette = smoothstep(0.5, 0.2, center_dst - radius) * strength + (1.0 - strength);
+    return color * sqrt(vignette);
+}
 
-    // depth is view-space distance, shape against render distance.
+// params: start, end, density, max. Start and end are fractions of the render distance.
+fn fog(pos: vec2<f32>, color: vec3<f32>, depth: f32, params: vec4<f32>) -> vec3<f32> {
+    // depth is the distance from the camera, shape against render distance.
     let view_dist = max(VIEW_DISTANCE, 1.0);
-    let fog_start = view_dist * FOG_START;
-    let fog_end = view_dist * FOG_END;
+    let fog_start = view_dist * params.x;
+    let fog_end = view_dist * params.y;
     let clamped_depth = min(depth, view_dist);
     let fog_t = clamp((clamped_depth - fog_start) / (fog_end - fog_start), 0.0, 1.0);
-    let fog = min(1.0 - exp2(-fog_t * fog_t * FOG_DENSITY), FOG_MAX);
-    let fog_color = mix(FOG_COLOR_LOW, FOG_COLOR_HIGH, clamp(pos.y * 0.8 + 0.1, 0.0, 1.0));
+    let fog_amount = min(1.0 - exp2(-fog_t * fog_t * params.z), params.w);
+    let fog_color = mix(sky.fog_color_low.rgb, sky.fog_color_high.rgb, clamp(pos.y * 0.8 + 0.1, 0.0, 1.0));
 
-    let base = mix(final_color, fog_color, fog);
+    let base = mix(color, fog_color, fog_amount);
+    // fade into the sky at the end of the render distance, the sky itself stays untouched
     let over = clamp((depth - view_dist) / (view_dist * 0.05), 0.0, 1.0);
-    return mix(base, SKY_COLOR, over);
+    return mix(base, sky_color(view_ray(pos)), over);
+}
+
+// Has to match config::Tonemapper.
+const TONEMAP_EXPONENTIAL: u32 = 0u;
+const TONEMAP_ACES: u32 = 1u;
+const TONEMAP_AGX: u32 = 2u;
+
+fn tonemap(color: vec3<f32>, exposure: f32, curve: u32) -> vec3<f32> {
+    let exposed = color * exposure;
+    switch curve {
+        case TONEMAP_ACES: {
+            return aces(exposed);
+        }
+        case TONEMAP_AGX: {
+            return agx(exposed);
+        }
+        default: {
+            return vec3(1.0) - exp(-exposed);
+        }
+    }
+}
+
+// The ACES filmic curve, fitted by Krzysztof Narkowicz.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let numerator = color * (2.51 * color + 0.03);
+    let denominator = color * (2.43 * color + 0.59) + 0.14;
+    return clamp(numerator / denominator, vec3(0.0), vec3(1.0));
+}
+
+// AgX with the polynomial fit of its default contrast curve by Benjamin Wrensch.
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    let outset = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    // into the log space the curve is defined in
+    var x = clamp(log2(max(inset * color, vec3(1e-10))), vec3(min_ev), vec3(max_ev));
+    x = (x - min_ev) / (max_ev - min_ev);
+
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    x = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+        - 0.00232;
+
+    // the curve ends up gamma encoded, the surface expects linear colors
+    return pow(max(outset * x, vec3(0.0)), vec3(2.2));
+}
+
+// params: contrast, saturation, brightness
+fn color_grading(color: vec3<f32>, params: vec4<f32>, tint: vec3<f32>) -> vec3<f32> {
+    let luma = rgb_to_luma(color);
+    var graded = mix(vec3(luma), color, params.y);
+    graded = (graded - 0.5) * params.x + 0.5 + params.z;
+    return clamp(graded * tint, vec3(0.0), vec3(1.0));
 }
 
 fn image(pos: vec2<f32>) -> vec3<f32> {
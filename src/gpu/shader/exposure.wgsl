@@ -0,0 +1,23 @@
+// Measures the average luminance of the scene for the auto exposure, see exposure.rs.
+#include "full_screen.wgsl"
+
+@group(0) @binding(0) var scene: texture_2d<f32>;
+
+// The scene is read in a grid of this many pixels per side.
+const GRID: u32 = 16u;
+
+// Writes the log2 of the average luminance. The pipeline blends it with the value of the earlier
+// frames, so the exposure adapts over time.
+@fragment fn average_luminance(in: PostProcessingOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(scene));
+    var sum = 0.0;
+    for (var x = 0u; x < GRID; x++) {
+        for (var y = 0u; y < GRID; y++) {
+            let pixel = vec2<i32>((vec2(f32(x), f32(y)) + 0.5) / f32(GRID) * size);
+            let color = textureLoad(scene, pixel, 0).rgb;
+            // averaging the logarithm keeps a few very bright pixels from darkening everything
+            sum += log2(max(dot(color, vec3(0.2126, 0.7152, 0.0722)), 1e-4));
+        }
+    }
+    return vec4(sum / f32(GRID * GRID), 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,21 @@
+struct PostProcessingOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+@vertex fn full_screen_quat(@builtin(vertex_index) vertex_index: u32) -> PostProcessingOutput {
+    var uv_coords: array<vec2<f32>, 6>;
+    uv_coords[0] = vec2<f32>(0.0, 0.0);
+    uv_coords[1] = vec2<f32>(1.0, 0.0);
+    uv_coords[2] = vec2<f32>(0.0, 1.0);
+    uv_coords[3] = vec2<f32>(0.0, 1.0);
+    uv_coords[4] = vec2<f32>(1.0, 0.0);
+    uv_coords[5] = vec2<f32>(1.0, 1.0);
+
+    let uv = uv_coords[vertex_index];
+    var output: PostProcessingOutput;
+    output.position = vec4<f32>(2.0 * uv.x - 1.0, 2.0 * uv.y - 1.0, 0.0, 1.0);
+    // output.tex_coords = uv;
+    output.tex_coords = vec2<f32>(uv.x, 1.0 - uv.y);
+    return output;
+}
@@ -0,0 +1,42 @@
+// Builds the depth pyramid for the occlusion culling. Every texel holds the furthest depth
+// of the texels it covers in the level below, which is the smallest value with reverse z.
+
+// Has to match hi_z::WORKGROUP_SIZE.
+const WORKGROUP_SIZE: u32 = 8u;
+
+@group(0) @binding(0) var depth: texture_depth_2d;
+@group(0) @binding(1) var dst: texture_storage_2d<r32float, write>;
+@group(0) @binding(2) var src: texture_2d<f32>;
+
+// The first level is a copy of the depth buffer.
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE) fn copy_depth(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(dst)) {
+        return;
+    }
+    textureStore(dst, id.xy, vec4(textureLoad(depth, id.xy, 0), 0.0, 0.0, 1.0));
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE) fn downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(dst);
+    if any(id.xy >= size) {
+        return;
+    }
+    let src_size = textureDimensions(src);
+    // with an odd size the last texel also covers the one which would otherwise be dropped
+    var end = id.xy * 2u + 1u;
+    if id.x + 1u == size.x && src_size.x % 2u == 1u {
+        end.x += 1u;
+    }
+    if id.y + 1u == size.y && src_size.y % 2u == 1u {
+        end.y += 1u;
+    }
+    end = min(end, src_size - 1u);
+
+    var furthest = 1.0;
+    for (var x = id.x * 2u; x <= end.x; x++) {
+        for (var y = id.y * 2u; y <= end.y; y++) {
+            furthest = min(furthest, textureLoad(src, vec2(x, y), 0).r);
+        }
+    }
+    textureStore(dst, id.xy, vec4(furthest, 0.0, 0.0, 1.0));
+}
@@ -0,0 +1,46 @@
+// The debug screen of hud.rs, quads in screen space showing a cell of the font atlas each.
+
+@group(0) @binding(0) var font_atlas: texture_2d<f32>;
+
+// has to match font.rs
+const CELL_SIZE = vec2<f32>(6.0, 8.0);
+
+struct HudOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // the position inside the cell in atlas pixels
+    @location(0) local: vec2<f32>,
+    @location(1) @interpolate(flat) cell: u32,
+    @location(2) color: vec4<f32>,
+}
+
+@vertex fn vs_hud(
+    @builtin(vertex_index) vertex: u32,
+    // the top left and bottom right corner in clip space
+    @location(0) rect: vec4<f32>,
+    @location(1) cell: u32,
+    @location(2) color: vec4<f32>,
+) -> HudOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[vertex];
+    var out: HudOutput;
+    out.clip_position = vec4<f32>(mix(rect.xy, rect.zw, corner), 0.0, 1.0);
+    out.local = corner * CELL_SIZE;
+    out.cell = cell;
+    out.color = color;
+    return out;
+}
+
+@fragment fn fs_hud(in: HudOutput) -> @location(0) vec4<f32> {
+    // the edge of the quad must not reach into the next cell
+    let local = min(vec2<u32>(in.local), vec2<u32>(CELL_SIZE) - 1u);
+    let texel = vec2<u32>(in.cell * u32(CELL_SIZE.x), 0u) + local;
+    let coverage = textureLoad(font_atlas, texel, 0).r;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
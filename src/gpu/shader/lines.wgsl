@@ -0,0 +1,18 @@
+// The debug lines of chunk_overlay.rs.
+#include "camera.wgsl"
+
+struct LineOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+@vertex fn vs_lines(@location(0) pos: vec3<f32>, @location(1) color: vec3<f32>) -> LineOutput {
+    var out: LineOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(pos, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment fn fs_lines(in: LineOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
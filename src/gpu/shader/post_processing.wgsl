@@ -1,41 +1,104 @@
-struct PostProcessingOutput {
-    @builtin(position) position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-}
-
-@vertex fn full_screen_quat(@builtin(vertex_index) vertex_index: u32) -> PostProcessingOutput {
-    var uv_coords: array<vec2<f32>, 6>;
-    uv_coords[0] = vec2<f32>(0.0, 0.0);
-    uv_coords[1] = vec2<f32>(1.0, 0.0);
-    uv_coords[2] = vec2<f32>(0.0, 1.0);
-    uv_coords[3] = vec2<f32>(0.0, 1.0);
-    uv_coords[4] = vec2<f32>(1.0, 0.0);
-    uv_coords[5] = vec2<f32>(1.0, 1.0);
-
-    let uv = uv_coords[vertex_index];
-    var output: PostProcessingOutput;
-    output.position = vec4<f32>(2.0 * uv.x - 1.0, 2.0 * uv.y - 1.0, 0.0, 1.0);
-    // output.tex_coords = uv;
-    output.tex_coords = vec2<f32>(uv.x, 1.0 - uv.y);
-    return output;
-}
+#include "camera.wgsl"
+#include "full_screen.wgsl"
+#include "sky.wgsl"
+#include "effects.wgsl"
 
 @group(0) @binding(0) var prev_img: texture_2d<f32>;
 @group(0) @binding(1) var prev_img_s: sampler;
+@group(0) @binding(2) var depth_img: texture_depth_2d;
+@group(0) @binding(3) var depth_img_s: sampler;
+
+// group 1 is the camera, group 2 the sky
+
+// Has to match the constants in post_processing.rs.
+const POST_COPY: u32 = 0u;
+const POST_FXAA: u32 = 1u;
+const POST_VIGNETTE: u32 = 2u;
+const POST_FOG: u32 = 3u;
+const POST_TONEMAP: u32 = 4u;
+const POST_COLOR_GRADING: u32 = 5u;
+const POST_BLOOM: u32 = 6u;
+const POST_SSAO: u32 = 7u;
+
+struct PostEffect {
+    kind: u32,
+    params: vec4<f32>,
+    params2: vec4<f32>,
+};
 
-@group(1) @binding(0) var depth_img: texture_depth_2d;
-@group(1) @binding(1) var depth_img_s: sampler;
+// The effect of the current pass, bound with a dynamic offset.
+@group(3) @binding(0) var<uniform> post_effect: PostEffect;
+// The log2 of the average luminance in a single texel, see exposure.rs.
+@group(3) @binding(1) var average_luminance: texture_2d<f32>;
+// The first level of the bloom chain, see bloom.rs.
+@group(3) @binding(2) var bloom_img: texture_2d<f32>;
+@group(3) @binding(3) var effect_s: sampler;
+// The blurred ambient occlusion, see ssao.rs.
+@group(3) @binding(4) var ssao_img: texture_2d<f32>;
+
+// The brightness the auto exposure maps the average luminance to.
+const MIDDLE_GRAY: f32 = 0.18;
 
 @fragment fn post_processing(in: PostProcessingOutput) -> @location(0) vec4<f32> {
     let pos = in.tex_coords;
-    let depth_sample = textureSample(depth_img, depth_img_s, pos);
-    let depth = linearize_depth(1. - depth_sample, 0.1, 10000.0);
-    let color = textureSample(prev_img, prev_img_s, pos).rgb;
+    let color = image(pos);
+
+    var result: vec3<f32>;
+    switch post_effect.kind {
+        case POST_FXAA: {
+            result = fxaa(pos, color);
+        }
+        case POST_VIGNETTE: {
+            result = vignette(pos, color, post_effect.params.x, post_effect.params.y);
+        }
+        case POST_FOG: {
+            let depth = textureSample(depth_img, depth_img_s, pos);
+            result = fog(pos, color, view_distance(pos, depth), post_effect.params);
+        }
+        case POST_TONEMAP: {
+            // params: exposure, the curve, whether the exposure is automatic
+            result = tonemap(
+                color,
+                exposure(post_effect.params.x, post_effect.params.z > 0.5),
+                u32(post_effect.params.y),
+            );
+        }
+        case POST_BLOOM: {
+            result = color + textureSample(bloom_img, effect_s, pos).rgb * post_effect.params.y;
+        }
+        case POST_SSAO: {
+            result = color * pow(textureSample(ssao_img, effect_s, pos).r, post_effect.params.x);
+        }
+        case POST_COLOR_GRADING: {
+            result = color_grading(color, post_effect.params, post_effect.params2.rgb);
+        }
+        default: {
+            result = color;
+        }
+    }
+    return vec4<f32>(result, 1.0);
+}
+
+fn exposure(base: f32, auto_exposure: bool) -> f32 {
+    if !auto_exposure {
+        return base;
+    }
+    let average = exp2(textureLoad(average_luminance, vec2(0, 0), 0).r);
+    return base * MIDDLE_GRAY / max(average, 1e-4);
+}
 
-    return vec4<f32>(apply_effects(pos, color, depth), 1.0);
+// The projection is an infinite reverse z one: depth = near / z, with 0 being infinitely far away.
+// Returns the distance along the view direction. Mirrored in the tests of projection.rs.
+fn linearize_depth(depth: f32, near: f32) -> f32 {
+    return near / max(depth, 1e-7);
 }
 
-fn linearize_depth(depth: f32, near: f32, far: f32) -> f32 {
-    // WGPU uses 0..1 NDC depth (D3D/Vulkan), so no remap to [-1,1].
-    return (near * far) / (far - depth * (far - near)); // view-space distance
+// The distance from the camera to what is visible at `pos`. Mirrored in the tests of projection.rs.
+fn view_distance(pos: vec2<f32>, depth: f32) -> f32 {
+    if depth <= 0.0 {
+        return 1e20; // the sky
+    }
+    let ndc = vec2(pos.x * 2.0 - 1.0, 1.0 - pos.y * 2.0);
+    let view_pos = camera.inverse_proj * vec4(ndc, depth, 1.0);
+    return length(view_pos.xyz / view_pos.w);
 }
@@ -1,20 +1,88 @@
-struct CameraUniform {
-    view_proj: mat4x4<f32>
+#include "camera.wgsl"
+#include "chunk_table.wgsl"
+#include "helpers/randomness.wgsl"
+
+struct ShadingUniform {
+    ambient_occlusion: u32,
+    debug_view: u32,
 };
+
+// Has to match debug_view::DebugView.
+const DEBUG_LOD: u32 = 1u;
+const DEBUG_CHUNK_ID: u32 = 2u;
+const DEBUG_FACE_ORIENTATION: u32 = 3u;
+const DEBUG_DEPTH: u32 = 5u;
+
+// What one draw adds to a pixel of the overdraw heatmap.
+const OVERDRAW_STEP: vec3<f32> = vec3(0.06, 0.03, 0.01);
+
 struct ChunkMetadata {
     pos_lod: vec4<i32>,
     orientation: u32
 }
 
+// Has to match block_table::MAX_BLOCK_TYPES.
+const MAX_BLOCK_TYPES: u32 = 256u;
+
+// Has to match block_table::BLOCK_TRANSLUCENT.
+const BLOCK_TRANSLUCENT: u32 = 1u;
+
+// Set per pipeline by ChunkPass::face_filter. The faces of the other pass are dropped
+// in the vertex shader, so the meshes can be drawn whole by both passes.
+const FACES_ALL: u32 = 0u;
+const FACES_OPAQUE: u32 = 1u;
+const FACES_TRANSLUCENT: u32 = 2u;
+override FACE_FILTER: u32 = FACES_ALL;
+
+// Two vectors per block type, the first six components are the texture layers per orientation,
+// the seventh the flags.
+struct BlockTable {
+    faces: array<vec4<u32>, MAX_BLOCK_TYPES * 2u>
+};
+
 @group(0) @binding(0) var tex_array: texture_2d_array<f32>;
 @group(0) @binding(1) var smp: sampler;
+@group(0) @binding(2) var<uniform> block_table: BlockTable;
+
+@group(1) @binding(1) var<uniform> shading_settings: ShadingUniform;
+
+// Has to match config::MAX_SHADOW_CASCADES.
+const MAX_SHADOW_CASCADES: u32 = 4u;
+
+struct LightingUniform {
+    cascades: array<mat4x4<f32>, MAX_SHADOW_CASCADES>,
+    splits: vec4<f32>, // the far end of every cascade in view depth
+    light_direction: vec4<f32>, // the sun or the moon
+    light_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    camera_pos: vec4<f32>,
+    camera_dir: vec4<f32>,
+    cascade_count: u32,
+    shadow_map_size: f32,
+};
 
-@group(1) @binding(0) var<uniform> camera: CameraUniform;
+@group(2) @binding(0) var shadow_map: texture_depth_2d_array;
+@group(2) @binding(1) var shadow_sampler: sampler_comparison;
+@group(2) @binding(2) var<uniform> lighting: LightingUniform;
 
+#ifdef PUSH_CONSTANTS
 var<push_constant> chunk_metadata: ChunkMetadata;
 
+// Used by the indirect draws instead of `chunk_metadata`, one per vertex buffer.
+struct IndirectMetadata {
+    table_base: u32, // the chunk table entry of the first slot of the buffer
+    faces_per_slot: u32,
+};
+var<push_constant> indirect_metadata: IndirectMetadata;
+@group(3) @binding(0) var<storage, read> chunk_table: array<ChunkEntry>;
+#else
+// Every draw binds its own entry with a dynamic offset, see chunk_uniforms.rs.
+@group(3) @binding(0) var<uniform> chunk_metadata: ChunkMetadata;
+#endif
+
 struct InstanceInput {
-    @location(1) kind: u32
+    @location(1) kind: u32,
+    @location(2) ambient_occlusion: u32, // 2 bits per corner, 3 = unoccluded
 };
 
 struct VertexOutput {
@@ -22,6 +90,9 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @interpolate(flat) @location(1) texture_index: u32, // contains the texture index
     @interpolate(flat) @location(2) orientation: u32,
+    @location(3) ambient_occlusion: f32,
+    @location(4) world_pos: vec3<f32>,
+    @interpolate(flat) @location(5) pos_lod: vec4<i32>, // the chunk, for the debug views
 }
 
 const QUAD_UVS: array<vec2<f32>, 4> = array(
@@ -61,6 +132,34 @@ const FACE_V_AXES: array<vec3<f32>, 6> = array(
 @vertex fn vs_main(
     @builtin(vertex_index) vertex_index: u32,
     instance: InstanceInput,
+) -> VertexOutput {
+    return chunk_vertex(vertex_index, instance, chunk_metadata.pos_lod, chunk_metadata.orientation);
+}
+
+#ifdef PUSH_CONSTANTS
+// The whole vertex buffer is bound, so the instance index tells the slot and with it the chunk.
+@vertex fn vs_indirect(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let chunk = chunk_table[indirect_metadata.table_base + instance_index / indirect_metadata.faces_per_slot];
+    let face = instance_index - chunk.first_face;
+    var orientation = 0u;
+    for (var axis = 1u; axis < 6u; axis++) {
+        if chunk.offsets[axis] <= face {
+            orientation = axis;
+        }
+    }
+    return chunk_vertex(vertex_index, instance, chunk.pos_lod, orientation);
+}
+#endif
+
+fn chunk_vertex(
+    vertex_index: u32,
+    instance: InstanceInput,
+    pos_lod: vec4<i32>,
+    orientation: u32,
 ) -> VertexOutput {
     let strip_vertex = vertex_index & 3u;
     var corner: u32;
@@ -71,9 +170,12 @@ const FACE_V_AXES: array<vec3<f32>, 6> = array(
     } else {
         corner = 5u - strip_vertex;
     }
+    // split the quad along the brighter diagonal so the occlusion is interpolated symmetrically
+    let ao = instance.ambient_occlusion;
+    if ((ao & 3u) + ((ao >> 4u) & 3u)) > (((ao >> 2u) & 3u) + ((ao >> 6u) & 3u)) {
+        corner = (corner + 1u) & 3u;
+    }
 
-
-    let orientation = chunk_metadata.orientation;
     let tex_coords = QUAD_UVS[corner];
     let vertex_position =
         FACE_ORIGINS[orientation]
@@ -84,53 +186,149 @@ const FACE_V_AXES: array<vec3<f32>, 6> = array(
     out.tex_coords = tex_coords;
 
     out.orientation = orientation;
-    out.texture_index = instance.kind & 16383;
-    let lod = u32(chunk_metadata.pos_lod.w);
-    out.clip_position = camera.view_proj * vec4<f32>(
-        (vec3<f32>(
-            f32(chunk_metadata.pos_lod.x),
-            f32(chunk_metadata.pos_lod.y),
-            f32(chunk_metadata.pos_lod.z)
+    out.ambient_occlusion = f32((ao >> (2u * corner)) & 3u) / 3.0;
+    out.texture_index = face_texture(instance.kind & 16383u, orientation);
+    out.pos_lod = pos_lod;
+    let lod = u32(pos_lod.w);
+    out.world_pos = (vec3<f32>(
+            f32(pos_lod.x),
+            f32(pos_lod.y),
+            f32(pos_lod.z)
         ) * 32
         + vec3<f32>(
             f32((instance.kind >> 27) & 31u),
             f32((instance.kind >> 22) & 31u),
             f32((instance.kind >> 17) & 31u)
-        ) + vertex_position) * f32(1u << lod),
-        1.0
-    );
+        ) + vertex_position) * f32(1u << lod);
+    out.clip_position = camera.view_proj * vec4<f32>(out.world_pos, 1.0);
+    let translucent = block_flags(instance.kind & 16383u) & BLOCK_TRANSLUCENT;
+    if (FACE_FILTER == FACES_OPAQUE && translucent != 0u)
+        || (FACE_FILTER == FACES_TRANSLUCENT && translucent == 0u) {
+        // all four corners outside of the clip volume, the face covers no pixel
+        out.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+    }
     return out;
 }
 
+fn face_texture(block: u32, orientation: u32) -> u32 {
+    if block >= MAX_BLOCK_TYPES {
+        return block;
+    }
+    return block_table.faces[block * 2u + orientation / 4u][orientation % 4u];
+}
+
+fn block_flags(block: u32) -> u32 {
+    if block >= MAX_BLOCK_TYPES {
+        return 0u;
+    }
+    return block_table.faces[block * 2u + 1u].z;
+}
+
+const FACE_NORMALS: array<vec3<f32>, 6> = array(
+    vec3(-1.0, 0.0, 0.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(0.0, 0.0, 1.0),
+);
+
+const LOD_COLORS: array<vec3<f32>, 6> = array(
+    vec3(1.0, 0.3, 0.3),
+    vec3(0.3, 1.0, 0.3),
+    vec3(0.3, 0.3, 1.0),
+    vec3(1.0, 1.0, 0.3),
+    vec3(1.0, 0.3, 1.0),
+    vec3(0.3, 1.0, 1.0),
+);
+
+// 1 = fully lit, 0 = in shadow. Beyond the last cascade everything is lit.
+fn sun_visibility(world_pos: vec3<f32>) -> f32 {
+    let depth = dot(world_pos - lighting.camera_pos.xyz, lighting.camera_dir.xyz);
+    var cascade = 0u;
+    loop {
+        if cascade >= lighting.cascade_count || depth <= lighting.splits[cascade] {
+            break;
+        }
+        cascade += 1u;
+    }
+    if cascade >= lighting.cascade_count {
+        return 1.0;
+    }
+
+    let light_pos = lighting.cascades[cascade] * vec4<f32>(world_pos, 1.0);
+    let uv = light_pos.xy * vec2(0.5, -0.5) + 0.5;
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) || light_pos.z > 1.0 {
+        return 1.0;
+    }
+
+    // 3x3 percentage closer filtering
+    let texel = 1.0 / lighting.shadow_map_size;
+    var visibility = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            visibility += textureSampleCompareLevel(
+                shadow_map,
+                shadow_sampler,
+                uv + vec2(f32(x), f32(y)) * texel,
+                cascade,
+                light_pos.z
+            );
+        }
+    }
+    return visibility / 9.0;
+}
+
+// Opaque blocks, the alpha channel only cuts out holes. Translucent ones follow in their own pass.
 @fragment fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var color = textureSample(tex_array, smp, in.tex_coords, in.texture_index);
+    let color = textureSampleBias(tex_array, smp, in.tex_coords, in.texture_index, MIP_BIAS);
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4<f32>(shade(in, color.rgb), 1.0);
+}
 
+// Translucent blocks, drawn back to front over the opaque ones and blended by their alpha.
+@fragment fn fs_translucent(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleBias(tex_array, smp, in.tex_coords, in.texture_index, MIP_BIAS);
     if color.a == 0.0 {
         discard;
     }
-    var shading: f32;
-    if in.orientation == 0u {
-        shading = 0.8;
-    } else if in.orientation == 1u {
-        shading = 0.3;
-    } else if in.orientation == 2u {
-        shading = 0.5;
-    } else if in.orientation == 3u {
-        shading = 0.5;
-    } else if in.orientation == 4u {
-        shading = 0.5;
-    } else if in.orientation == 5u {
-        shading = 0.5;
+    return vec4<f32>(shade(in, color.rgb), color.a);
+}
+
+// Every face adds up, the pipeline blends additively and has no depth test.
+@fragment fn fs_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(OVERDRAW_STEP, 1.0);
+}
+
+fn shade(in: VertexOutput, color: vec3<f32>) -> vec3<f32> {
+    switch shading_settings.debug_view {
+        case DEBUG_LOD: {
+            return LOD_COLORS[u32(in.pos_lod.w) % 6u] * (0.6 + 0.4 * in.ambient_occlusion);
+        }
+        case DEBUG_CHUNK_ID: {
+            return random3(vec3<f32>(in.pos_lod.xyz), f32(in.pos_lod.w) + 1.0);
+        }
+        case DEBUG_FACE_ORIENTATION: {
+            return FACE_NORMALS[in.orientation] * 0.5 + 0.5;
+        }
+        case DEBUG_DEPTH: {
+            let dist = length(in.world_pos - camera.position.xyz);
+            return vec3(1.0 - clamp(dist / (RENDER_DISTANCE * 32.0), 0.0, 1.0));
+        }
+        default: {}
     }
 
-    /*var tint: vec3<f32>;
-    if in.lod_level == 0 {
-        tint = vec3(2., 0.5, 0.5);
-    } else if in.lod_level == 1 {
-        tint = vec3(0.5, 2., 0.5);
-    } else {
-        tint = vec3(0.5, 0.5, 2.);
-    }*/
+    let sun = max(dot(FACE_NORMALS[in.orientation], lighting.light_direction.xyz), 0.0);
+    var shading = lighting.ambient_color.rgb;
+    if sun > 0.0 {
+        shading += lighting.light_color.rgb * sun * sun_visibility(in.world_pos);
+    }
+
+    if shading_settings.ambient_occlusion != 0u {
+        shading *= 0.4 + 0.6 * in.ambient_occlusion;
+    }
 
-    return vec4<f32>(shading * color.rgb, color.a);
+    return shading * color;
 }
@@ -0,0 +1,61 @@
+#include "camera.wgsl"
+#include "helpers/randomness.wgsl"
+
+struct SkyUniform {
+    sun_direction: vec4<f32>,
+    moon_direction: vec4<f32>,
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>,
+    fog_color_low: vec4<f32>,
+    fog_color_high: vec4<f32>,
+    time_of_day: f32, // 0 = midnight, 0.5 = noon
+    star_visibility: f32,
+};
+
+@group(2) @binding(0) var<uniform> sky: SkyUniform;
+
+const SUN_DISK: f32 = 0.9995; // cosine of the angular radius
+const SUN_GLOW: f32 = 0.98;
+const MOON_DISK: f32 = 0.9996;
+const STAR_DENSITY: f32 = 300.0;
+
+// The world space direction of the pixel at `pos`.
+fn view_ray(pos: vec2<f32>) -> vec3<f32> {
+    let ndc = vec2(pos.x * 2.0 - 1.0, 1.0 - pos.y * 2.0);
+    // reverse z: 1 is the near plane, smaller values are further away
+    let near = camera.inverse_view_proj * vec4(ndc, 1.0, 1.0);
+    let far = camera.inverse_view_proj * vec4(ndc, 0.5, 1.0);
+    return normalize(far.xyz / far.w - near.xyz / near.w);
+}
+
+fn sky_color(dir: vec3<f32>) -> vec3<f32> {
+    let height = dir.y;
+    var color = mix(
+        sky.horizon_color.rgb,
+        sky.zenith_color.rgb,
+        sqrt(clamp(height, 0.0, 1.0))
+    );
+    // below the horizon the sky fades into the fog
+    color = mix(color, sky.fog_color_low.rgb, clamp(-height * 4.0, 0.0, 1.0));
+
+    // stars, at most one per cell of a direction grid, they fade out at dawn
+    let cell = floor(dir * STAR_DENSITY);
+    let star = random3(cell, 1.0);
+    if star.x > 0.997 && height > 0.0 {
+        let twinkle = 0.7 + 0.3 * sin(sky.time_of_day * 5000.0 + star.y * 6.2831);
+        color += vec3(star.z * 0.5 + 0.5) * twinkle * sky.star_visibility;
+    }
+
+    let sun = dot(dir, sky.sun_direction.xyz);
+    let sun_glow = pow(clamp((sun - SUN_GLOW) / (1.0 - SUN_GLOW), 0.0, 1.0), 4.0);
+    color += vec3(1.0, 0.8, 0.5) * sun_glow * 0.5;
+    color = mix(color, vec3(1.0, 0.95, 0.8), smoothstep(SUN_DISK - 0.0002, SUN_DISK, sun));
+
+    let moon = dot(dir, sky.moon_direction.xyz);
+    color = mix(
+        color,
+        vec3(0.8, 0.85, 0.9),
+        smoothstep(MOON_DISK - 0.0002, MOON_DISK, moon) * sky.star_visibility
+    );
+    return color;
+}
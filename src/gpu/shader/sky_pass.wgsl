@@ -0,0 +1,7 @@
+#include "full_screen.wgsl"
+#include "sky.wgsl"
+
+// Drawn at the end of the main pass onto every pixel without terrain.
+@fragment fn sky_main(in: PostProcessingOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(sky_color(view_ray(in.tex_coords)), 1.0);
+}
@@ -0,0 +1,117 @@
+// Screen space ambient occlusion, see ssao.rs. The normals are reconstructed from the depth
+// buffer and the occlusion is sampled in a hemisphere around them. The sample pattern is rotated
+// differently in every pixel of a 4x4 tile, which the blur averages out again.
+#include "camera.wgsl"
+#include "full_screen.wgsl"
+
+@group(0) @binding(2) var depth_img: texture_depth_2d;
+
+// group 1 is the camera
+
+struct SsaoSettings {
+    // the radius as a fraction of the distance to the camera
+    radius: f32,
+    samples: u32,
+};
+
+@group(2) @binding(0) var<uniform> settings: SsaoSettings;
+// The unblurred occlusion, only read by the blur.
+@group(2) @binding(1) var occlusion_img: texture_2d<f32>;
+
+const TAU: f32 = 6.28318530;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+@fragment fn ssao(in: PostProcessingOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let depth = textureLoad(depth_img, pixel, 0);
+    if depth <= 0.0 {
+        return vec4(1.0); // the sky
+    }
+    let pos = view_position(pixel, depth);
+    let normal = reconstruct_normal(pixel, pos);
+    let helper = select(vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), abs(normal.x) > 0.9);
+    let tangent = normalize(cross(normal, helper));
+    let bitangent = cross(normal, tangent);
+    let radius = settings.radius * length(pos);
+
+    let tile = vec2<u32>(pixel) % 4u;
+    let offset = f32(tile.x + tile.y * 4u) / 16.0;
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < settings.samples; i++) {
+        // a spiral over the hemisphere, the distances are spread independently of the directions
+        let height = 1.0 - (f32(i) + 0.5) / f32(settings.samples);
+        let angle = f32(i) * GOLDEN_ANGLE + offset * TAU;
+        let dir = (tangent * cos(angle) + bitangent * sin(angle)) * sqrt(1.0 - height * height)
+            + normal * height;
+        let scale = fract(f32(i) * 0.618034 + offset);
+        let sample_pos = pos + dir * radius * mix(0.1, 1.0, scale * scale);
+
+        let sample_pixel = project(sample_pos);
+        let scene_depth = textureLoad(depth_img, sample_pixel, 0);
+        if scene_depth <= 0.0 {
+            continue;
+        }
+        let scene_pos = view_position(sample_pixel, scene_depth);
+        // occluded if the surface there is in front of the sample, surfaces far in front don't count
+        let range = smoothstep(0.0, 1.0, radius / abs(pos.z - scene_pos.z));
+        occlusion += select(0.0, range, scene_pos.z >= sample_pos.z + radius * 0.02);
+    }
+    return vec4(1.0 - occlusion / f32(settings.samples), 0.0, 0.0, 1.0);
+}
+
+// Averages the 4x4 tile of the sample pattern, but only over pixels at a similar depth.
+@fragment fn ssao_blur(in: PostProcessingOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let size = vec2<i32>(textureDimensions(occlusion_img));
+    let depth = textureLoad(depth_img, pixel, 0);
+
+    var sum = 0.0;
+    var weights = 0.0;
+    for (var x = -2; x < 2; x++) {
+        for (var y = -2; y < 2; y++) {
+            let neighbour = clamp(pixel + vec2(x, y), vec2(0), size - 1);
+            // the depth is inversely proportional to the distance, so this is a relative difference
+            let neighbour_depth = textureLoad(depth_img, neighbour, 0);
+            let weight = select(0.0, 1.0, abs(neighbour_depth - depth) <= depth * 0.05);
+            sum += textureLoad(occlusion_img, neighbour, 0).r * weight;
+            weights += weight;
+        }
+    }
+    return vec4(sum / max(weights, 1.0), 0.0, 0.0, 1.0);
+}
+
+// The position in view space of what is visible in `pixel`.
+fn view_position(pixel: vec2<i32>, depth: f32) -> vec3<f32> {
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(textureDimensions(depth_img));
+    let ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let view_pos = camera.inverse_proj * vec4(ndc, max(depth, 1e-7), 1.0);
+    return view_pos.xyz / view_pos.w;
+}
+
+// The pixel a position in view space ends up in.
+fn project(pos: vec3<f32>) -> vec2<i32> {
+    let clip = camera.proj * vec4(pos, 1.0);
+    let uv = vec2(clip.x / clip.w * 0.5 + 0.5, 0.5 - clip.y / clip.w * 0.5);
+    let size = vec2<i32>(textureDimensions(depth_img));
+    return clamp(vec2<i32>(uv * vec2<f32>(size)), vec2(0), size - 1);
+}
+
+// The normal facing the camera from the differences to the neighbours. On every axis the
+// neighbour closer in depth is used, the other one might be on another surface.
+fn reconstruct_normal(pixel: vec2<i32>, pos: vec3<f32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(depth_img));
+    let left = neighbour_position(clamp(pixel - vec2(1, 0), vec2(0), size - 1));
+    let right = neighbour_position(clamp(pixel + vec2(1, 0), vec2(0), size - 1));
+    let up = neighbour_position(clamp(pixel - vec2(0, 1), vec2(0), size - 1));
+    let down = neighbour_position(clamp(pixel + vec2(0, 1), vec2(0), size - 1));
+
+    let dx = select(pos - left, right - pos, abs(right.z - pos.z) < abs(pos.z - left.z));
+    let dy = select(pos - up, down - pos, abs(down.z - pos.z) < abs(pos.z - up.z));
+    let normal = normalize(cross(dx, dy));
+    return select(normal, -normal, dot(normal, pos) > 0.0);
+}
+
+fn neighbour_position(pixel: vec2<i32>) -> vec3<f32> {
+    return view_position(pixel, textureLoad(depth_img, pixel, 0));
+}
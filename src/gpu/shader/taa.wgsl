@@ -0,0 +1,46 @@
+// Blends the scene with the history of the earlier frames, see anti_aliasing::Taa.
+#include "camera.wgsl"
+#include "full_screen.wgsl"
+
+@group(0) @binding(0) var scene: texture_2d<f32>;
+@group(0) @binding(2) var depth_img: texture_depth_2d;
+
+// group 1 is the camera
+
+@group(2) @binding(0) var history: texture_2d<f32>;
+@group(2) @binding(1) var history_s: sampler;
+
+// How much of the history is kept every frame.
+const HISTORY_WEIGHT: f32 = 0.9;
+
+@fragment fn taa_resolve(in: PostProcessingOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let size = vec2<i32>(textureDimensions(scene));
+    let color = textureLoad(scene, pixel, 0).rgb;
+
+    // the history is clamped to the colors around the pixel, which drops most of what
+    // isn't visible anymore
+    var low = color;
+    var high = color;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let neighbour = textureLoad(scene, clamp(pixel + vec2(x, y), vec2(0), size - 1), 0).rgb;
+            low = min(low, neighbour);
+            high = max(high, neighbour);
+        }
+    }
+
+    // where the surface in this pixel was in the last frame. The sky has a depth of 0,
+    // which is a point at infinity, so only the rotation of the camera moves it.
+    let depth = textureLoad(depth_img, pixel, 0);
+    let ndc = vec2(in.tex_coords.x * 2.0 - 1.0, 1.0 - in.tex_coords.y * 2.0);
+    let world = camera.inverse_view_proj * vec4(ndc, depth, 1.0);
+    let prev_clip = camera.prev_view_proj * world;
+    let prev_ndc = prev_clip.xy / prev_clip.w;
+    let prev_pos = vec2(prev_ndc.x * 0.5 + 0.5, 0.5 - prev_ndc.y * 0.5);
+    if prev_clip.w <= 0.0 || any(prev_pos < vec2(0.0)) || any(prev_pos > vec2(1.0)) {
+        return vec4(color, 1.0);
+    }
+    let prev = clamp(textureSampleLevel(history, history_s, prev_pos, 0.0).rgb, low, high);
+    return vec4(mix(color, prev, HISTORY_WEIGHT), 1.0);
+}
@@ -0,0 +1,428 @@
+//! Directional sun light with cascaded shadow maps.
+//!
+//! Every cascade covers a slice of the view frustum and is rendered with the normal chunk vertex
+//! shader, the cascades only swap the camera bind group for one holding the light matrix.
+//! The chunks drawn into a cascade are selected from its light space box, terrain outside of the
+//! view still casts shadows into it.
+use std::collections::HashSet;
+
+use glam::{IVec4, Mat4, Vec3};
+
+use crate::{
+    config::{MAX_SHADOW_CASCADES, ShadowConfig},
+    gpu::{
+        projection::{CameraUniform, Projection, View, frustum_corners},
+        sky::Light,
+        texture::Texture,
+    },
+};
+
+/// How much the cascade splits follow a logarithmic instead of a uniform distribution.
+const SPLIT_LAMBDA: f32 = 0.75;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct LightingUniform {
+    cascades: [[[f32; 4]; 4]; MAX_SHADOW_CASCADES as usize],
+    /// The far end of every cascade in view space depth.
+    splits: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    ambient_color: [f32; 4],
+    camera_pos: [f32; 4],
+    camera_dir: [f32; 4],
+    cascade_count: u32,
+    shadow_map_size: f32,
+    _padding: [u32; 2],
+}
+
+pub struct ShadowMaps {
+    config: ShadowConfig,
+    /// One view per cascade to render into.
+    pub layer_views: Vec<wgpu::TextureView>,
+    /// One camera bind group per cascade, compatible with the main camera bind group.
+    pub cascade_bind_groups: Vec<wgpu::BindGroup>,
+    cascade_buffers: Vec<wgpu::Buffer>,
+    /// The light matrices of the last update, empty without a view.
+    cascade_matrices: Vec<Mat4>,
+
+    lighting_buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl ShadowMaps {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &ShadowConfig,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        shading_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Maps"),
+            size: wgpu::Extent3d {
+                width: config.map_size,
+                height: config.map_size,
+                // the texture is bound even if shadows are disabled
+                depth_or_array_layers: config.cascades.max(1),
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Map Array View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..config.cascades)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow Map Layer View"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let cascade_buffers: Vec<wgpu::Buffer> = (0..config.cascades)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Shadow Cascade Camera Buffer"),
+                    // only the view projection matrix at the start is used
+                    size: std::mem::size_of::<CameraUniform>() as u64,
+                    mapped_at_creation: false,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                })
+            })
+            .collect();
+        let cascade_bind_groups = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: camera_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: shading_buffer.as_entire_binding(),
+                        },
+                    ],
+                    label: Some("shadow cascade camera bind group"),
+                })
+            })
+            .collect();
+
+        let lighting_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lighting Buffer"),
+            size: std::mem::size_of::<LightingUniform>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Lighting Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lighting Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&array_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: lighting_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            config: config.clone(),
+            layer_views,
+            cascade_bind_groups,
+            cascade_buffers,
+            cascade_matrices: Vec::new(),
+            lighting_buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Fits the cascades around the view and uploads them together with the light.
+    /// Without a view there is nothing to fit the cascades to, so the scene is drawn unshadowed.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        view: Option<View>,
+        proj: &Projection,
+        light: &Light,
+    ) {
+        let light_direction = light.direction.normalize_or_zero();
+        let mut uniform = LightingUniform {
+            cascades: [[[0.; 4]; 4]; MAX_SHADOW_CASCADES as usize],
+            splits: [0.; 4],
+            light_direction: light_direction.extend(0.).to_array(),
+            light_color: light.color.extend(1.).to_array(),
+            ambient_color: light.ambient.extend(1.).to_array(),
+            camera_pos: [0.; 4],
+            camera_dir: [0.; 4],
+            cascade_count: 0,
+            shadow_map_size: self.config.map_size as f32,
+            _padding: [0; 2],
+        };
+        self.cascade_matrices.clear();
+
+        if let Some(view) = view
+            && light_direction != Vec3::ZERO
+        {
+            let splits = cascade_splits(proj.znear, self.config.distance, self.config.cascades);
+            let mut near = proj.znear;
+            for (cascade, far) in splits
+                .iter()
+                .take(self.config.cascades as usize)
+                .enumerate()
+            {
+                let matrix = cascade_matrix(
+                    view,
+                    proj,
+                    near,
+                    *far,
+                    light_direction,
+                    self.config.map_size,
+                    self.config.distance,
+                );
+                uniform.cascades[cascade] = matrix.to_cols_array_2d();
+                self.cascade_matrices.push(matrix);
+                queue.write_buffer(
+                    &self.cascade_buffers[cascade],
+                    0,
+                    bytemuck::cast_slice(&matrix.to_cols_array_2d()),
+                );
+                near = *far;
+            }
+            uniform.splits = splits;
+            uniform.camera_pos = view.pos().extend(1.).to_array();
+            uniform.camera_dir = view.dir().normalize().extend(0.).to_array();
+            uniform.cascade_count = self.config.cascades;
+        }
+
+        queue.write_buffer(&self.lighting_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// The chunks to draw into every cascade. The loaded chunks are kept in all their levels of
+    /// detail, so besides the drawn chunks only the coarsest loaded ones which overlap none of
+    /// them are taken.
+    pub fn casters(
+        &self,
+        drawn: &[voxine::ChunkID],
+        loaded: impl IntoIterator<Item = voxine::ChunkID>,
+    ) -> Vec<Vec<voxine::ChunkID>> {
+        let mut casters = vec![Vec::new(); self.cascade_matrices.len()];
+        if self.cascade_matrices.is_empty() {
+            return casters;
+        }
+        let in_any_cascade = |chunk: &voxine::ChunkID| {
+            let (low, high) = chunk_bounds(chunk);
+            self.cascade_matrices
+                .iter()
+                .any(|matrix| in_light_volume(*matrix, low, high))
+        };
+        let loaded: Vec<_> = loaded.into_iter().filter(in_any_cascade).collect();
+        let max_size = drawn
+            .iter()
+            .chain(&loaded)
+            .map(chunk_size)
+            .max()
+            .unwrap_or(1);
+
+        let drawn_keys: HashSet<_> = drawn
+            .iter()
+            .map(|chunk| region(chunk, chunk_size(chunk)))
+            .collect();
+        // the regions around the drawn chunks, a loaded chunk there overlaps some of them
+        let drawn_parents: HashSet<_> = drawn
+            .iter()
+            .flat_map(|chunk| parents(chunk, max_size))
+            .collect();
+        let undrawn: Vec<_> = loaded
+            .into_iter()
+            .filter(|chunk| {
+                let key = region(chunk, chunk_size(chunk));
+                !drawn_keys.contains(&key)
+                    && !drawn_parents.contains(&key)
+                    && !parents(chunk, max_size).any(|parent| drawn_keys.contains(&parent))
+            })
+            .collect();
+        let undrawn_keys: HashSet<_> = undrawn
+            .iter()
+            .map(|chunk| region(chunk, chunk_size(chunk)))
+            .collect();
+        let coarsest = undrawn
+            .iter()
+            .filter(|chunk| !parents(chunk, max_size).any(|parent| undrawn_keys.contains(&parent)));
+
+        for chunk in drawn.iter().chain(coarsest) {
+            let (low, high) = chunk_bounds(chunk);
+            for (cascade, matrix) in self.cascade_matrices.iter().enumerate() {
+                if in_light_volume(*matrix, low, high) {
+                    casters[cascade].push(*chunk);
+                }
+            }
+        }
+        casters
+    }
+}
+
+/// The side length in chunks of the smallest level of detail.
+fn chunk_size(chunk: &voxine::ChunkID) -> i32 {
+    1 << chunk.lod
+}
+
+/// The corners of the chunk in world space.
+fn chunk_bounds(chunk: &voxine::ChunkID) -> (Vec3, Vec3) {
+    let low = chunk.total_pos().as_vec3() * 32.;
+    (low, low + (32 * chunk_size(chunk)) as f32)
+}
+
+/// The cell of `size` chunks containing the chunk, with the size in `w`.
+fn region(chunk: &voxine::ChunkID, size: i32) -> IVec4 {
+    (chunk.total_pos().as_vec3() / size as f32)
+        .floor()
+        .as_ivec3()
+        .extend(size)
+}
+
+/// The regions of the levels of detail above the chunk, up to `max_size`.
+fn parents(chunk: &voxine::ChunkID, max_size: i32) -> impl Iterator<Item = IVec4> + '_ {
+    std::iter::successors(Some(chunk_size(chunk) * 2), |size| Some(size * 2))
+        .take_while(move |size| *size <= max_size)
+        .map(move |size| region(chunk, size))
+}
+
+/// Whether the box reaches into the volume of the orthographic light matrix.
+fn in_light_volume(matrix: Mat4, low: Vec3, high: Vec3) -> bool {
+    let center = matrix.transform_point3((low + high) * 0.5);
+    let half_size = (high - low) * 0.5;
+    let extent = Vec3::new(
+        matrix.row(0).truncate().abs().dot(half_size),
+        matrix.row(1).truncate().abs().dot(half_size),
+        matrix.row(2).truncate().abs().dot(half_size),
+    );
+    center.x.abs() - extent.x <= 1.
+        && center.y.abs() - extent.y <= 1.
+        && center.z + extent.z >= 0.
+        && center.z - extent.z <= 1.
+}
+
+/// The far end of every cascade, a blend between a logarithmic and a uniform split.
+fn cascade_splits(near: f32, far: f32, count: u32) -> [f32; 4] {
+    let mut splits = [far; 4];
+    for (i, split) in splits.iter_mut().enumerate().take(count as usize) {
+        let t = (i + 1) as f32 / count as f32;
+        let logarithmic = near * (far / near).powf(t);
+        let uniform = near + (far - near) * t;
+        *split = SPLIT_LAMBDA * logarithmic + (1. - SPLIT_LAMBDA) * uniform;
+    }
+    splits
+}
+
+/// An orthographic light matrix enclosing the frustum slice between `near` and `far`.
+/// The slice is wrapped in a sphere and snapped to shadow map texels,
+/// so the shadows don't shimmer when the camera rotates or moves.
+fn cascade_matrix(
+    view: View,
+    proj: &Projection,
+    near: f32,
+    far: f32,
+    sun_direction: Vec3,
+    map_size: u32,
+    caster_distance: f32,
+) -> Mat4 {
+    let corners = frustum_corners(view, proj.fov, proj.aspect, near, far);
+    let center = corners.iter().copied().sum::<Vec3>() / 8.;
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0., f32::max);
+    // quantizing the radius keeps the texel size constant while the camera moves
+    let radius = (radius * 16.).ceil() / 16.;
+
+    let light_up = if sun_direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let light_rotation = Mat4::look_to_rh(Vec3::ZERO, -sun_direction, light_up);
+    let texel_size = 2. * radius / map_size as f32;
+    let mut center_light_space = light_rotation.transform_point3(center);
+    center_light_space.x = (center_light_space.x / texel_size).floor() * texel_size;
+    center_light_space.y = (center_light_space.y / texel_size).floor() * texel_size;
+    let center = light_rotation
+        .inverse()
+        .transform_point3(center_light_space);
+
+    // terrain between the slice and the sun still casts shadows into it
+    let eye = center + sun_direction * (radius + caster_distance);
+    let light_view = Mat4::look_to_rh(eye, -sun_direction, light_up);
+    let light_proj = Mat4::orthographic_rh(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.,
+        2. * radius + caster_distance,
+    );
+    light_proj * light_view
+}
@@ -0,0 +1,188 @@
+//! The time of day and everything that depends on it: the sun and moon, the procedural sky,
+//! the fog colors and the light the terrain is shaded with.
+use glam::{Quat, Vec3};
+use web_time::Instant;
+
+use crate::config::{DayCycleConfig, LightingConfig};
+
+/// How many seconds a day takes at a time speed of 1.
+pub const DAY_LENGTH: f64 = 1200.;
+
+const DAY_ZENITH: Vec3 = Vec3::new(0.22, 0.45, 0.85);
+const DAY_HORIZON: Vec3 = Vec3::new(0.6, 0.75, 0.9);
+const SUNSET_HORIZON: Vec3 = Vec3::new(0.95, 0.45, 0.2);
+const NIGHT_ZENITH: Vec3 = Vec3::new(0.005, 0.008, 0.025);
+const NIGHT_HORIZON: Vec3 = Vec3::new(0.03, 0.04, 0.07);
+
+const SUN_COLOR: Vec3 = Vec3::new(1.0, 0.95, 0.85);
+const SUNSET_SUN_COLOR: Vec3 = Vec3::new(1.0, 0.55, 0.3);
+const MOON_COLOR: Vec3 = Vec3::new(0.25, 0.3, 0.45);
+const DAY_AMBIENT: Vec3 = Vec3::new(0.9, 0.95, 1.0);
+const NIGHT_AMBIENT: Vec3 = Vec3::new(0.12, 0.14, 0.25);
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct SkyUniform {
+    sun_direction: [f32; 4],
+    moon_direction: [f32; 4],
+    zenith_color: [f32; 4],
+    horizon_color: [f32; 4],
+    fog_color_low: [f32; 4],
+    fog_color_high: [f32; 4],
+    /// 0 = midnight, 0.5 = noon.
+    time_of_day: f32,
+    star_visibility: f32,
+    _padding: [f32; 2],
+}
+
+/// The light the terrain is shaded with, either the sun or, at night, the moon.
+pub struct Light {
+    /// Points towards the light source.
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub ambient: Vec3,
+}
+
+pub struct Sky {
+    config: DayCycleConfig,
+    /// 0 = midnight, 0.25 = sunrise, 0.5 = noon, 0.75 = sunset.
+    time: f64,
+    last_update: Instant,
+
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Sky {
+    pub fn new(device: &wgpu::Device, config: &DayCycleConfig) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sky Buffer"),
+            size: std::mem::size_of::<SkyUniform>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sky Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sky Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            config: config.clone(),
+            time: config.start_time as f64,
+            last_update: Instant::now(),
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn set_config(&mut self, config: DayCycleConfig) {
+        self.config = config;
+    }
+
+    /// The current time of day, `fixed-time` if it is set.
+    pub fn time_of_day(&self) -> f32 {
+        self.config
+            .fixed_time
+            .unwrap_or(self.time as f32)
+            .rem_euclid(1.)
+    }
+
+    /// Advances the time, uploads the sky and returns the light for this frame.
+    pub fn update(&mut self, queue: &wgpu::Queue, lighting: &LightingConfig) -> Light {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+        self.time = (self.time + elapsed * self.config.time_speed / DAY_LENGTH).rem_euclid(1.);
+
+        let time_of_day = self.time_of_day();
+        let sun_direction = sun_direction(
+            time_of_day,
+            self.config.sun_azimuth.to_radians(),
+            self.config.sun_tilt.to_radians(),
+        );
+        let moon_direction = -sun_direction;
+
+        let elevation = sun_direction.y;
+        let day = smoothstep(-0.1, 0.2, elevation);
+        // strongest while the sun is at the horizon
+        let twilight = (1. - elevation.abs() / 0.25).clamp(0., 1.);
+
+        let zenith = NIGHT_ZENITH.lerp(DAY_ZENITH, day);
+        let horizon = NIGHT_HORIZON
+            .lerp(DAY_HORIZON, day)
+            .lerp(SUNSET_HORIZON, twilight * 0.6);
+
+        // the light fades out at the horizon, so switching to the moon isn't visible
+        let (direction, color) = if elevation > 0. {
+            (
+                sun_direction,
+                SUN_COLOR.lerp(SUNSET_SUN_COLOR, twilight) * smoothstep(0., 0.15, elevation),
+            )
+        } else {
+            (
+                moon_direction,
+                MOON_COLOR * smoothstep(0., 0.15, -elevation),
+            )
+        };
+        let light = Light {
+            direction,
+            color: color * lighting.sun_intensity,
+            ambient: NIGHT_AMBIENT
+                .lerp(DAY_AMBIENT, day)
+                .lerp(horizon, twilight * 0.3)
+                * lighting.ambient,
+        };
+
+        let uniform = SkyUniform {
+            sun_direction: sun_direction.extend(0.).to_array(),
+            moon_direction: moon_direction.extend(0.).to_array(),
+            zenith_color: zenith.extend(1.).to_array(),
+            horizon_color: horizon.extend(1.).to_array(),
+            fog_color_low: (horizon * 0.6).extend(1.).to_array(),
+            fog_color_high: horizon.extend(1.).to_array(),
+            time_of_day,
+            star_visibility: 1. - smoothstep(-0.2, 0.05, elevation),
+            _padding: [0.; 2],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        light
+    }
+}
+
+/// Without azimuth the sun rises in +X and sets in -X, the azimuth turns its path around Y.
+/// The tilt leans the path to the right of the sunrise, at noon it stands `tilt` away from the
+/// zenith, so shadows aren't perfectly axis aligned.
+fn sun_direction(time_of_day: f32, azimuth: f32, tilt: f32) -> Vec3 {
+    let angle = (time_of_day - 0.25) * std::f32::consts::TAU;
+    let path = Vec3::new(
+        angle.cos(),
+        angle.sin() * tilt.cos(),
+        angle.sin() * tilt.sin(),
+    );
+    Quat::from_rotation_y(-azimuth) * path
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
@@ -0,0 +1,182 @@
+//! Screen space ambient occlusion. Unlike the per vertex occlusion of `ambient_occlusion` it also
+//! darkens the distant terrain, whose coarse levels of detail lose the small creases. One pass
+//! samples the depth buffer around every pixel, a second one blurs the noisy result, and the
+//! post-processing multiplies the scene with it, see `POST_SSAO` in post_processing.wgsl.
+use crate::gpu::pipelines::SsaoPipelines;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct SsaoUniform {
+    radius: f32,
+    samples: u32,
+    _padding: [u32; 2],
+}
+
+pub struct Ssao {
+    /// The unblurred occlusion.
+    raw: wgpu::TextureView,
+    raw_bind_group: wgpu::BindGroup,
+    /// The blurred occlusion, which the post-processing samples.
+    pub view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    settings_bind_group: wgpu::BindGroup,
+    pub settings_layout: wgpu::BindGroupLayout,
+    pub raw_layout: wgpu::BindGroupLayout,
+}
+
+impl Ssao {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let settings_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Settings Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        // binding 1, so both can share a group in ssao.wgsl
+        let raw_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Blur Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSAO Settings Buffer"),
+            size: std::mem::size_of::<SsaoUniform>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let settings_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Settings Bind Group"),
+            layout: &settings_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        let (raw, raw_bind_group, view) = Self::targets(device, config, &raw_layout);
+        Self {
+            raw,
+            raw_bind_group,
+            view,
+            buffer,
+            settings_bind_group,
+            settings_layout,
+            raw_layout,
+        }
+    }
+
+    /// Has to be called whenever the surface is resized.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.raw, self.raw_bind_group, self.view) =
+            Self::targets(device, config, &self.raw_layout);
+    }
+
+    fn targets(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        raw_layout: &wgpu::BindGroupLayout,
+    ) -> (wgpu::TextureView, wgpu::BindGroup, wgpu::TextureView) {
+        let target = |label| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: config.width.max(1),
+                        height: config.height.max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: Self::FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let raw = target("SSAO");
+        let raw_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Blur Bind Group"),
+            layout: raw_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&raw),
+            }],
+        });
+        (raw, raw_bind_group, target("Blurred SSAO"))
+    }
+
+    pub fn set_settings(&self, queue: &wgpu::Queue, radius: f32, samples: u32) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[SsaoUniform {
+                radius,
+                samples,
+                _padding: [0; 2],
+            }]),
+        );
+    }
+
+    /// Computes the occlusion from the depth in `scene`, a render target bind group.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipelines: &SsaoPipelines,
+        scene: &wgpu::BindGroup,
+        camera: &wgpu::BindGroup,
+    ) {
+        for (target, pipeline, bind_group, label) in [
+            (
+                &self.raw,
+                &pipelines.occlusion,
+                &self.settings_bind_group,
+                "Render Pass - SSAO",
+            ),
+            (
+                &self.view,
+                &pipelines.blur,
+                &self.raw_bind_group,
+                "Render Pass - SSAO Blur",
+            ),
+        ] {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, scene, &[]);
+            pass.set_bind_group(1, camera, &[]);
+            pass.set_bind_group(2, bind_group, &[]);
+            pass.draw(0..6, 0..1);
+        }
+    }
+}
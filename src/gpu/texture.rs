@@ -1,4 +1,93 @@
-use image::GenericImageView;
+use std::fmt::Display;
+
+use image::{GenericImageView, imageops::FilterType};
+
+use crate::config::TextureConfig;
+
+#[derive(Debug)]
+pub enum TextureError {
+    NoImages,
+    DimensionMismatch {
+        layer: usize,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    TooManyLayers {
+        layers: usize,
+        max: u32,
+    },
+    TooLarge {
+        dimensions: (u32, u32),
+        max: u32,
+    },
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::NoImages => write!(f, "a texture array needs at least one image"),
+            TextureError::DimensionMismatch {
+                layer,
+                expected,
+                found,
+            } => write!(
+                f,
+                "layer {} is {}x{} but the array is {}x{}",
+                layer, found.0, found.1, expected.0, expected.1
+            ),
+            TextureError::TooManyLayers { layers, max } => write!(
+                f,
+                "{} layers are more than the {} the device supports",
+                layers, max
+            ),
+            TextureError::TooLarge { dimensions, max } => write!(
+                f,
+                "the layers are {}x{} but the device supports at most {}x{}",
+                dimensions.0, dimensions.1, max, max
+            ),
+        }
+    }
+}
+
+/// Checks that all images share the same size and returns it.
+pub fn layer_dimensions(imgs: &[image::DynamicImage]) -> Result<(u32, u32), TextureError> {
+    let Some(first) = imgs.first() else {
+        return Err(TextureError::NoImages);
+    };
+    let dimensions = first.dimensions();
+
+    for (layer, img) in imgs.iter().enumerate() {
+        if img.dimensions() != dimensions {
+            return Err(TextureError::DimensionMismatch {
+                layer,
+                expected: dimensions,
+                found: img.dimensions(),
+            });
+        }
+    }
+    Ok(dimensions)
+}
+
+/// Like `layer_dimensions`, but also checks that the device can create the array.
+pub fn array_dimensions(
+    imgs: &[image::DynamicImage],
+    limits: &wgpu::Limits,
+) -> Result<(u32, u32), TextureError> {
+    let dimensions = layer_dimensions(imgs)?;
+    if imgs.len() > limits.max_texture_array_layers as usize {
+        return Err(TextureError::TooManyLayers {
+            layers: imgs.len(),
+            max: limits.max_texture_array_layers,
+        });
+    }
+    if dimensions.0.max(dimensions.1) > limits.max_texture_dimension_2d {
+        return Err(TextureError::TooLarge {
+            dimensions,
+            max: limits.max_texture_dimension_2d,
+        });
+    }
+    Ok(dimensions)
+}
 
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -10,14 +99,15 @@ impl Texture {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         imgs: &[image::DynamicImage],
+        config: &TextureConfig,
         label: Option<&str>,
-    ) -> Self {
-        assert!(!imgs.is_empty());
-        let dimensions = imgs[0].dimensions();
-
-        for img in imgs.iter() {
-            assert!(dimensions == img.dimensions())
-        }
+    ) -> Result<Self, TextureError> {
+        let dimensions = array_dimensions(imgs, &device.limits())?;
+        let mip_level_count = if config.mipmaps {
+            u32::BITS - dimensions.0.max(dimensions.1).leading_zeros()
+        } else {
+            1
+        };
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
@@ -27,7 +117,7 @@ impl Texture {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8Unorm,
@@ -36,30 +126,41 @@ impl Texture {
         });
 
         for (i, img) in imgs.iter().enumerate() {
-            let rgba = img.to_rgba8();
-            queue.write_texture(
-                wgpu::TexelCopyTextureInfo {
-                    aspect: wgpu::TextureAspect::All,
-                    texture: &texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d {
-                        x: 0,
-                        y: 0,
-                        z: i as u32,
+            let mut rgba = img.to_rgba8();
+            for mip_level in 0..mip_level_count {
+                if mip_level > 0 {
+                    // every level is downsampled from the previous one, which averages 2x2 texels
+                    rgba = image::imageops::resize(
+                        &rgba,
+                        (rgba.width() / 2).max(1),
+                        (rgba.height() / 2).max(1),
+                        FilterType::Triangle,
+                    );
+                }
+                queue.write_texture(
+                    wgpu::TexelCopyTextureInfo {
+                        aspect: wgpu::TextureAspect::All,
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: i as u32,
+                        },
                     },
-                },
-                &rgba,
-                wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * dimensions.0),
-                    rows_per_image: Some(dimensions.1),
-                },
-                wgpu::Extent3d {
-                    width: dimensions.0,
-                    height: dimensions.1,
-                    depth_or_array_layers: 1, // 👈 nur 1 Layer pro call
-                },
-            );
+                    &rgba,
+                    wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * rgba.width()),
+                        rows_per_image: Some(rgba.height()),
+                    },
+                    wgpu::Extent3d {
+                        width: rgba.width(),
+                        height: rgba.height(),
+                        depth_or_array_layers: 1, // 👈 nur 1 Layer pro call
+                    },
+                );
+            }
         }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
@@ -72,19 +173,27 @@ impl Texture {
             address_mode_u: wgpu::AddressMode::Repeat,
             address_mode_v: wgpu::AddressMode::Repeat,
             address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Nearest,
+            // anisotropic filtering is only allowed if every filter is linear
+            mag_filter: if config.anisotropy > 1 {
+                wgpu::FilterMode::Linear
+            } else {
+                wgpu::FilterMode::Nearest
+            },
             min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy_clamp: config.anisotropy,
             ..Default::default()
         });
 
-        Self {
+        Ok(Self {
             texture,
             view,
             sampler,
-        }
+        })
     }
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    /// The format of the scene until the tone mapping, it keeps colors brighter than white.
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
 
     pub fn create_depth_texture(
         device: &wgpu::Device,
@@ -141,7 +250,7 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: config.format, // Verwende das gleiche Format wie das Ausgabefenster
+            format: Self::HDR_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
         };
@@ -152,7 +261,8 @@ impl Texture {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
+            // the last post-processing pass scales it up with the dynamic resolution
+            mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
@@ -1,3 +1,6 @@
+/// The block textures that are compiled into the binary.
+/// They form the fallback resource pack which is used when no pack is configured
+/// or the configured one couldn't be loaded.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Texture {
     CrackedStone,
@@ -9,6 +12,19 @@ pub enum Texture {
 use Texture::*;
 
 impl Texture {
+    /// All embedded textures in the order of their array layers.
+    pub const ALL: [Texture; 5] = [CrackedStone, Stone, Dirt0, Dirt1, Debug];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CrackedStone => "cracked-stone",
+            Stone => "stone",
+            Dirt0 => "dirt-0",
+            Dirt1 => "dirt-1",
+            Debug => "debug",
+        }
+    }
+
     pub fn bytes(self) -> &'static [u8] {
         match self {
             CrackedStone => include_bytes!("normal_stone-8x8.png"),
@@ -0,0 +1,136 @@
+//! GPU timings of the main pass and the post-processing. Their passes write timestamps at their
+//! beginning and end, which are resolved into a buffer and read back a few frames late, the same
+//! way as the culling stats of `indirect`. Without `FEATURES` only the CPU timings are reported.
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::gpu::indirect::Readback;
+
+/// The adapter features the timings need.
+pub const FEATURES: wgpu::Features = wgpu::Features::TIMESTAMP_QUERY;
+
+/// The measured parts of a frame, with two timestamps each.
+#[derive(Clone, Copy)]
+pub enum GpuPass {
+    Main = 0,
+    /// The stack itself, not the bloom, SSAO and auto exposure built before it.
+    PostProcessing = 1,
+}
+
+const QUERY_COUNT: u32 = 4;
+
+/// In milliseconds.
+pub struct GpuTimings {
+    pub main_pass: f64,
+    pub post_processing: f64,
+}
+
+pub struct Timestamps {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    readback: Readback,
+    /// Nanoseconds per tick.
+    period: f64,
+}
+
+impl Timestamps {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = u64::from(QUERY_COUNT) * wgpu::QUERY_SIZE as u64;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pass Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: QUERY_COUNT,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Resolve Buffer"),
+                size,
+                mapped_at_creation: false,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Readback Buffer"),
+                size,
+                mapped_at_creation: false,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            }),
+            readback: Readback::Idle,
+            period: f64::from(queue.get_timestamp_period()),
+        }
+    }
+
+    /// The timestamps a render pass of `pass` writes. A part made of several passes writes the
+    /// beginning in its first one and the end in its last one.
+    pub fn writes(
+        &self,
+        pass: GpuPass,
+        first: bool,
+        last: bool,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let beginning = pass as u32 * 2;
+        (first || last).then(|| wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: first.then_some(beginning),
+            end_of_pass_write_index: last.then_some(beginning + 1),
+        })
+    }
+
+    /// Copies the timestamps of this frame for the readback, unless an earlier one is still
+    /// underway. Has to be recorded after all measured passes.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Readback::Idle = self.readback {
+            encoder.resolve_query_set(&self.query_set, 0..QUERY_COUNT, &self.resolve_buffer, 0);
+            encoder.copy_buffer_to_buffer(
+                &self.resolve_buffer,
+                0,
+                &self.readback_buffer,
+                0,
+                self.resolve_buffer.size(),
+            );
+            self.readback = Readback::Copied;
+        }
+    }
+
+    /// Maps the timestamps resolved this frame, has to be called after the submit.
+    pub fn after_submit(&mut self) {
+        if let Readback::Copied = self.readback {
+            let mapped = Arc::new(AtomicBool::new(false));
+            let done = mapped.clone();
+            self.readback_buffer.slice(..).map_async(
+                wgpu::MapMode::Read,
+                move |result| match result {
+                    Ok(()) => done.store(true, Ordering::Release),
+                    Err(err) => log::warn!("can't read the timestamps: {}", err),
+                },
+            );
+            self.readback = Readback::Mapping(mapped);
+        }
+    }
+
+    /// The timings of an earlier frame, if they arrived since the last call.
+    pub fn timings(&mut self, device: &wgpu::Device) -> Option<GpuTimings> {
+        let Readback::Mapping(mapped) = &self.readback else {
+            return None;
+        };
+        let _ = device.poll(wgpu::PollType::Poll);
+        if !mapped.load(Ordering::Acquire) {
+            return None;
+        }
+        let ticks: [u64; QUERY_COUNT as usize] =
+            *bytemuck::from_bytes(&self.readback_buffer.slice(..).get_mapped_range());
+        self.readback_buffer.unmap();
+        self.readback = Readback::Idle;
+
+        let ms = |pass: GpuPass| {
+            let beginning = pass as usize * 2;
+            ticks[beginning + 1].saturating_sub(ticks[beginning]) as f64 * self.period / 1e6
+        };
+        Some(GpuTimings {
+            main_pass: ms(GpuPass::Main),
+            post_processing: ms(GpuPass::PostProcessing),
+        })
+    }
+}
@@ -0,0 +1,76 @@
+//! Draws the debug UI of debug_ui.rs over the finished frame, with the wgpu renderer of egui.
+
+/// The tessellated output of one run of the UI.
+pub struct UiFrame {
+    pub primitives: Vec<egui::ClippedPrimitive>,
+    pub textures_delta: egui::TexturesDelta,
+    pub pixels_per_point: f32,
+}
+
+pub struct UiOverlay {
+    renderer: egui_wgpu::Renderer,
+    /// Taken by the next draw, nothing is drawn without a new frame.
+    frame: Option<UiFrame>,
+}
+
+impl UiOverlay {
+    pub fn new(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> Self {
+        Self {
+            renderer: egui_wgpu::Renderer::new(device, surface_format, None, 1, false),
+            frame: None,
+        }
+    }
+
+    pub fn set_frame(&mut self, mut frame: UiFrame) {
+        // the texture changes of a frame which was never drawn are still needed
+        if let Some(mut skipped) = self.frame.take() {
+            skipped.textures_delta.append(frame.textures_delta);
+            frame.textures_delta = skipped.textures_delta;
+        }
+        self.frame = Some(frame);
+    }
+
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        size_in_pixels: [u32; 2],
+    ) {
+        let Some(frame) = self.frame.take() else {
+            return;
+        };
+        for (id, delta) in &frame.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels,
+            pixels_per_point: frame.pixels_per_point,
+        };
+        // only paint callbacks record command buffers of their own, the UI has none
+        self.renderer
+            .update_buffers(device, queue, encoder, &frame.primitives, &screen);
+
+        let ui_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass - UI"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        self.renderer
+            .render(&mut ui_pass.forget_lifetime(), &frame.primitives, &screen);
+
+        for id in &frame.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
@@ -61,11 +61,25 @@ impl<'a> Window<'a> {
         self.size = new_size;
     }
 
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Wrapper für winit::window::Window::scale_factor().
+    pub fn scale_factor(&self) -> f64 {
+        self.window.scale_factor()
+    }
+
     /// Wrapper für winit::window::Window::id().
     pub fn id(&self) -> WindowId {
         self.window.id()
     }
 
+    /// Wrapper für winit::window::Window::set_title().
+    pub fn set_title(&self, title: &str) {
+        self.window.set_title(title)
+    }
+
     /// Wrapper für winit::window::Window::request_redraw().
     pub fn request_redraw(&self) {
         self.window.request_redraw()
@@ -1,9 +1,7 @@
-use std::{
-    ops::Range,
-    time::{Duration, Instant},
-};
+use std::{ops::Range, time::Duration};
 
 use glam::Vec3;
+use web_time::Instant;
 use winit::{
     dpi::PhysicalPosition,
     event::{DeviceEvent, Event, KeyEvent, MouseScrollDelta, WindowEvent},
@@ -165,6 +163,12 @@ pub struct Inputs {
     pub free_cam: bool,
     pub status: bool,
     pub toggle_impl: bool,
+    pub toggle_ambient_occlusion: bool,
+    pub cycle_debug_view: bool,
+    pub toggle_chunk_bounds: bool,
+    pub detach_frustum: bool,
+    pub toggle_hud: bool,
+    pub toggle_debug_ui: bool,
 
     pub space: InputState,
     pub last_space_press: Option<Instant>,
@@ -192,6 +196,12 @@ impl Inputs {
             free_cam: false,
             status: false,
             toggle_impl: false,
+            toggle_ambient_occlusion: false,
+            cycle_debug_view: false,
+            toggle_chunk_bounds: false,
+            detach_frustum: false,
+            toggle_hud: false,
+            toggle_debug_ui: false,
 
             space: InputState {
                 state: FrameState::NotPressed,
@@ -218,7 +228,10 @@ pub struct InputEventFilter {
 impl InputEventFilter {
     pub fn new() -> InputResult<Self> {
         Ok(InputEventFilter {
+            #[cfg(not(target_arch = "wasm32"))]
             key_map: KeyMap::from_file("keymap.json")?,
+            #[cfg(target_arch = "wasm32")]
+            key_map: KeyMap::from_json(include_str!("../../keymap.json"))?,
             inputs: Inputs::new(),
         })
     }
@@ -334,6 +347,30 @@ impl InputEventFilter {
                             self.inputs.toggle_impl = true;
                             return true;
                         }
+                        KeyCode::KeyO if is_pressed => {
+                            self.inputs.toggle_ambient_occlusion = true;
+                            return true;
+                        }
+                        KeyCode::KeyV if is_pressed => {
+                            self.inputs.cycle_debug_view = true;
+                            return true;
+                        }
+                        KeyCode::KeyG if is_pressed => {
+                            self.inputs.toggle_chunk_bounds = true;
+                            return true;
+                        }
+                        KeyCode::KeyF if is_pressed => {
+                            self.inputs.detach_frustum = true;
+                            return true;
+                        }
+                        KeyCode::F3 if is_pressed => {
+                            self.inputs.toggle_hud = true;
+                            return true;
+                        }
+                        KeyCode::F1 if is_pressed => {
+                            self.inputs.toggle_debug_ui = true;
+                            return true;
+                        }
 
                         KeyCode::Space if !is_pressed => {
                             self.inputs.space.release();
@@ -384,6 +421,12 @@ impl InputEventFilter {
         self.inputs.free_cam = false;
         self.inputs.status = false;
         self.inputs.toggle_impl = false;
+        self.inputs.toggle_ambient_occlusion = false;
+        self.inputs.cycle_debug_view = false;
+        self.inputs.toggle_chunk_bounds = false;
+        self.inputs.detach_frustum = false;
+        self.inputs.toggle_hud = false;
+        self.inputs.toggle_debug_ui = false;
 
         self.inputs.space.frame_done();
     }
@@ -1,3 +1,4 @@
+#[cfg(not(target_arch = "wasm32"))]
 use std::{fs::File, io::Read, path::Path};
 
 use serde::Deserialize;
@@ -33,11 +34,16 @@ struct ReMaps {
 }
 
 impl KeyMap {
+    #[cfg(not(target_arch = "wasm32"))]
     pub(super) fn from_file(path: impl AsRef<Path>) -> InputResult<KeyMap> {
         let mut settings = File::open(path)?;
         let mut json_settings = String::new();
         settings.read_to_string(&mut json_settings)?;
-        let re_maps: ReMaps = serde_json::from_str(&json_settings)?;
+        Self::from_json(&json_settings)
+    }
+
+    pub(super) fn from_json(json_settings: &str) -> InputResult<KeyMap> {
+        let re_maps: ReMaps = serde_json::from_str(json_settings)?;
 
         Ok(KeyMap {
             forward: str_to_keycode(re_maps.forward.as_deref().unwrap_or("w"))?,
@@ -1,8 +1,11 @@
+use std::time::Duration;
+
 use glam::Vec3;
 use winit::{dpi::PhysicalSize, event::Event, event_loop::EventLoopWindowTarget};
 
 use crate::{
     config::{Config, LiveConfig},
+    debug_ui::DebugUi,
     event_loop::make_window,
     gpu::{projection::View, window::Window},
     input::InputEventFilter,
@@ -13,6 +16,7 @@ use voxine::{
 };
 
 mod config;
+mod debug_ui;
 #[allow(dead_code)]
 mod error;
 mod event_loop;
@@ -27,7 +31,21 @@ const GRAVITY: f32 = 9.81;
 const WALK_JUMP_SPEED: f32 = 5000.;
 
 fn main() {
-    env_logger::init(); // this logs error messages
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        env_logger::init(); // this logs error messages
+
+        if std::env::args().any(|arg| arg == "--list-adapters") {
+            gpu::list_adapters();
+            return;
+        }
+    }
+    // the browser console
+    #[cfg(target_arch = "wasm32")]
+    {
+        console_error_panic_hook::set_once();
+        console_log::init_with_level(log::Level::Info).expect("logger");
+    }
 
     make_window::<EventHandler>();
 }
@@ -42,14 +60,17 @@ struct EventHandler<'a> {
     config_updates: rtrb::Consumer<LiveConfig>,
 
     input_event_filter: InputEventFilter,
+    debug_ui: DebugUi,
     frames_drawn: usize,
     change_mesh: bool,
-    toggle_impl: bool,
     paused: bool,
+    /// The position and direction the chunks are selected from, while detached from the camera.
+    detached_frustum: Option<(Vec3, Vec3)>,
 }
 
 impl event_loop::EventHandler<'static> for EventHandler<'static> {
-    fn new(window: &'static winit::window::Window) -> Self {
+    async fn new(window: &'static winit::window::Window) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
         let (config, config_updates) = voxine::config_loader::config_thread::<
             config::ConfigFile,
             config::Config,
@@ -57,6 +78,8 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
             config::Error,
         >("config.toml".into())
         .expect("config");
+        #[cfg(target_arch = "wasm32")]
+        let (config, config_updates) = Config::embedded();
 
         let delta_time = DeltaTimeMeter::new();
 
@@ -101,17 +124,14 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
                     ),
             )
             .unwrap(),
-            gpu: pollster::block_on(gpu::Gpu::connect_to(
-                &window,
-                wgpu::PresentMode::AutoVsync,
-                &config,
-            )),
+            gpu: gpu::Gpu::connect_to(&window, &config).await,
 
             input_event_filter: input::InputEventFilter::new().expect("input event filter"),
+            debug_ui: DebugUi::new(),
             frames_drawn: 0,
             change_mesh: true,
-            toggle_impl: true,
             paused: false,
+            detached_frustum: None,
             delta_time,
             config,
             config_updates,
@@ -124,6 +144,11 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
         own_window_id: winit::window::WindowId,
         keyboard_focus: bool,
     ) -> bool {
+        if self.debug_ui.could_handle(event, own_window_id) {
+            return true;
+        }
+        // the cursor is released while the UI is open, the keys still move the camera
+        let keyboard_focus = keyboard_focus || (self.debug_ui.is_open() && !self.paused);
         self.input_event_filter
             .could_handle(event, own_window_id, keyboard_focus)
     }
@@ -135,8 +160,26 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
     ) {
         self.delta_time.update();
 
-        let camera_config = if let Ok(config_update) = self.config_updates.pop() {
+        let mut config_update = self.config_updates.pop().ok();
+        if self.debug_ui.is_open() {
+            let (frame, edited) = self.debug_ui.run(
+                window,
+                &self.config,
+                self.change_mesh,
+                &mut self.input_event_filter.inputs,
+            );
+            self.gpu.set_ui(frame);
+            config_update = edited.or(config_update);
+        }
+
+        let camera_config = if let Some(config_update) = config_update {
             self.config.update(config_update.clone());
+            self.gpu
+                .set_lighting(self.config.lighting.clone(), self.config.day_cycle.clone());
+            self.gpu.set_post_processing(&self.config.post_processing);
+            self.gpu
+                .set_dynamic_resolution(&self.config.dynamic_resolution);
+            self.gpu.set_display(&self.config.display);
             let camera = config_update.camera.clone();
             self.engine_channel
                 .updates
@@ -149,17 +192,52 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
             None
         };
 
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(reload) = self.gpu.reload_shaders() {
+            match reload {
+                Ok(()) => {
+                    log::info!("shaders reloaded");
+                    window.set_title(event_loop::TITLE);
+                    self.gpu.set_shader_error(None);
+                }
+                Err(err) => {
+                    log::error!("shader error, keeping the last working shaders: {}", err);
+                    window.set_title(&format!("{} - shader error: {}", event_loop::TITLE, err));
+                    self.gpu.set_shader_error(Some(&err));
+                }
+            }
+        }
+
         let inputs = self.input_event_filter.get();
 
         if inputs.pause {
             self.paused = !self.paused;
-            window.set_focus(!self.paused);
+        }
+        if inputs.toggle_debug_ui {
+            self.debug_ui.toggle();
+        }
+        // the cursor is only captured while playing
+        let captured = !self.paused && !self.debug_ui.is_open();
+        if window.focused() != captured {
+            window.set_focus(captured);
         }
         if inputs.remesh {
             self.change_mesh = !self.change_mesh;
         }
         if inputs.toggle_impl {
-            self.toggle_impl = !self.toggle_impl;
+            self.gpu.toggle_indirect_draws();
+        }
+        if inputs.toggle_ambient_occlusion {
+            self.gpu.toggle_ambient_occlusion();
+        }
+        if inputs.cycle_debug_view {
+            self.gpu.cycle_debug_view();
+        }
+        if inputs.toggle_chunk_bounds {
+            self.gpu.toggle_chunk_bounds();
+        }
+        if inputs.toggle_hud {
+            self.gpu.toggle_hud();
         }
 
         let frustum = {
@@ -167,8 +245,15 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
             if let Some(camera_config) = camera_config {
                 camera.update_config(camera_config)
             }
+            if inputs.detach_frustum {
+                self.detached_frustum = match self.detached_frustum {
+                    Some(_) => None,
+                    None => Some((camera.pos(), camera.dir())),
+                };
+                self.gpu.show_frustum(self.detached_frustum.is_some());
+            }
 
-            if !self.paused && window.focused() {
+            if !self.paused && (window.focused() || self.debug_ui.is_open()) {
                 let prev_cam_pos = camera.pos();
 
                 if inputs.free_cam {
@@ -212,7 +297,7 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
                     } else {
                         0.0
                     };
-                    println!(
+                    log::info!(
                         "FPS: {}\tpos: [{}]\tvel: {:+12.5} kmh",
                         fps,
                         camera
@@ -236,9 +321,12 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
                     },
                 ));
             }
+            let (frustum_pos, frustum_dir) = self
+                .detached_frustum
+                .unwrap_or((camera.pos(), camera.dir()));
             Frustum {
-                cam_pos: camera.pos() / 32.,
-                direction: camera.dir(),
+                cam_pos: frustum_pos / 32.,
+                direction: frustum_dir,
                 fov: self.config.fov,
                 aspect_ratio: window.aspect_ratio,
                 max_chunks: self.config.max_chunks,
@@ -260,6 +348,18 @@ impl event_loop::EventHandler<'static> for EventHandler<'static> {
         self.frames_drawn += 1;
     }
 
+    fn frame_interval(&self) -> Option<Duration> {
+        let display = &self.config.display;
+        let fps = if self.paused {
+            Some(display.max_fps.map_or(display.background_fps, |fps| {
+                fps.min(display.background_fps)
+            }))
+        } else {
+            display.max_fps
+        };
+        fps.map(|fps| Duration::from_secs_f64(1. / fps))
+    }
+
     fn reconfigure(&mut self) {
         self.gpu.reconfigure();
     }